@@ -7,6 +7,11 @@ use sus_common::{
 #[derive(Component)]
 pub struct MyPlayer;
 
+// Marker component for remote players the server didn't include in the last
+// tick because we can't see them.
+#[derive(Component)]
+pub struct OutOfSight;
+
 #[derive(Debug, Bundle)]
 pub struct ClientPlayerBundle {
     pub id: PlayerId,
@@ -1,45 +1,64 @@
 use crate::{
-    components::{ClientPlayerBundle, MyPlayer},
+    components::{ClientPlayerBundle, MyPlayer, OutOfSight},
     events::OutgoingPacket,
-    resources::{InputCounter, MyName},
-    systems::{sets, ClientNetworkPlugin, RenderPlugin},
+    network_stats::NetworkStats,
+    prediction::PredictionSmoothing,
+    resources::{ChatInput, InputCounter, MyName, MyRoom, MyState, MyVent},
+    systems::{sets, ChatPlugin, ClientNetworkPlugin, GamePlugin, RenderPlugin},
 };
 use std::{
     collections::{HashMap, VecDeque},
     net::SocketAddr,
+    time::Duration,
 };
 use sus_common::{
     components::player::{MyPlayerId, PlayerId, PlayerName, UnprocessedInputs},
-    math::NormalizedInt,
+    discovery::{discover_servers, DISCOVERY_PORT},
+    logging::{init_logging, LogFormat},
+    map::Map,
+    master_server::{list_servers, ServerQuery},
+    movement::{move_ghost, move_player},
     network::{
         ClientToServer, ConnectAckPacket, DeliveryType, FullGameStatePacket, LobbyTickPacket,
-        NewPlayerPacket,
+        NewPlayerPacket, PlayerLeftPacket, RoomRequest, SpectatorTickPacket, MAX_ROOM_CODE_LEN,
     },
-    resources::PlayerToEntity,
+    protocol::SUPPORTED_PROTOCOL,
+    resources::{ClosedDoors, PlayerToEntity},
+    settings::GameSettings,
     simple_game::{
         bevy::{
-            bevy_ecs, App, BevyGame, Commands, CoreSchedule, EventReader, EventWriter, FixedTime,
-            IntoSystemAppConfigs, IntoSystemConfigs, IntoSystemSetConfig, Query, Res, ResMut,
-            Resource, SimpleGamePlugin, Transform, With,
+            bevy_ecs, App, BevyGame, Commands, CoreSchedule, Entity, EventReader, EventWriter,
+            FixedTime, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfig,
+            IntoSystemConfigs, IntoSystemSetConfig, Query, Res, ResMut, Resource, SimpleGamePlugin,
+            Time, Transform, With,
         },
-        glam::{vec3, Vec3},
+        glam::{vec2, vec3, Vec2, Vec3},
         winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
         WindowDimensions,
     },
-    PlayerInput,
+    tracing::{debug, error, info, trace, warn},
+    PlayerInput, PlayerState,
 };
 
 mod components;
 mod events;
+mod network_stats;
+mod prediction;
 mod resources;
 mod systems;
 
 const SERVER_ADDR: &str = "127.0.0.1:7600";
+const SERVER_SEARCH_TIMEOUT: Duration = Duration::from_millis(500);
 
 #[derive(Debug, Resource)]
 struct SusGame {
     server_addr: SocketAddr,
     connected: bool,
+    // Watching the room rather than playing in it.
+    spectating: bool,
+    // Why the server dropped us, once it has, to show in place of the
+    // connection status.
+    disconnected: Option<String>,
 }
 
 impl BevyGame for SusGame {
@@ -58,17 +77,49 @@ impl BevyGame for SusGame {
     fn init_systems() -> App {
         let mut ecs_world_builder = App::new();
 
-        let game = SusGame { server_addr: SERVER_ADDR.parse().unwrap(), connected: false };
         let my_name = "Brian".to_string();
 
+        // Join a friend's room by setting SUS_ROOM to its code, otherwise start a new one.
+        // Anything that can't be part of a code is dropped, so what we send stays
+        // within the protocol's limits.
+        let my_room = match std::env::var("SUS_ROOM") {
+            Ok(code) => RoomRequest::Join(
+                code.chars()
+                    .filter(char::is_ascii_alphanumeric)
+                    .take(MAX_ROOM_CODE_LEN)
+                    .collect::<String>()
+                    .to_uppercase(),
+            ),
+            Err(_) => RoomRequest::Create,
+        };
+
+        // Set SUS_SPECTATE to watch the room in SUS_ROOM instead of playing in it.
+        // There's nothing to watch in a room we'd be creating, so play instead.
+        let mut spectating = std::env::var_os("SUS_SPECTATE").is_some();
+
+        if spectating && my_room == RoomRequest::Create {
+            error!("SUS_SPECTATE needs SUS_ROOM set to the room to watch, playing instead");
+            spectating = false;
+        }
+
+        let game = SusGame {
+            server_addr: find_server(&my_room),
+            connected: false,
+            spectating,
+            disconnected: None,
+        };
+
         ecs_world_builder
             .add_plugin(SimpleGamePlugin)
             .insert_resource(FixedTime::new_from_secs(1.0 / Self::desired_fps() as f32))
             .insert_resource(game)
             .insert_resource(MyName(my_name))
+            .insert_resource(MyRoom(my_room))
             .add_startup_system(init)
             .add_plugin(ClientNetworkPlugin)
             .add_plugin(RenderPlugin)
+            .add_plugin(GamePlugin)
+            .add_plugin(ChatPlugin)
             .configure_set(sets::MainLogic.after(sets::NetworkSystem::Receive))
             .add_system(handle_input)
             .add_systems(
@@ -77,13 +128,21 @@ impl BevyGame for SusGame {
                     handle_connect_ack,
                     handle_full_game_state,
                     new_player_joined,
+                    player_left,
                     handle_lobby_tick,
+                    handle_spectator_tick,
                     update_game,
                 )
                     .after(handle_input)
                     .after(sets::NetworkSystem::Receive)
                     .in_set(sets::MainLogic)
                     .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                blend_prediction_error
+                    .after(handle_lobby_tick)
+                    .in_set(sets::MainLogic)
+                    .in_schedule(CoreSchedule::FixedUpdate),
             );
 
         ecs_world_builder
@@ -96,13 +155,25 @@ fn init(mut commands: Commands) {
     commands.insert_resource(MyPlayerId(None));
     commands.insert_resource(PlayerToEntity(HashMap::new()));
     commands.insert_resource(UnprocessedInputs(VecDeque::new()));
+    commands.insert_resource(NetworkStats::default());
+    commands.insert_resource(PredictionSmoothing::default());
 }
 
 fn handle_input(
     mut keyboard_input_events: EventReader<KeyboardInput>,
+    chat_input: Res<ChatInput>,
     mut player_input: ResMut<PlayerInput>,
 ) {
+    // Stop walking while typing in the chat box.
+    if chat_input.0.is_some() {
+        *player_input = PlayerInput::default();
+    }
+
     for event in keyboard_input_events.iter() {
+        if chat_input.0.is_some() {
+            continue;
+        }
+
         if let KeyboardInput { virtual_keycode: Some(key_code), state, .. } = event {
             let pressed = *state == ElementState::Pressed;
 
@@ -118,13 +189,22 @@ fn handle_input(
 }
 
 fn send_input_to_server(
+    game: Res<SusGame>,
+    time: Res<Time>,
     player_input: Res<PlayerInput>,
     mut input_counter: ResMut<InputCounter>,
     mut unprocessed_inputs: ResMut<UnprocessedInputs>,
+    mut network_stats: ResMut<NetworkStats>,
     mut outgoing_packets: EventWriter<OutgoingPacket>,
 ) {
+    // There's nobody for a spectator to move.
+    if game.spectating {
+        return;
+    }
+
     let input_packet = player_input.to_player_input_packet(input_counter.0);
     unprocessed_inputs.0.push_back(input_packet);
+    network_stats.input_sent(input_counter.0, time.elapsed());
 
     input_counter.0 = input_counter.0.wrapping_add(1);
 
@@ -145,7 +225,7 @@ fn handle_connect_ack(
     mut my_player_id: ResMut<MyPlayerId>,
 ) {
     for connect_ack in connect_ack_rx.iter() {
-        println!("Got a connect ack");
+        debug!(player = connect_ack.id, "Spawning our player");
 
         let entity_id = commands
             .spawn((
@@ -182,6 +262,18 @@ fn new_player_joined(
     }
 }
 
+fn player_left(
+    mut commands: Commands,
+    mut player_left_rx: EventReader<PlayerLeftPacket>,
+    mut player_to_entity: ResMut<PlayerToEntity>,
+) {
+    for player_left in player_left_rx.iter() {
+        if let Some(entity) = player_to_entity.0.remove(&player_left.id) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
 fn handle_full_game_state(
     mut commands: Commands,
     mut full_game_state_rx: EventReader<FullGameStatePacket>,
@@ -202,22 +294,50 @@ fn handle_full_game_state(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_lobby_tick(
+    mut commands: Commands,
+    time: Res<Time>,
     player_to_entity: Res<PlayerToEntity>,
     mut lobby_tick_rx: EventReader<LobbyTickPacket>,
     mut unprocessed_inputs: ResMut<UnprocessedInputs>,
+    mut network_stats: ResMut<NetworkStats>,
+    mut smoothing: ResMut<PredictionSmoothing>,
     my_player_id: Res<MyPlayerId>,
-    mut players: Query<(&PlayerId, &mut Transform)>,
+    my_vent: Res<MyVent>,
+    my_state: Res<MyState>,
+    settings: Res<GameSettings>,
+    map: Res<Map>,
+    closed_doors: Res<ClosedDoors>,
+    mut players: Query<(Entity, &PlayerId, &mut Transform)>,
 ) {
     for lobby_tick in lobby_tick_rx.iter() {
         unprocessed_inputs.clear_acknowledged_inputs(lobby_tick.last_input_counter);
+        network_stats.input_acked(
+            lobby_tick.last_input_counter,
+            unprocessed_inputs.0.len(),
+            time.elapsed(),
+        );
+
+        // Where we thought we were, to see how far off the server says it was.
+        let mut predicted = None;
+
+        // The server leaves out players we can't see, so hide anyone missing from this tick.
+        for (entity, player_id, _) in players.iter() {
+            if lobby_tick.players.iter().any(|player| player.id == player_id.0) {
+                commands.entity(entity).remove::<OutOfSight>();
+            } else {
+                commands.entity(entity).insert(OutOfSight);
+            }
+        }
 
         for player in &lobby_tick.players {
             if let Some(player_entity) = player_to_entity.0.get(&player.id) {
-                if let Ok((_, mut transform)) = players.get_mut(*player_entity) {
+                if let Ok((_, _, mut transform)) = players.get_mut(*player_entity) {
                     if let Some(my_player_id) = my_player_id.0 {
                         if my_player_id == player.id {
                             // Update my player
+                            predicted = Some(transform.translation);
                             transform.translation = vec3(player.pos.0, player.pos.1, 0.0);
                         } else {
                             // TODO - Handle updating the other players using the position history.
@@ -231,13 +351,36 @@ fn handle_lobby_tick(
             }
         }
 
-        // Apply all unacknowledged inputs
+        // Apply all unacknowledged inputs, unless we're stuck in a vent.
+        if my_vent.0.is_some() {
+            smoothing.snap();
+            continue;
+        }
+
         if let Some(my_player_id) = my_player_id.0 {
             if let Some(my_player_entity) = player_to_entity.0.get(&my_player_id) {
-                if let Ok((_, mut transform)) = players.get_mut(*my_player_entity) {
+                if let Ok((_, _, mut transform)) = players.get_mut(*my_player_entity) {
+                    let mut pos = vec2(transform.translation.x, transform.translation.y);
+
                     for input in &unprocessed_inputs.0 {
-                        let velocity = vec3(input.x.normalized(), input.y.normalized(), 0.0);
-                        transform.translation += velocity * 0.1;
+                        pos = predict_move(
+                            &map,
+                            &closed_doors,
+                            &settings,
+                            my_state.0,
+                            pos,
+                            input.x,
+                            input.y,
+                        );
+                    }
+
+                    transform.translation = vec3(pos.x, pos.y, 0.0);
+
+                    // The simulation carries on from the corrected position
+                    // straight away, while what's drawn catches up to it.
+                    if let Some(predicted) = predicted {
+                        let snapped = smoothing.correct(predicted.truncate(), pos);
+                        network_stats.reconciled(predicted.truncate().distance(pos), snapped);
                     }
                 }
             }
@@ -245,17 +388,156 @@ fn handle_lobby_tick(
     }
 }
 
+// Spectators aren't told about players joining or leaving, each tick lists
+// everyone there is to see.
+fn handle_spectator_tick(
+    mut commands: Commands,
+    mut spectator_tick_rx: EventReader<SpectatorTickPacket>,
+    mut player_to_entity: ResMut<PlayerToEntity>,
+    mut players: Query<&mut Transform>,
+) {
+    for spectator_tick in spectator_tick_rx.iter() {
+        player_to_entity.0.retain(|id, entity| {
+            let listed = spectator_tick.players.iter().any(|player| player.id == *id);
+
+            if !listed {
+                commands.entity(*entity).despawn();
+            }
+
+            listed
+        });
+
+        for player in &spectator_tick.players {
+            let translation = vec3(player.pos.0, player.pos.1, 0.0);
+
+            match player_to_entity.0.get(&player.id) {
+                Some(entity) => {
+                    if let Ok(mut transform) = players.get_mut(*entity) {
+                        transform.translation = translation;
+                    }
+                },
+                None => {
+                    let entity = commands
+                        .spawn(ClientPlayerBundle {
+                            id: PlayerId(player.id),
+                            name: PlayerName(String::new()),
+                            transform: Transform::from_translation(translation),
+                        })
+                        .id();
+
+                    player_to_entity.0.insert(player.id, entity);
+                },
+            }
+        }
+    }
+}
+
+// Steps along with the corrections, so each one starts to fade from the step
+// that made it, however many fixed steps a frame runs.
+fn blend_prediction_error(fixed_time: Res<FixedTime>, mut smoothing: ResMut<PredictionSmoothing>) {
+    smoothing.blend(fixed_time.period);
+}
+
 fn update_game(
     player_input: Res<PlayerInput>,
+    my_vent: Res<MyVent>,
+    my_state: Res<MyState>,
+    settings: Res<GameSettings>,
+    map: Res<Map>,
+    closed_doors: Res<ClosedDoors>,
     mut players: Query<(&PlayerId, &mut Transform), With<MyPlayer>>,
 ) {
+    if my_vent.0.is_some() {
+        return;
+    }
+
     if let Ok((_my_player_id, mut transform)) = players.get_single_mut() {
-        let velocity = vec3(player_input.x().normalized(), player_input.y().normalized(), 0.0);
-        transform.translation += velocity * 0.1;
+        let pos = vec2(transform.translation.x, transform.translation.y);
+        let (x, y) = (player_input.x(), player_input.y());
+        let new_pos = predict_move(&map, &closed_doors, &settings, my_state.0, pos, x, y);
+        transform.translation = vec3(new_pos.x, new_pos.y, 0.0);
+    }
+    trace!(player_input = ?*player_input, "Input");
+}
+
+// Mirrors the server's movement rules, ghosts ignore walls and doors.
+fn predict_move(
+    map: &Map,
+    closed_doors: &ClosedDoors,
+    settings: &GameSettings,
+    player_state: PlayerState,
+    pos: Vec2,
+    x: i16,
+    y: i16,
+) -> Vec2 {
+    match player_state {
+        PlayerState::Alive => move_player(map, closed_doors, settings.player_speed, pos, x, y),
+        PlayerState::Dead => move_ghost(settings.player_speed, pos, x, y),
+    }
+}
+
+// SUS_SERVER picks a server by address. Otherwise we ask the master server at
+// SUS_MASTER_SERVER, or look on the LAN, for one that can take us, and fall
+// back to a local server if nobody answers.
+fn find_server(my_room: &RoomRequest) -> SocketAddr {
+    if let Ok(addr) = std::env::var("SUS_SERVER") {
+        return addr.parse().expect("SUS_SERVER should be an address like 192.168.1.2:7600");
+    }
+
+    // Servers never list their room codes, so to join a room we ask the master
+    // server for the server running it. LAN discovery can't be asked, so on a
+    // LAN with more than one server, SUS_SERVER picks the right one.
+    let room_code = match my_room {
+        RoomRequest::Create => None,
+        RoomRequest::Join(code) => Some(code.as_str()),
+    };
+
+    let servers = match std::env::var("SUS_MASTER_SERVER") {
+        Ok(master_addr) => {
+            let master_addr = master_addr
+                .parse()
+                .expect("SUS_MASTER_SERVER should be an address like 1.2.3.4:7602");
+            let query = ServerQuery {
+                protocol: SUPPORTED_PROTOCOL,
+                joinable_only: false,
+                room_code: room_code.map(str::to_string),
+            };
+
+            list_servers(master_addr, query, SERVER_SEARCH_TIMEOUT)
+        },
+        Err(_) => {
+            let probe_addr = SocketAddr::from(([255, 255, 255, 255], DISCOVERY_PORT));
+            discover_servers(probe_addr, SERVER_SEARCH_TIMEOUT)
+        },
+    };
+
+    let servers = match servers {
+        Ok(servers) => servers,
+        Err(e) => {
+            warn!(error = ?e, "Couldn't search for servers");
+            vec![]
+        },
+    };
+
+    for server in &servers {
+        info!(
+            name = ?server.info.name,
+            addr = %server.addr,
+            players = server.info.player_count(),
+            rooms = server.info.rooms.len(),
+            "Found a server"
+        );
+    }
+
+    // A search for a room on the master server only finds the server running
+    // it, anything will do for a new one.
+    match servers.first() {
+        Some(server) => server.addr,
+        None => SERVER_ADDR.parse().unwrap(),
     }
-    // println!("player_input: {:?}", *player_input);
 }
 
 fn main() {
+    init_logging("info", LogFormat::Text);
     sus_common::simple_game::bevy::run_bevy_game::<SusGame>();
 }
@@ -0,0 +1,219 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    time::Duration,
+};
+use sus_common::{
+    network::SequenceCmp,
+    simple_game::bevy::{bevy_ecs, Resource},
+};
+
+// Byte rates are averaged over this long.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+// Loss is worked out over this many of the server's most recent ticks.
+const LOSS_WINDOW: u64 = 100;
+
+// How many reconciliations the worst prediction error is kept for.
+const ERROR_WINDOW: usize = 60;
+
+// How the connection to the server is doing, for the debug overlay. Times are
+// however long the game has been running, so this can be fed anything.
+#[derive(Debug, Default, Resource)]
+pub struct NetworkStats {
+    // When each input still waiting for the server went out.
+    inputs_sent: VecDeque<(u16, Duration)>,
+    unacked_inputs: usize,
+    rtt: Option<Duration>,
+    first_tick: Option<u64>,
+    // Ticks received inside the loss window, oldest first.
+    ticks: VecDeque<u64>,
+    received: BTreeMap<&'static str, VecDeque<(Duration, usize)>>,
+    sent: BTreeMap<&'static str, VecDeque<(Duration, usize)>>,
+    prediction_errors: VecDeque<f32>,
+    // Corrections too big to smooth over.
+    snaps: u32,
+}
+
+impl NetworkStats {
+    pub fn packet_received(&mut self, stream: &'static str, bytes: usize, now: Duration) {
+        record_bytes(self.received.entry(stream).or_default(), bytes, now);
+    }
+
+    pub fn packet_sent(&mut self, stream: &'static str, bytes: usize, now: Duration) {
+        record_bytes(self.sent.entry(stream).or_default(), bytes, now);
+    }
+
+    pub fn input_sent(&mut self, counter: u16, now: Duration) {
+        self.inputs_sent.push_back((counter, now));
+    }
+
+    // The server has applied everything up to `counter`, leaving `unacked`
+    // inputs for us to replay. Acks only measure a round trip the first time
+    // they move forward, as later ones are just repeating themselves.
+    pub fn input_acked(&mut self, counter: u16, unacked: usize, now: Duration) {
+        self.unacked_inputs = unacked;
+
+        let mut sent_at = None;
+
+        while let Some((sent, at)) = self.inputs_sent.front() {
+            if sent.sequentially_greater_than(counter) {
+                break;
+            }
+
+            if *sent == counter {
+                sent_at = Some(*at);
+            }
+
+            self.inputs_sent.pop_front();
+        }
+
+        // Smoothed the way TCP does it, so one slow packet doesn't make the
+        // number jump around.
+        if let Some(sent_at) = sent_at {
+            let sample = now.saturating_sub(sent_at);
+            self.rtt = Some(match self.rtt {
+                Some(rtt) => (rtt * 7 + sample) / 8,
+                None => sample,
+            });
+        }
+    }
+
+    pub fn tick_received(&mut self, tick: u64) {
+        if self.ticks.back().is_some_and(|newest| tick <= *newest) {
+            return;
+        }
+
+        self.first_tick.get_or_insert(tick);
+        self.ticks.push_back(tick);
+
+        while self.ticks.front().is_some_and(|oldest| oldest + LOSS_WINDOW <= tick) {
+            self.ticks.pop_front();
+        }
+    }
+
+    // How far the predicted position was from where the server put us, and
+    // whether we had to jump there.
+    pub fn reconciled(&mut self, prediction_error: f32, snapped: bool) {
+        if self.prediction_errors.len() == ERROR_WINDOW {
+            self.prediction_errors.pop_front();
+        }
+
+        self.prediction_errors.push_back(prediction_error);
+        self.snaps += snapped as u32;
+    }
+
+    pub fn rtt(&self) -> Option<Duration> {
+        self.rtt
+    }
+
+    pub fn unacked_inputs(&self) -> usize {
+        self.unacked_inputs
+    }
+
+    pub fn server_tick(&self) -> Option<u64> {
+        self.ticks.back().copied()
+    }
+
+    // The fraction of the server's recent ticks that never arrived.
+    pub fn packet_loss(&self) -> Option<f32> {
+        let (first, newest) = (self.first_tick?, *self.ticks.back()?);
+        let expected = (newest - first + 1).min(LOSS_WINDOW);
+
+        Some(1.0 - self.ticks.len() as f32 / expected as f32)
+    }
+
+    // Bytes per second received and sent on each stream.
+    pub fn byte_rates(&self, now: Duration) -> BTreeMap<&'static str, (f32, f32)> {
+        let mut rates = BTreeMap::new();
+
+        for (stream, packets) in &self.received {
+            rates.entry(*stream).or_insert((0.0, 0.0)).0 = byte_rate(packets, now);
+        }
+
+        for (stream, packets) in &self.sent {
+            rates.entry(*stream).or_insert((0.0, 0.0)).1 = byte_rate(packets, now);
+        }
+
+        rates
+    }
+
+    pub fn last_prediction_error(&self) -> Option<f32> {
+        self.prediction_errors.back().copied()
+    }
+
+    pub fn max_prediction_error(&self) -> Option<f32> {
+        self.prediction_errors.iter().copied().reduce(f32::max)
+    }
+
+    pub fn snaps(&self) -> u32 {
+        self.snaps
+    }
+}
+
+fn record_bytes(packets: &mut VecDeque<(Duration, usize)>, bytes: usize, now: Duration) {
+    packets.push_back((now, bytes));
+
+    while packets.front().is_some_and(|(at, _)| now.saturating_sub(*at) > RATE_WINDOW) {
+        packets.pop_front();
+    }
+}
+
+fn byte_rate(packets: &VecDeque<(Duration, usize)>, now: Duration) -> f32 {
+    let bytes: usize = packets
+        .iter()
+        .filter(|(at, _)| now.saturating_sub(*at) <= RATE_WINDOW)
+        .map(|(_, bytes)| bytes)
+        .sum();
+
+    bytes as f32 / RATE_WINDOW.as_secs_f32()
+}
+
+#[test]
+fn test_network_stats() {
+    let ms = Duration::from_millis;
+    let mut stats = NetworkStats::default();
+
+    assert_eq!(stats.rtt(), None);
+    assert_eq!(stats.packet_loss(), None);
+
+    for counter in 0..5 {
+        stats.input_sent(counter, ms(counter as u64 * 16));
+    }
+
+    // Input 2 went out at 32ms.
+    stats.input_acked(2, 2, ms(132));
+    assert_eq!(stats.rtt(), Some(ms(100)));
+    assert_eq!(stats.unacked_inputs(), 2);
+
+    // Repeating the same ack isn't a new measurement.
+    stats.input_acked(2, 2, ms(500));
+    assert_eq!(stats.rtt(), Some(ms(100)));
+
+    // Input 4 went out at 64ms and took 180ms, pulling the average up an eighth
+    // of the way.
+    stats.input_acked(4, 0, ms(244));
+    assert_eq!(stats.rtt(), Some(ms(110)));
+
+    // Ticks 13 and 17 went missing.
+    for tick in (10..30).filter(|tick| *tick != 13 && *tick != 17) {
+        stats.tick_received(tick);
+    }
+
+    assert_eq!(stats.server_tick(), Some(29));
+    assert!((stats.packet_loss().unwrap() - 0.1).abs() < 1e-6);
+
+    stats.packet_received("game_state", 300, ms(0));
+    stats.packet_received("game_state", 200, ms(900));
+    stats.packet_sent("input", 40, ms(950));
+
+    // The first packet has dropped out of the window.
+    let rates = stats.byte_rates(ms(1500));
+    assert_eq!(rates["game_state"], (200.0, 0.0));
+    assert_eq!(rates["input"], (0.0, 40.0));
+
+    stats.reconciled(5.0, true);
+    stats.reconciled(0.1, false);
+    assert_eq!(stats.last_prediction_error(), Some(0.1));
+    assert_eq!(stats.max_prediction_error(), Some(5.0));
+    assert_eq!(stats.snaps(), 1);
+}
@@ -0,0 +1,98 @@
+use std::time::Duration;
+use sus_common::simple_game::{
+    bevy::{bevy_ecs, Resource},
+    glam::{Vec2, Vec3},
+};
+
+// What's left of a correction shrinks by a factor of e every this long, so it's
+// mostly gone within a few frames.
+const ERROR_DECAY_TIME: Duration = Duration::from_millis(100);
+
+// Corrections bigger than this are shown straight away rather than blended,
+// as sliding across that much of the map would look worse than a jump. That's
+// about a fifth of a second of walking at the fastest speed the settings
+// allow, 0.3 units a step at 60 steps a second.
+pub const SNAP_DISTANCE: f32 = 4.0;
+
+// Below this the offset isn't visible, and is dropped.
+const SETTLED_DISTANCE: f32 = 0.001;
+
+// Keeps where our player is drawn separate from where the simulation has them.
+// When the server corrects our prediction the simulation moves straight to the
+// corrected position, and the jump is carried here and blended out instead of
+// showing up as a pop.
+#[derive(Debug, Default, Resource)]
+pub struct PredictionSmoothing {
+    // From the simulated position to the drawn one.
+    offset: Vec2,
+}
+
+impl PredictionSmoothing {
+    // The simulation had us at `predicted` and now has us at `corrected`.
+    // Returns whether the correction was too big to blend.
+    pub fn correct(&mut self, predicted: Vec2, corrected: Vec2) -> bool {
+        let offset = predicted + self.offset - corrected;
+
+        if offset.length() > SNAP_DISTANCE {
+            self.offset = Vec2::ZERO;
+            return true;
+        }
+
+        self.offset = offset;
+        false
+    }
+
+    // For when the simulation moves us somewhere we shouldn't be seen sliding
+    // to, like through a vent.
+    pub fn snap(&mut self) {
+        self.offset = Vec2::ZERO;
+    }
+
+    pub fn blend(&mut self, delta: Duration) {
+        self.offset *= (-delta.as_secs_f32() / ERROR_DECAY_TIME.as_secs_f32()).exp();
+
+        if self.offset.length() < SETTLED_DISTANCE {
+            self.offset = Vec2::ZERO;
+        }
+    }
+
+    // How far what's drawn still is from the simulation.
+    pub fn visual_error(&self) -> f32 {
+        self.offset.length()
+    }
+
+    pub fn rendered(&self, simulated: Vec3) -> Vec3 {
+        simulated + self.offset.extend(0.0)
+    }
+}
+
+#[test]
+fn test_prediction_smoothing() {
+    use sus_common::simple_game::glam::vec2;
+
+    let mut smoothing = PredictionSmoothing::default();
+
+    // Drawn where we were predicted to be, then eased over to the correction.
+    assert!(!smoothing.correct(vec2(10.0, 5.0), vec2(11.0, 5.0)));
+    assert_eq!(smoothing.rendered(Vec3::new(11.0, 5.0, 0.0)), Vec3::new(10.0, 5.0, 0.0));
+
+    smoothing.blend(ERROR_DECAY_TIME);
+    assert!((smoothing.visual_error() - (-1.0f32).exp()).abs() < 1e-4);
+
+    // A second correction starts from wherever we're drawn, not where the
+    // first one ended up.
+    let drawn = smoothing.rendered(Vec3::new(11.0, 5.0, 0.0));
+    smoothing.correct(vec2(11.0, 5.0), vec2(11.5, 5.0));
+    assert!(smoothing.rendered(Vec3::new(11.5, 5.0, 0.0)).abs_diff_eq(drawn, 1e-5));
+
+    // Gone within a second.
+    for _ in 0..60 {
+        smoothing.blend(Duration::from_millis(16));
+    }
+
+    assert_eq!(smoothing.visual_error(), 0.0);
+
+    // Too far to slide.
+    assert!(smoothing.correct(vec2(0.0, 0.0), vec2(SNAP_DISTANCE + 1.0, 0.0)));
+    assert_eq!(smoothing.visual_error(), 0.0);
+}
@@ -1,7 +1,63 @@
-use sus_common::simple_game::bevy::{bevy_ecs, Resource};
+use sus_common::{
+    network::{ConsoleInfo, LobbyStatusPacket, RoomRequest, SabotageStatus, TaskProgress},
+    simple_game::bevy::{bevy_ecs, Resource},
+    PlayerState, PlayerType,
+};
 
 #[derive(Debug, Resource)]
 pub struct InputCounter(pub u16);
 
 #[derive(Debug, Resource)]
 pub struct MyName(pub String);
+
+// The room to create or join when connecting.
+#[derive(Debug, Resource)]
+pub struct MyRoom(pub RoomRequest);
+
+#[derive(Debug, Default, Resource)]
+pub struct MyRole(pub Option<PlayerType>);
+
+// None until the game starts, and while comms are sabotaged.
+#[derive(Debug, Default, Resource)]
+pub struct MyTasks(pub Option<Vec<TaskProgress>>);
+
+#[derive(Debug, Default, Resource)]
+pub struct CurrentSabotage(pub Option<SabotageStatus>);
+
+// The vent we're hiding in, if any.
+#[derive(Debug, Default, Resource)]
+pub struct MyVent(pub Option<u8>);
+
+// Whether we're alive or a ghost this game.
+#[derive(Debug, Resource)]
+pub struct MyState(pub PlayerState);
+
+impl Default for MyState {
+    fn default() -> Self {
+        Self(PlayerState::Alive)
+    }
+}
+
+// The message being typed, while the chat box is open.
+#[derive(Debug, Default, Resource)]
+pub struct ChatInput(pub Option<String>);
+
+// What the console we're using is showing, if we're using one.
+#[derive(Debug, Default, Resource)]
+pub struct ConsoleView(pub Option<ConsoleInfo>);
+
+// The player who can change the settings and start the game.
+#[derive(Debug, Default, Resource)]
+pub struct Host(pub Option<u16>);
+
+// Who's ready in the lobby, and how long until the game starts.
+#[derive(Debug, Default, Resource)]
+pub struct LobbyStatus(pub Option<LobbyStatusPacket>);
+
+// Whether the network debug overlay is showing. F12 toggles it.
+#[derive(Debug, Default, Resource)]
+pub struct DebugOverlay(pub bool);
+
+// How many people are watching the room.
+#[derive(Debug, Default, Resource)]
+pub struct SpectatorCount(pub u8);
@@ -0,0 +1,146 @@
+use crate::{events::OutgoingPacket, resources::ChatInput, sets};
+use sus_common::{
+    components::player::PlayerName,
+    network::{ChatPacket, ClientToServer, DeliveryType, CHAT_STREAM, MAX_CHAT_MESSAGE_LEN},
+    resources::PlayerToEntity,
+    simple_game::{
+        bevy::{
+            App, CoreSchedule, EventReader, EventWriter, IntoSystemAppConfig, IntoSystemConfig,
+            Plugin, Query, Res, ResMut,
+        },
+        winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
+    },
+    tracing::info,
+};
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ChatInput>().add_system(handle_chat_input).add_system(
+            handle_chat
+                .after(sets::NetworkSystem::Receive)
+                .in_set(sets::MainLogic)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+// Return opens the chat box and sends the message, Escape throws it away.
+fn handle_chat_input(
+    mut keyboard_input_events: EventReader<KeyboardInput>,
+    mut chat_input: ResMut<ChatInput>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in keyboard_input_events.iter() {
+        let key_code = match event {
+            KeyboardInput {
+                virtual_keycode: Some(key_code), state: ElementState::Pressed, ..
+            } => *key_code,
+            _ => continue,
+        };
+
+        let message = match &mut chat_input.0 {
+            Some(message) => message,
+            None => {
+                if key_code == VirtualKeyCode::Return {
+                    chat_input.0 = Some(String::new());
+                }
+
+                continue;
+            },
+        };
+
+        match key_code {
+            VirtualKeyCode::Back => {
+                message.pop();
+                continue;
+            },
+            VirtualKeyCode::Escape => {
+                chat_input.0 = None;
+                continue;
+            },
+            VirtualKeyCode::Return => {},
+            _ => {
+                if let Some(c) = key_to_char(key_code) {
+                    if message.len() < MAX_CHAT_MESSAGE_LEN {
+                        message.push(c);
+                    }
+                }
+
+                continue;
+            },
+        }
+
+        let message = chat_input.0.take().unwrap_or_default();
+
+        if !message.trim().is_empty() {
+            outgoing_packets.send(OutgoingPacket::new(
+                ClientToServer::Chat(message),
+                DeliveryType::ReliableOrdered,
+                Some(CHAT_STREAM),
+            ));
+        }
+    }
+}
+
+fn handle_chat(
+    mut chat_rx: EventReader<ChatPacket>,
+    player_to_entity: Res<PlayerToEntity>,
+    players: Query<&PlayerName>,
+) {
+    for chat in chat_rx.iter() {
+        let name = player_to_entity
+            .0
+            .get(&chat.id)
+            .and_then(|entity| players.get(*entity).ok())
+            .map_or("???", |name| name.0.as_str());
+
+        info!(player = chat.id, %name, message = %chat.message, "Chat");
+    }
+}
+
+fn key_to_char(key_code: VirtualKeyCode) -> Option<char> {
+    let c = match key_code {
+        VirtualKeyCode::A => 'a',
+        VirtualKeyCode::B => 'b',
+        VirtualKeyCode::C => 'c',
+        VirtualKeyCode::D => 'd',
+        VirtualKeyCode::E => 'e',
+        VirtualKeyCode::F => 'f',
+        VirtualKeyCode::G => 'g',
+        VirtualKeyCode::H => 'h',
+        VirtualKeyCode::I => 'i',
+        VirtualKeyCode::J => 'j',
+        VirtualKeyCode::K => 'k',
+        VirtualKeyCode::L => 'l',
+        VirtualKeyCode::M => 'm',
+        VirtualKeyCode::N => 'n',
+        VirtualKeyCode::O => 'o',
+        VirtualKeyCode::P => 'p',
+        VirtualKeyCode::Q => 'q',
+        VirtualKeyCode::R => 'r',
+        VirtualKeyCode::S => 's',
+        VirtualKeyCode::T => 't',
+        VirtualKeyCode::U => 'u',
+        VirtualKeyCode::V => 'v',
+        VirtualKeyCode::W => 'w',
+        VirtualKeyCode::X => 'x',
+        VirtualKeyCode::Y => 'y',
+        VirtualKeyCode::Z => 'z',
+        VirtualKeyCode::Key0 => '0',
+        VirtualKeyCode::Key1 => '1',
+        VirtualKeyCode::Key2 => '2',
+        VirtualKeyCode::Key3 => '3',
+        VirtualKeyCode::Key4 => '4',
+        VirtualKeyCode::Key5 => '5',
+        VirtualKeyCode::Key6 => '6',
+        VirtualKeyCode::Key7 => '7',
+        VirtualKeyCode::Key8 => '8',
+        VirtualKeyCode::Key9 => '9',
+        VirtualKeyCode::Space => ' ',
+        _ => return None,
+    };
+
+    Some(c)
+}
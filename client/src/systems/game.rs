@@ -0,0 +1,405 @@
+use crate::{
+    components::{MyPlayer, OutOfSight},
+    events::OutgoingPacket,
+    resources::{
+        ChatInput, ConsoleView, CurrentSabotage, Host, LobbyStatus, MyRole, MyState, MyTasks,
+        MyVent, SpectatorCount,
+    },
+    sets,
+};
+use sus_common::{
+    components::player::{MyPlayerId, PlayerId},
+    map::{within_interact_distance, Map},
+    network::{
+        ClientToServer, ConsoleInfoPacket, ConsoleKind, DeliveryType, DoorStatusPacket,
+        GameOverPacket, HoldReactorPacket, LobbyStatusPacket, OxygenCodePacket, PlayerStatusPacket,
+        RoleAssignmentPacket, SabotageKind, SabotageStatus, SabotageStatusPacket, SettingsPacket,
+        SpectatorCountPacket, TaskListPacket, VentStatusPacket,
+    },
+    resources::ClosedDoors,
+    settings::GameSettings,
+    simple_game::{
+        bevy::{
+            App, CoreSchedule, EventReader, EventWriter, IntoSystemAppConfigs, IntoSystemConfigs,
+            Local, Plugin, Query, Res, ResMut, Transform, With, Without,
+        },
+        glam::{vec2, Vec2},
+        winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
+    },
+    tracing::info,
+    PlayerState, PlayerType,
+};
+
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Map::default())
+            .init_resource::<MyRole>()
+            .init_resource::<MyTasks>()
+            .init_resource::<CurrentSabotage>()
+            .init_resource::<MyVent>()
+            .init_resource::<ClosedDoors>()
+            .init_resource::<MyState>()
+            .init_resource::<ConsoleView>()
+            .init_resource::<GameSettings>()
+            .init_resource::<Host>()
+            .init_resource::<LobbyStatus>()
+            .init_resource::<SpectatorCount>()
+            .add_system(handle_actions)
+            .add_system(handle_lobby_actions)
+            .add_systems(
+                (
+                    handle_role_assignment,
+                    handle_task_list,
+                    handle_sabotage_status,
+                    handle_game_over,
+                    handle_vent_status,
+                    handle_door_status,
+                    handle_player_status,
+                    handle_console_info,
+                    handle_settings,
+                    handle_lobby_status,
+                    handle_spectator_count,
+                )
+                    .after(sets::NetworkSystem::Receive)
+                    .in_set(sets::MainLogic)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+fn handle_role_assignment(
+    mut role_assignment_rx: EventReader<RoleAssignmentPacket>,
+    mut my_role: ResMut<MyRole>,
+) {
+    for role_assignment in role_assignment_rx.iter() {
+        my_role.0 = Some(role_assignment.player_type);
+    }
+}
+
+fn handle_task_list(mut task_list_rx: EventReader<TaskListPacket>, mut my_tasks: ResMut<MyTasks>) {
+    for task_list in task_list_rx.iter() {
+        my_tasks.0 = task_list.tasks.clone();
+    }
+}
+
+fn handle_sabotage_status(
+    mut sabotage_status_rx: EventReader<SabotageStatusPacket>,
+    mut current_sabotage: ResMut<CurrentSabotage>,
+) {
+    for sabotage_status in sabotage_status_rx.iter() {
+        current_sabotage.0 = sabotage_status.sabotage.clone();
+    }
+}
+
+fn handle_vent_status(
+    mut vent_status_rx: EventReader<VentStatusPacket>,
+    mut my_vent: ResMut<MyVent>,
+) {
+    for vent_status in vent_status_rx.iter() {
+        my_vent.0 = vent_status.vent;
+    }
+}
+
+fn handle_door_status(
+    mut door_status_rx: EventReader<DoorStatusPacket>,
+    mut closed_doors: ResMut<ClosedDoors>,
+) {
+    for door_status in door_status_rx.iter() {
+        closed_doors.0 = door_status.closed_groups.clone();
+    }
+}
+
+fn handle_player_status(
+    mut player_status_rx: EventReader<PlayerStatusPacket>,
+    mut my_state: ResMut<MyState>,
+) {
+    for player_status in player_status_rx.iter() {
+        if player_status.player_state == PlayerState::Dead {
+            info!("You died, you're a ghost now");
+        }
+
+        my_state.0 = player_status.player_state;
+    }
+}
+
+fn handle_console_info(
+    mut console_info_rx: EventReader<ConsoleInfoPacket>,
+    mut console_view: ResMut<ConsoleView>,
+) {
+    for console_info in console_info_rx.iter() {
+        console_view.0 = console_info.info.clone();
+    }
+}
+
+fn handle_settings(
+    mut settings_rx: EventReader<SettingsPacket>,
+    mut settings: ResMut<GameSettings>,
+    mut host: ResMut<Host>,
+) {
+    for packet in settings_rx.iter() {
+        *settings = packet.settings.clone();
+        host.0 = packet.host;
+    }
+}
+
+fn handle_lobby_status(
+    mut lobby_status_rx: EventReader<LobbyStatusPacket>,
+    mut lobby_status: ResMut<LobbyStatus>,
+) {
+    for packet in lobby_status_rx.iter() {
+        if let Some(countdown_secs) = packet.countdown_secs {
+            info!(countdown_secs, "Starting soon");
+        }
+
+        lobby_status.0 = Some(packet.clone());
+    }
+}
+
+fn handle_spectator_count(
+    mut spectator_count_rx: EventReader<SpectatorCountPacket>,
+    mut spectator_count: ResMut<SpectatorCount>,
+) {
+    for packet in spectator_count_rx.iter() {
+        if packet.count != spectator_count.0 {
+            info!(count = packet.count, "Spectators changed");
+        }
+
+        spectator_count.0 = packet.count;
+    }
+}
+
+fn handle_game_over(
+    mut game_over_rx: EventReader<GameOverPacket>,
+    mut my_role: ResMut<MyRole>,
+    mut my_tasks: ResMut<MyTasks>,
+    mut current_sabotage: ResMut<CurrentSabotage>,
+    mut my_vent: ResMut<MyVent>,
+    mut closed_doors: ResMut<ClosedDoors>,
+    mut console_view: ResMut<ConsoleView>,
+) {
+    for _game_over in game_over_rx.iter() {
+        my_role.0 = None;
+        my_tasks.0 = None;
+        current_sabotage.0 = None;
+        my_vent.0 = None;
+        closed_doors.0.clear();
+        console_view.0 = None;
+    }
+}
+
+// E uses whatever is nearby, and impostors can sabotage with the number keys.
+// Impostors can also hop in and out of vents with V, and crawl to the next
+// linked vent with Tab. Keys 5 to 7 slam a group of doors shut, and Q kills
+// the closest player in view. C opens or closes a nearby console.
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn handle_actions(
+    mut keyboard_input_events: EventReader<KeyboardInput>,
+    map: Res<Map>,
+    settings: Res<GameSettings>,
+    chat_input: Res<ChatInput>,
+    my_role: Res<MyRole>,
+    my_state: Res<MyState>,
+    my_tasks: Res<MyTasks>,
+    current_sabotage: Res<CurrentSabotage>,
+    my_vent: Res<MyVent>,
+    console_view: Res<ConsoleView>,
+    mut next_vent_link: Local<usize>,
+    mut held_reactor_station: Local<Option<u8>>,
+    my_player: Query<&Transform, With<MyPlayer>>,
+    other_players: Query<(&PlayerId, &Transform), (Without<MyPlayer>, Without<OutOfSight>)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let pos = match my_player.get_single() {
+        Ok(transform) => vec2(transform.translation.x, transform.translation.y),
+        Err(_) => return,
+    };
+
+    let is_impostor = my_role.0 == Some(PlayerType::Impostor);
+    let is_alive = my_state.0 == PlayerState::Alive;
+
+    for event in keyboard_input_events.iter() {
+        if chat_input.0.is_some() {
+            continue;
+        }
+
+        let (key_code, pressed) = match event {
+            KeyboardInput { virtual_keycode: Some(key_code), state, .. } => {
+                (*key_code, *state == ElementState::Pressed)
+            },
+            _ => continue,
+        };
+
+        let msg = match (key_code, pressed) {
+            (VirtualKeyCode::E, true) => {
+                interact(pos, &map, &my_tasks, &current_sabotage, &mut held_reactor_station)
+            },
+            (VirtualKeyCode::E, false) => held_reactor_station.take().map(|station| {
+                ClientToServer::HoldReactor(HoldReactorPacket { station, holding: false })
+            }),
+            (VirtualKeyCode::Key1, true) if is_impostor => {
+                Some(ClientToServer::Sabotage(SabotageKind::Lights))
+            },
+            (VirtualKeyCode::Key2, true) if is_impostor => {
+                Some(ClientToServer::Sabotage(SabotageKind::Comms))
+            },
+            (VirtualKeyCode::Key3, true) if is_impostor => {
+                Some(ClientToServer::Sabotage(SabotageKind::Reactor))
+            },
+            (VirtualKeyCode::Key4, true) if is_impostor => {
+                Some(ClientToServer::Sabotage(SabotageKind::Oxygen))
+            },
+            (VirtualKeyCode::Key5, true) if is_impostor => Some(ClientToServer::CloseDoors(0)),
+            (VirtualKeyCode::Key6, true) if is_impostor => Some(ClientToServer::CloseDoors(1)),
+            (VirtualKeyCode::Key7, true) if is_impostor => Some(ClientToServer::CloseDoors(2)),
+            (VirtualKeyCode::Q, true) if is_impostor && is_alive => other_players
+                .iter()
+                .map(|(id, transform)| {
+                    (id.0, vec2(transform.translation.x, transform.translation.y))
+                })
+                // The same check the server makes, with the room's settings.
+                .filter(|(_, other_pos)| pos.distance(*other_pos) <= settings.kill_distance)
+                .min_by(|(_, a), (_, b)| pos.distance(*a).total_cmp(&pos.distance(*b)))
+                .map(|(id, _)| ClientToServer::Kill(id)),
+            (VirtualKeyCode::V, true) if is_impostor && is_alive => match my_vent.0 {
+                Some(_) => Some(ClientToServer::ExitVent),
+                None => map
+                    .vents
+                    .iter()
+                    .position(|vent| within_interact_distance(pos, vent.pos))
+                    .map(|vent| ClientToServer::EnterVent(vent as u8)),
+            },
+            (VirtualKeyCode::C, true) => match console_view.0 {
+                Some(_) => Some(ClientToServer::LeaveConsole),
+                None => [ConsoleKind::Cameras, ConsoleKind::Admin, ConsoleKind::Vitals]
+                    .iter()
+                    .copied()
+                    .find(|kind| within_interact_distance(pos, map.console_pos(*kind)))
+                    .map(ClientToServer::UseConsole),
+            },
+            (VirtualKeyCode::Tab, true) => my_vent.0.and_then(|vent| {
+                let links = &map.vents.get(vent as usize)?.links;
+                let link = links.get(*next_vent_link % links.len().max(1))?;
+                *next_vent_link += 1;
+
+                Some(ClientToServer::MoveVent(*link))
+            }),
+            _ => None,
+        };
+
+        if let Some(msg) = msg {
+            outgoing_packets.send(OutgoingPacket::new(msg, DeliveryType::ReliableOrdered, None));
+        }
+    }
+}
+
+// R toggles whether we're ready. The host can start the game with F2, call off
+// the countdown with F3, cycle the number of impostors with F1, and add or
+// remove bots with F4 and F5.
+fn handle_lobby_actions(
+    mut keyboard_input_events: EventReader<KeyboardInput>,
+    chat_input: Res<ChatInput>,
+    settings: Res<GameSettings>,
+    host: Res<Host>,
+    lobby_status: Res<LobbyStatus>,
+    my_player_id: Res<MyPlayerId>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let my_player_id = match my_player_id.0 {
+        Some(id) => id,
+        None => return,
+    };
+
+    let is_host = host.0 == Some(my_player_id);
+    let is_ready =
+        lobby_status.0.as_ref().is_some_and(|status| status.ready.contains(&my_player_id));
+
+    for event in keyboard_input_events.iter() {
+        let key_code = match event {
+            KeyboardInput {
+                virtual_keycode: Some(key_code), state: ElementState::Pressed, ..
+            } => *key_code,
+            _ => continue,
+        };
+
+        if chat_input.0.is_some() {
+            continue;
+        }
+
+        let msg = match key_code {
+            VirtualKeyCode::R => ClientToServer::SetReady(!is_ready),
+            VirtualKeyCode::F1 if is_host => {
+                let mut new_settings = settings.clone();
+                new_settings.impostor_count = new_settings.impostor_count % 3 + 1;
+
+                ClientToServer::UpdateSettings(new_settings)
+            },
+            VirtualKeyCode::F2 if is_host => ClientToServer::StartGame,
+            VirtualKeyCode::F3 if is_host => ClientToServer::CancelStart,
+            VirtualKeyCode::F4 if is_host => ClientToServer::AddBot,
+            VirtualKeyCode::F5 if is_host => ClientToServer::RemoveBot,
+            _ => continue,
+        };
+
+        outgoing_packets.send(OutgoingPacket::new(msg, DeliveryType::ReliableOrdered, None));
+    }
+}
+
+// Works out what the use key does at `pos`. Fixing an active sabotage takes
+// priority over doing tasks.
+fn interact(
+    pos: Vec2,
+    map: &Map,
+    my_tasks: &MyTasks,
+    current_sabotage: &CurrentSabotage,
+    held_reactor_station: &mut Option<u8>,
+) -> Option<ClientToServer> {
+    let nearby_station = |stations: &[Vec2; 2]| {
+        stations.iter().position(|station| within_interact_distance(pos, *station))
+    };
+
+    match &current_sabotage.0 {
+        Some(SabotageStatus::Lights { switches })
+            if within_interact_distance(pos, map.lights_panel) =>
+        {
+            let switch = switches.iter().position(|on| !on)?;
+            return Some(ClientToServer::ToggleLightSwitch(switch as u8));
+        },
+        Some(SabotageStatus::Comms) if within_interact_distance(pos, map.comms_panel) => {
+            return Some(ClientToServer::FixComms);
+        },
+        Some(SabotageStatus::Reactor { .. }) => {
+            if let Some(station) = nearby_station(&map.reactor_stations) {
+                let station = station as u8;
+                *held_reactor_station = Some(station);
+
+                return Some(ClientToServer::HoldReactor(HoldReactorPacket {
+                    station,
+                    holding: true,
+                }));
+            }
+        },
+        Some(SabotageStatus::Oxygen { code, .. }) => {
+            if let Some(station) = nearby_station(&map.oxygen_stations) {
+                return Some(ClientToServer::EnterOxygenCode(OxygenCodePacket {
+                    station: station as u8,
+                    code: *code,
+                }));
+            }
+        },
+        _ => {},
+    }
+
+    my_tasks
+        .0
+        .as_ref()?
+        .iter()
+        .filter(|progress| !progress.done)
+        .find(|progress| {
+            map.tasks
+                .get(progress.task as usize)
+                .is_some_and(|station| within_interact_distance(pos, station.pos))
+        })
+        .map(|progress| ClientToServer::CompleteTask(progress.task))
+}
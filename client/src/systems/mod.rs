@@ -1,5 +1,11 @@
 pub mod sets;
 
+pub mod chat;
+pub use chat::*;
+
+pub mod game;
+pub use game::*;
+
 pub mod network;
 pub use network::*;
 
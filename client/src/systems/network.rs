@@ -1,18 +1,37 @@
-use crate::{events::OutgoingPacket, sets, MyName, SusGame};
-use std::time::Duration;
+use crate::{
+    events::OutgoingPacket, network_stats::NetworkStats, resources::MyRoom, sets, MyName, SusGame,
+};
+use std::{net::SocketAddr, time::Duration};
 use sus_common::{
-    laminar::{Config as NetworkConfig, Socket, SocketEvent},
+    cookie::Cookie,
+    crossbeam_channel::Sender,
+    encryption::{key_from_hex, Handshake, Session, WireMessage},
+    laminar::{Config as NetworkConfig, Packet, Socket, SocketEvent},
     network::{
-        make_packet, ClientToServer, ConnectAckPacket, ConnectPacket, DeliveryType,
-        FullGameStatePacket, LobbyTickPacket, NewPlayerPacket, ServerToClient,
+        decode_server_message, encode_client_message, is_reliable, make_packet, stream_name,
+        with_payload, ChatPacket, ClientToServer, ConnectAckPacket, ConnectPacket,
+        ConsoleInfoPacket, DeliveryType, DisconnectReason, DoorStatusPacket, FullGameStatePacket,
+        GameOverPacket, JoinRoomError, LobbyStatusPacket, LobbyTickPacket, NewPlayerPacket,
+        PlayerLeftPacket, PlayerStatusPacket, RoleAssignmentPacket, SabotageStatusPacket,
+        ServerToClient, SettingsPacket, SpectatorCountPacket, SpectatorTickPacket, TaskListPacket,
+        VentStatusPacket,
     },
     resources::network::{NetRx, NetTx, NetworkThread},
     simple_game::bevy::{
-        bevy_ecs::event::Events, App, Commands, CoreSchedule, EventWriter, IntoSystemAppConfig,
-        IntoSystemConfig, Plugin, Res, ResMut,
+        bevy_ecs,
+        bevy_ecs::{event::Events, system::SystemParam},
+        App, Commands, CoreSchedule, EventWriter, IntoSystemAppConfig, IntoSystemConfig, Local,
+        Plugin, Res, ResMut, Resource, Time,
     },
+    tracing::{debug, error, info, trace, warn},
 };
 
+// How often the socket is polled, the same as laminar's own polling loop.
+const NETWORK_POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+// Our hello is sent again this often until the server answers it.
+const HELLO_RESEND_INTERVAL: Duration = Duration::from_secs(1);
+
 pub struct ClientNetworkPlugin;
 
 impl Plugin for ClientNetworkPlugin {
@@ -20,8 +39,22 @@ impl Plugin for ClientNetworkPlugin {
         app.add_startup_system(setup)
             .add_event::<ConnectAckPacket>()
             .add_event::<NewPlayerPacket>()
+            .add_event::<PlayerLeftPacket>()
             .add_event::<FullGameStatePacket>()
             .add_event::<LobbyTickPacket>()
+            .add_event::<RoleAssignmentPacket>()
+            .add_event::<TaskListPacket>()
+            .add_event::<SabotageStatusPacket>()
+            .add_event::<GameOverPacket>()
+            .add_event::<VentStatusPacket>()
+            .add_event::<DoorStatusPacket>()
+            .add_event::<PlayerStatusPacket>()
+            .add_event::<ChatPacket>()
+            .add_event::<ConsoleInfoPacket>()
+            .add_event::<SettingsPacket>()
+            .add_event::<LobbyStatusPacket>()
+            .add_event::<SpectatorCountPacket>()
+            .add_event::<SpectatorTickPacket>()
             .init_resource::<Events<OutgoingPacket>>()
             .add_system(
                 network_receive
@@ -39,21 +72,60 @@ impl Plugin for ClientNetworkPlugin {
     }
 }
 
+// Everything we exchange with the server is encrypted. Until the server has
+// answered our handshake there's no session, and outgoing packets wait.
+#[derive(Resource)]
+struct ServerSession {
+    handshake: Option<Handshake>,
+    // From the server's answer to our first hello, to send back with the next.
+    cookie: Option<Cookie>,
+    next_hello: Option<Duration>,
+    // The key the server has to have, if we were told it.
+    pinned_key: Option<[u8; 32]>,
+    session: Option<Session>,
+}
+
+// Set SUS_SERVER_KEY to the key the server logs when it starts to be sure
+// nobody is standing in for it.
+fn pinned_server_key() -> Option<[u8; 32]> {
+    let hex = std::env::var("SUS_SERVER_KEY").ok()?;
+
+    match key_from_hex(&hex) {
+        Some(key) => Some(key),
+        None => {
+            // Carrying on would mean connecting without the check that was asked for.
+            error!("SUS_SERVER_KEY should be the 64 hex digit key the server logs when it starts");
+            std::process::exit(1);
+        },
+    }
+}
+
 fn setup(
     mut commands: Commands,
+    game: Res<SusGame>,
     my_name: Res<MyName>,
+    my_room: Res<MyRoom>,
     mut outgoing_packets: EventWriter<OutgoingPacket>,
 ) {
-    let mut socket = initialize_network();
+    let socket = initialize_network();
     let (net_tx, net_rx) = (socket.get_packet_sender(), socket.get_event_receiver());
 
-    let network_thread = std::thread::spawn(move || socket.start_polling());
-
-    let connect_packet = ClientToServer::Connect(ConnectPacket::new(&my_name.0));
+    let connect_packet = ConnectPacket::new(&my_name.0, my_room.0.clone());
+    let connect_packet = match game.spectating {
+        true => ClientToServer::ConnectAsSpectator(connect_packet),
+        false => ClientToServer::Connect(connect_packet),
+    };
 
     outgoing_packets.send(OutgoingPacket::new(connect_packet, DeliveryType::ReliableOrdered, None));
 
-    commands.insert_resource(NetworkThread(network_thread));
+    commands.insert_resource(ServerSession {
+        handshake: Some(Handshake::new()),
+        cookie: None,
+        next_hello: None,
+        pinned_key: pinned_server_key(),
+        session: None,
+    });
+    commands.insert_resource(NetworkThread::spawn(socket, NETWORK_POLL_INTERVAL));
     commands.insert_resource(NetTx(net_tx));
     commands.insert_resource(NetRx(net_rx));
 }
@@ -68,71 +140,315 @@ fn initialize_network() -> Socket {
     Socket::bind_with_config("0.0.0.0:0", net_config).expect("Could not connect to server")
 }
 
+// Events for everything the server sends once the lobby is up and running,
+// grouped so `network_receive` stays under the system parameter limit.
+#[derive(SystemParam)]
+struct GamePacketWriters<'w> {
+    role_assignment: EventWriter<'w, RoleAssignmentPacket>,
+    task_list: EventWriter<'w, TaskListPacket>,
+    sabotage_status: EventWriter<'w, SabotageStatusPacket>,
+    game_over: EventWriter<'w, GameOverPacket>,
+    vent_status: EventWriter<'w, VentStatusPacket>,
+    door_status: EventWriter<'w, DoorStatusPacket>,
+    player_status: EventWriter<'w, PlayerStatusPacket>,
+    chat: EventWriter<'w, ChatPacket>,
+    console_info: EventWriter<'w, ConsoleInfoPacket>,
+    settings: EventWriter<'w, SettingsPacket>,
+    lobby_status: EventWriter<'w, LobbyStatusPacket>,
+    spectator_count: EventWriter<'w, SpectatorCountPacket>,
+    spectator_tick: EventWriter<'w, SpectatorTickPacket>,
+}
+
+#[allow(clippy::too_many_arguments)]
 fn network_receive(
     mut game: ResMut<SusGame>,
+    time: Res<Time>,
     net_rx: Res<NetRx>,
+    mut network_stats: ResMut<NetworkStats>,
+    mut network_thread: ResMut<NetworkThread>,
+    mut server_session: ResMut<ServerSession>,
+    mut bad_packets: Local<u32>,
     mut connect_ack_tx: EventWriter<ConnectAckPacket>,
     mut new_player_tx: EventWriter<NewPlayerPacket>,
+    mut player_left_tx: EventWriter<PlayerLeftPacket>,
     mut full_game_state_tx: EventWriter<FullGameStatePacket>,
     mut lobby_tick_tx: EventWriter<LobbyTickPacket>,
+    mut game_packets: GamePacketWriters,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
 ) {
     let net_rx = &net_rx.0;
 
     for event in net_rx.try_iter() {
         match event {
             SocketEvent::Packet(packet) => {
-                let msg = packet.payload();
-
-                if packet.addr() == game.server_addr {
-                    if let Ok(decoded) = bincode::deserialize::<ServerToClient>(msg) {
-                        match decoded {
-                            ServerToClient::ConnectAck(connect_ack_packet) => {
-                                println!(
-                                    "Server accepted us, yay! Our id is {}",
-                                    connect_ack_packet.id
-                                );
-                                game.connected = true;
-
-                                connect_ack_tx.send(connect_ack_packet);
-                            },
-                            ServerToClient::NewPlayer(new_player_packet) => {
-                                println!("New player: {:?}", new_player_packet);
-                                new_player_tx.send(new_player_packet);
-                            },
-                            ServerToClient::FullGameState(full_game_state) => {
-                                println!("Full game state: {:?}", full_game_state);
-                                full_game_state_tx.send(full_game_state);
-                            },
-                            ServerToClient::LobbyTick(lobby_tick_packet) => {
-                                // println!("Lobby tick - {:?}", lobby_tick_packet);
-                                lobby_tick_tx.send(lobby_tick_packet);
+                if packet.addr() != game.server_addr {
+                    debug!(addr = %packet.addr(), "Dropped a packet from an unknown sender");
+                    continue;
+                }
+
+                let stream = stream_name(&packet);
+                network_stats.packet_received(stream, packet.payload().len(), time.elapsed());
+
+                let msg = match server_session.open(packet.payload()) {
+                    Ok(Some(msg)) => msg,
+                    Ok(None) => continue,
+                    Err(message) => {
+                        error!("{}", message);
+
+                        game.connected = false;
+                        game.disconnected = Some(message.to_string());
+                        network_thread.stop();
+                        return;
+                    },
+                };
+
+                let decoded = match decode_server_message(&msg) {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        *bad_packets += 1;
+                        warn!(count = *bad_packets, error = ?e, "Dropped a bad packet from the server");
+                        continue;
+                    },
+                };
+
+                match decoded {
+                    ServerToClient::ConnectAck(connect_ack_packet) => {
+                        info!(
+                            player = connect_ack_packet.id,
+                            room = %connect_ack_packet.room_code,
+                            protocol = connect_ack_packet.protocol_version,
+                            "Joined the room"
+                        );
+                        game.connected = true;
+
+                        connect_ack_tx.send(connect_ack_packet);
+                    },
+                    ServerToClient::NewPlayer(new_player_packet) => {
+                        info!(player = new_player_packet.id, name = %new_player_packet.name, "New player");
+                        new_player_tx.send(new_player_packet);
+                    },
+                    ServerToClient::PlayerLeft(player_left) => {
+                        info!(player = player_left.id, "Player left");
+                        player_left_tx.send(player_left);
+                    },
+                    ServerToClient::FullGameState(full_game_state) => {
+                        debug!(?full_game_state, "Full game state");
+                        full_game_state_tx.send(full_game_state);
+                    },
+                    ServerToClient::LobbyTick(lobby_tick_packet) => {
+                        trace!(?lobby_tick_packet, "Lobby tick");
+                        network_stats.tick_received(lobby_tick_packet.tick);
+                        lobby_tick_tx.send(lobby_tick_packet);
+                    },
+                    ServerToClient::RoleAssignment(role_assignment) => {
+                        info!(?role_assignment, "Role assigned");
+                        game_packets.role_assignment.send(role_assignment);
+                    },
+                    ServerToClient::TaskList(task_list) => {
+                        game_packets.task_list.send(task_list);
+                    },
+                    ServerToClient::SabotageStatus(sabotage_status) => {
+                        game_packets.sabotage_status.send(sabotage_status);
+                    },
+                    ServerToClient::GameOver(game_over) => {
+                        info!(winner = ?game_over.winner, "Game over");
+                        game_packets.game_over.send(game_over);
+                    },
+                    ServerToClient::VentStatus(vent_status) => {
+                        game_packets.vent_status.send(vent_status);
+                    },
+                    ServerToClient::DoorStatus(door_status) => {
+                        game_packets.door_status.send(door_status);
+                    },
+                    ServerToClient::PlayerStatus(player_status) => {
+                        game_packets.player_status.send(player_status);
+                    },
+                    ServerToClient::Chat(chat) => {
+                        game_packets.chat.send(chat);
+                    },
+                    ServerToClient::ConsoleInfo(console_info) => {
+                        game_packets.console_info.send(console_info);
+                    },
+                    ServerToClient::Settings(settings) => {
+                        debug!(?settings, "Settings");
+                        game_packets.settings.send(settings);
+                    },
+                    ServerToClient::JoinRoomFailed(join_room_failed) => {
+                        warn!(
+                            room = %join_room_failed.room_code,
+                            error = ?join_room_failed.error,
+                            "Couldn't join the room"
+                        );
+
+                        let message = match join_room_failed.error {
+                            JoinRoomError::NotFound => "There's no room with that code",
+                            JoinRoomError::Full => "The room is full",
+                            JoinRoomError::IncompatibleProtocol => {
+                                "The server runs an incompatible version of the game"
                             },
-                        }
-                    }
-                } else {
-                    println!("Unknown sender.");
+                            JoinRoomError::Banned => "Banned from the server",
+                            JoinRoomError::InProgress => "The room's game has already started",
+                            JoinRoomError::TooManyRooms => "The server can't open any more rooms",
+                        };
+
+                        // The server won't be sending anything else.
+                        game.disconnected = Some(message.to_string());
+                        network_thread.stop();
+                        return;
+                    },
+                    ServerToClient::LobbyStatus(lobby_status) => {
+                        game_packets.lobby_status.send(lobby_status);
+                    },
+                    ServerToClient::SpectatorCount(spectator_count) => {
+                        game_packets.spectator_count.send(spectator_count);
+                    },
+                    ServerToClient::Disconnected(disconnected) => {
+                        warn!(reason = ?disconnected.reason, "Disconnected by the server");
+
+                        let message = match disconnected.reason {
+                            DisconnectReason::Kicked => "Kicked from the server",
+                            DisconnectReason::Banned => "Banned from the server",
+                        };
+
+                        game.connected = false;
+                        game.disconnected = Some(message.to_string());
+
+                        // Nothing more is coming, or going.
+                        network_thread.stop();
+                        return;
+                    },
+                    ServerToClient::ServerShutdown(server_shutdown) => {
+                        warn!(reason = %server_shutdown.reason, "The server shut down");
+
+                        let message = match server_shutdown.reason.as_str() {
+                            "" => "The server shut down".to_string(),
+                            reason => format!("The server shut down: {}", reason),
+                        };
+
+                        game.connected = false;
+                        game.disconnected = Some(message);
+                        network_thread.stop();
+                        return;
+                    },
+                    ServerToClient::SystemMessage(system_message) => {
+                        info!(message = %system_message.message, "Message from the server");
+                    },
+                    ServerToClient::Ping(ping) => {
+                        let pong = ClientToServer::Pong(ping);
+                        outgoing_packets.send(OutgoingPacket::new(
+                            pong,
+                            DeliveryType::Unreliable,
+                            None,
+                        ));
+                    },
+                    ServerToClient::SpectateAck(spectate_ack) => {
+                        info!(
+                            room = %spectate_ack.room_code,
+                            protocol = spectate_ack.protocol_version,
+                            delay_secs = spectate_ack.delay_secs,
+                            "Spectating the room"
+                        );
+                        game.connected = true;
+                    },
+                    ServerToClient::SpectatorTick(spectator_tick) => {
+                        game_packets.spectator_tick.send(spectator_tick);
+                    },
                 }
             },
             SocketEvent::Timeout(addr) => {
-                println!("Server timed out: {}", addr);
+                warn!(%addr, "Server timed out");
             },
             SocketEvent::Connect(addr) => {
-                println!("Server connected: {}", addr);
+                info!(%addr, "Server connected");
             },
             SocketEvent::Disconnect(addr) => {
-                println!("Server disconnected: {}", addr);
+                info!(%addr, "Server disconnected");
+            },
+        }
+    }
+}
+
+impl ServerSession {
+    // Works through the handshake as the server answers it, and decrypts
+    // everything after it. Anything that fails either is dropped. Only fails
+    // outright if the server doesn't have the key we were told to expect.
+    fn open(&mut self, payload: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+        if let Some(session) = &mut self.session {
+            return Ok(session.open(payload));
+        }
+
+        match WireMessage::decode(payload) {
+            Some(WireMessage::Cookie(cookie)) => {
+                self.cookie = Some(cookie);
+                self.next_hello = None;
+            },
+            Some(WireMessage::ServerHello { public, server_key }) => {
+                if matches!(self.pinned_key, Some(pinned) if pinned != server_key) {
+                    return Err("The server's key doesn't match SUS_SERVER_KEY");
+                }
+
+                let handshake = match self.handshake.take() {
+                    Some(handshake) => handshake,
+                    None => return Ok(None),
+                };
+
+                self.session = handshake.finish_client(public, server_key);
+
+                if self.session.is_none() {
+                    warn!("The server sent an invalid handshake");
+                }
             },
+            _ => {},
+        }
+
+        Ok(None)
+    }
+
+    // Sends our hello until the server answers it, and straight away once
+    // there's a new cookie to send back.
+    fn send_hello(&mut self, net_tx: &Sender<Packet>, server_addr: SocketAddr, now: Duration) {
+        if matches!(self.next_hello, Some(next) if now < next) {
+            return;
+        }
+
+        let hello = match &self.handshake {
+            Some(handshake) => handshake.client_hello(self.cookie).encode(),
+            None => return,
+        };
+
+        self.next_hello = Some(now + HELLO_RESEND_INTERVAL);
+
+        let hello = make_packet(DeliveryType::Unreliable, hello, server_addr, None);
+
+        if let Err(e) = net_tx.send(hello) {
+            error!(error = ?e, "Failed to send handshake");
         }
     }
 }
 
 fn network_send(
     game: Res<SusGame>,
+    time: Res<Time>,
     net_tx: Res<NetTx>,
+    mut server_session: ResMut<ServerSession>,
+    mut network_stats: ResMut<NetworkStats>,
     mut outgoing_packets: ResMut<Events<OutgoingPacket>>, // Manual event cleanup
 ) {
     let net_tx = &net_tx.0;
 
+    if game.disconnected.is_some() {
+        outgoing_packets.clear();
+        return;
+    }
+
+    let session = match &mut server_session.session {
+        Some(session) => session,
+        None => {
+            server_session.send_hello(net_tx, game.server_addr, time.elapsed());
+            return;
+        },
+    };
+
     // Event cleanup happens here with .drain()
     for outgoing in outgoing_packets.drain() {
         if !game.connected && matches!(outgoing.packet, ClientToServer::PlayerInput(_)) {
@@ -140,13 +456,15 @@ fn network_send(
             continue;
         }
 
-        let data = bincode::serialize(&outgoing.packet).unwrap();
-
+        let data = encode_client_message(&outgoing.packet);
         let packet =
             make_packet(outgoing.delivery_type, data, game.server_addr, outgoing.stream_id);
+        let packet = with_payload(&packet, session.seal(is_reliable(&packet), packet.payload()));
+
+        network_stats.packet_sent(stream_name(&packet), packet.payload().len(), time.elapsed());
 
         if let Err(e) = net_tx.send(packet) {
-            println!("Failed to send packet: {:?}", e);
+            error!(error = ?e, "Failed to send packet");
         }
     }
 }
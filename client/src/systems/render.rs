@@ -1,13 +1,23 @@
-use crate::{sets, SusGame};
+use crate::{
+    components::{MyPlayer, OutOfSight},
+    network_stats::NetworkStats,
+    prediction::PredictionSmoothing,
+    resources::DebugOverlay,
+    sets, SusGame,
+};
+use std::{fmt::Write as _, time::Duration};
 use sus_common::{
-    components::player::PlayerId,
     simple_game::{
-        bevy::{App, Commands, IntoSystemConfig, Plugin, Query, Res, ResMut, Transform},
+        bevy::{
+            App, Commands, EventReader, IntoSystemConfig, Plugin, Query, Res, ResMut, Time,
+            Transform, Without,
+        },
         graphics::{
             text::{AxisAlign, Color, DefaultFont, StyledText, TextAlignment, TextSystem},
             DebugDrawer, FullscreenQuad, GraphicsDevice,
         },
         wgpu,
+        winit::event::{ElementState, KeyboardInput, VirtualKeyCode},
     },
     PlayerInput,
 };
@@ -16,7 +26,10 @@ pub struct RenderPlugin;
 
 impl Plugin for RenderPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup).add_system(render.in_set(sets::Render));
+        app.init_resource::<DebugOverlay>()
+            .add_startup_system(setup)
+            .add_system(toggle_debug_overlay.before(sets::Render))
+            .add_system(render.in_set(sets::Render));
     }
 }
 
@@ -32,13 +45,34 @@ fn setup(mut commands: Commands, graphics_device: Res<GraphicsDevice>) {
     commands.insert_resource(player_input);
 }
 
+fn toggle_debug_overlay(
+    mut keyboard_input_events: EventReader<KeyboardInput>,
+    mut debug_overlay: ResMut<DebugOverlay>,
+) {
+    for event in keyboard_input_events.iter() {
+        if let KeyboardInput {
+            virtual_keycode: Some(VirtualKeyCode::F12),
+            state: ElementState::Pressed,
+            ..
+        } = event
+        {
+            debug_overlay.0 = !debug_overlay.0;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn render(
     game: Res<SusGame>,
+    time: Res<Time>,
+    debug_overlay: Res<DebugOverlay>,
+    network_stats: Res<NetworkStats>,
+    smoothing: Res<PredictionSmoothing>,
     mut graphics_device: ResMut<GraphicsDevice>,
     fullscreen_quad: ResMut<FullscreenQuad>,
     mut text_system: ResMut<TextSystem>,
     mut debug_drawer: ResMut<DebugDrawer>,
-    players: Query<(&PlayerId, &Transform)>,
+    players: Query<(&Transform, Option<&MyPlayer>), Without<OutOfSight>>,
 ) {
     let mut frame_encoder = graphics_device.begin_frame();
 
@@ -63,46 +97,35 @@ fn render(
 
     let mut shape_recorder = debug_drawer.begin();
 
-    for (_player_id, transform) in players.iter() {
-        shape_recorder.draw_circle(transform.translation, 2.0, 0.0);
+    for (transform, my_player) in players.iter() {
+        // We're drawn where we appear to be, rather than where we are.
+        let position = match my_player {
+            Some(_) => smoothing.rendered(transform.translation),
+            None => transform.translation,
+        };
+
+        shape_recorder.draw_circle(position, 2.0, 0.0);
     }
     shape_recorder.end(&mut frame_encoder);
 
+    let overlay = if debug_overlay.0 {
+        debug_overlay_text(&game, &network_stats, &smoothing, time.elapsed())
+    } else {
+        String::new()
+    };
+
     text_system.render_horizontal(
         TextAlignment {
             x: AxisAlign::Start(10),
-            y: AxisAlign::WindowCenter,
+            y: AxisAlign::Start(10),
             max_width: None,
             max_height: None,
         },
         &[
-            StyledText::default_styling("This is a test."),
-            StyledText {
-                text: "Another test, blue this time",
-                font: DefaultFont::SpaceMono400(40),
-                color: Color::new(0, 0, 255, 255),
-            },
-            StyledText {
-                text: "\nTest with a line break, green.",
-                font: DefaultFont::SpaceMono400(40),
-                color: Color::new(0, 255, 0, 255),
-            },
-            StyledText {
-                text: "Red test\nHere are some numbers:\n0123456789!@#$%^&*(){}[].",
-                font: DefaultFont::SpaceMono400(40),
-                color: Color::new(255, 0, 0, 255),
-            },
+            StyledText::default_styling(connection_status(&game)),
             StyledText {
-                text: "\nOpacity test, this should be half-faded white",
-                font: DefaultFont::SpaceMono400(40),
-                color: Color::new(255, 255, 255, 128),
-            },
-            StyledText {
-                text: &format!(
-                    "\nServer addr: {}\nConnected: {}",
-                    game.server_addr, game.connected
-                ),
-                font: DefaultFont::SpaceMono400(40),
+                text: &overlay,
+                font: DefaultFont::SpaceMono400(24),
                 color: Color::new(255, 255, 255, 255),
             },
         ],
@@ -111,3 +134,49 @@ fn render(
 
     frame_encoder.finish();
 }
+
+fn connection_status(game: &SusGame) -> &str {
+    match &game.disconnected {
+        Some(message) => message,
+        None if game.connected && game.spectating => "Spectating",
+        None if game.connected => "Connected",
+        None => "Connecting",
+    }
+}
+
+fn debug_overlay_text(
+    game: &SusGame,
+    stats: &NetworkStats,
+    smoothing: &PredictionSmoothing,
+    now: Duration,
+) -> String {
+    let unknown = || "-".to_string();
+    let error = |error: f32| format!("{:.2}", error);
+
+    let mut text = format!("\nServer: {}", game.server_addr);
+    let tick = stats.server_tick().map_or_else(unknown, |tick| tick.to_string());
+    let _ = write!(text, "\nTick: {}", tick);
+    let rtt = stats.rtt().map_or_else(unknown, |rtt| format!("{}ms", rtt.as_millis()));
+    let _ = write!(text, "\nRTT: {}", rtt);
+    let loss = stats.packet_loss().map_or_else(unknown, |loss| format!("{:.1}%", loss * 100.0));
+    let _ = write!(text, "\nLoss: {}", loss);
+    let _ = write!(text, "\nUnacked inputs: {}", stats.unacked_inputs());
+    let _ = write!(
+        text,
+        "\nPrediction error: {} (max {})",
+        stats.last_prediction_error().map_or_else(unknown, error),
+        stats.max_prediction_error().map_or_else(unknown, error)
+    );
+    let _ = write!(
+        text,
+        "\nShown off by: {}, {} snaps",
+        error(smoothing.visual_error()),
+        stats.snaps()
+    );
+
+    for (stream, (received, sent)) in stats.byte_rates(now) {
+        let _ = write!(text, "\n{}: {:.0} B/s in, {:.0} B/s out", stream, received, sent);
+    }
+
+    text
+}
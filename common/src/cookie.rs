@@ -0,0 +1,80 @@
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+// A cookie comes back with a request to show the sender really is at the
+// address the request came from. It's a keyed hash of that address and the
+// current time window, so nothing has to be kept for anyone until they've
+// shown they can receive what we send them. That stops spoofed requests from
+// filling our tables or turning us into a reflector.
+pub type Cookie = [u8; 16];
+
+// A cookie stays valid for at least this long, and at most twice as long.
+pub const COOKIE_WINDOW: Duration = Duration::from_secs(30);
+
+pub struct CookieJar {
+    secret: [u8; 32],
+    start: Instant,
+}
+
+impl Default for CookieJar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CookieJar {
+    pub fn new() -> Self {
+        let mut secret = [0u8; 32];
+        OsRng.fill_bytes(&mut secret);
+
+        Self { secret, start: Instant::now() }
+    }
+
+    pub fn cookie(&self, addr: SocketAddr, now: Instant) -> Cookie {
+        self.cookie_for_window(addr, self.window(now))
+    }
+
+    // Cookies from the window before this one are still accepted, so one
+    // handed out just before the window changes doesn't go bad on the way.
+    pub fn check(&self, addr: SocketAddr, cookie: &Cookie, now: Instant) -> bool {
+        let window = self.window(now);
+
+        *cookie == self.cookie_for_window(addr, window)
+            || (window > 0 && *cookie == self.cookie_for_window(addr, window - 1))
+    }
+
+    fn window(&self, now: Instant) -> u64 {
+        now.saturating_duration_since(self.start).as_secs() / COOKIE_WINDOW.as_secs()
+    }
+
+    fn cookie_for_window(&self, addr: SocketAddr, window: u64) -> Cookie {
+        let mut hasher = Sha256::new();
+        hasher.update(self.secret);
+        hasher.update(window.to_le_bytes());
+        hasher.update(addr.to_string().as_bytes());
+
+        let mut cookie = [0u8; 16];
+        cookie.copy_from_slice(&hasher.finalize()[..16]);
+        cookie
+    }
+}
+
+#[test]
+fn test_cookies_are_tied_to_address_and_time() {
+    let jar = CookieJar::new();
+    let now = Instant::now();
+    let addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+    let other: SocketAddr = "10.0.0.1:5001".parse().unwrap();
+
+    let cookie = jar.cookie(addr, now);
+
+    assert!(jar.check(addr, &cookie, now));
+    assert!(jar.check(addr, &cookie, now + COOKIE_WINDOW));
+    assert!(!jar.check(other, &cookie, now));
+    assert!(!jar.check(addr, &cookie, now + COOKIE_WINDOW * 2));
+    assert!(!CookieJar::new().check(addr, &cookie, now));
+}
@@ -0,0 +1,253 @@
+use crate::{
+    protocol::{bincode_options, ProtocolVersions, SUPPORTED_PROTOCOL},
+    GameState,
+};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+// Discovery runs on its own plain UDP socket, next to the laminar one used for
+// the game itself.
+pub const DISCOVERY_PORT: u16 = 7601;
+
+// A probe is padded out to `PROBE_SIZE`, and only answered if the answer is no
+// bigger than the probe, so a probe sent from someone else's address can't be
+// used to flood them with more than it took to send.
+const PROBE: &[u8] = b"sus-discovery-probe";
+const PROBE_SIZE: usize = 512;
+const MAX_RESPONSE_SIZE: usize = 4096;
+
+// How many probes are answered per call to `answer_probes`, so a flood of them
+// can't hold up the game. Anything past that waits for the next call.
+const MAX_PROBES_PER_CALL: usize = 16;
+
+// However busy a server is, what it says about itself stays small enough to
+// read in one go. See `ServerInfo::limit`.
+pub const MAX_LISTED_ROOMS: usize = 16;
+pub const MAX_SERVER_NAME_LEN: usize = 64;
+
+// Room codes are left out, so only people who've been given one can join a
+// room. Anyone can see how many there are and how full they are.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoomInfo {
+    pub player_count: u16,
+    pub max_players: u16,
+    pub game_state: GameState,
+}
+
+// What a server says about itself in reply to a probe.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerInfo {
+    pub name: String,
+    pub protocol: ProtocolVersions,
+    // The port the game itself is running on.
+    pub game_port: u16,
+    pub rooms: Vec<RoomInfo>,
+}
+
+impl ServerInfo {
+    pub fn player_count(&self) -> usize {
+        self.rooms.iter().map(|room| room.player_count as usize).sum()
+    }
+
+    // Whether there's a room still in the lobby with space left to join.
+    pub fn is_joinable(&self) -> bool {
+        self.rooms.iter().any(RoomInfo::is_joinable)
+    }
+
+    // Cuts the name and room list down to `MAX_SERVER_NAME_LEN` and
+    // `MAX_LISTED_ROOMS`. Joinable rooms are kept over the rest, as they're
+    // the ones anyone is looking for.
+    pub fn limit(&mut self) {
+        if self.name.len() > MAX_SERVER_NAME_LEN {
+            let mut end = MAX_SERVER_NAME_LEN;
+
+            while !self.name.is_char_boundary(end) {
+                end -= 1;
+            }
+
+            self.name.truncate(end);
+        }
+
+        self.rooms.sort_by_key(|room| !room.is_joinable());
+        self.rooms.truncate(MAX_LISTED_ROOMS);
+    }
+}
+
+impl RoomInfo {
+    pub fn is_joinable(&self) -> bool {
+        self.game_state == GameState::Lobby && self.player_count < self.max_players
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiscoveredServer {
+    // Where to connect to play, built from the responder's IP and `info.game_port`.
+    pub addr: SocketAddr,
+    pub info: ServerInfo,
+}
+
+pub struct DiscoveryResponder {
+    socket: UdpSocket,
+}
+
+impl DiscoveryResponder {
+    pub fn bind(addr: SocketAddr) -> io::Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self { socket })
+    }
+
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    // Replies to the probes that have arrived since the last call, up to
+    // `MAX_PROBES_PER_CALL`, without blocking. `info` is only built if there's
+    // someone to answer.
+    pub fn answer_probes(&self, info: impl Fn() -> ServerInfo) -> io::Result<()> {
+        let mut buf = [0u8; PROBE_SIZE];
+        let mut response = None;
+
+        for _ in 0..MAX_PROBES_PER_CALL {
+            let (len, addr) = match self.socket.recv_from(&mut buf) {
+                Ok(received) => received,
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(e) => return Err(e),
+            };
+
+            if !buf[..len].starts_with(PROBE) {
+                continue;
+            }
+
+            let response = response.get_or_insert_with(|| {
+                let mut info = info();
+                info.limit();
+
+                bincode::serialize(&info).expect("ServerInfo serializes")
+            });
+
+            if response.len() > len {
+                continue;
+            }
+
+            // One sender going away shouldn't leave everyone after them
+            // unanswered.
+            if let Err(e) = self.socket.send_to(response, addr) {
+                warn!(%addr, error = ?e, "Failed to answer a discovery probe");
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Sends a probe to `probe_addr`, usually the broadcast address on
+// `DISCOVERY_PORT`, and collects every compatible server that answers before
+// `timeout` runs out. There's no asking which server has a room, as that
+// would tell anyone whether a room code is in use.
+pub fn discover_servers(
+    probe_addr: SocketAddr,
+    timeout: Duration,
+) -> io::Result<Vec<DiscoveredServer>> {
+    let mut probe = PROBE.to_vec();
+    probe.resize(PROBE_SIZE, 0);
+
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&probe, probe_addr)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut servers: Vec<DiscoveredServer> = vec![];
+    let mut buf = [0u8; MAX_RESPONSE_SIZE];
+
+    loop {
+        let time_left = deadline.saturating_duration_since(Instant::now());
+
+        if time_left.is_zero() {
+            return Ok(servers);
+        }
+
+        socket.set_read_timeout(Some(time_left))?;
+
+        let (len, addr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                return Ok(servers);
+            },
+            Err(e) => return Err(e),
+        };
+
+        let info = match bincode_options().deserialize::<ServerInfo>(&buf[..len]) {
+            Ok(info) if SUPPORTED_PROTOCOL.negotiate(&info.protocol).is_some() => info,
+            _ => continue,
+        };
+
+        let addr = SocketAddr::new(addr.ip(), info.game_port);
+
+        if !servers.iter().any(|server| server.addr == addr) {
+            servers.push(DiscoveredServer { addr, info });
+        }
+    }
+}
+
+#[test]
+fn test_discover_on_loopback() {
+    let responder = DiscoveryResponder::bind(([127, 0, 0, 1], 0).into()).unwrap();
+    let probe_addr = responder.local_addr().unwrap();
+
+    let info = ServerInfo {
+        name: "Test server".to_string(),
+        protocol: SUPPORTED_PROTOCOL,
+        game_port: 7600,
+        rooms: vec![RoomInfo { player_count: 3, max_players: 16, game_state: GameState::Lobby }],
+    };
+
+    let discover = || {
+        let client = std::thread::spawn(move || {
+            discover_servers(probe_addr, Duration::from_millis(500)).unwrap()
+        });
+
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            responder.answer_probes(|| info.clone()).unwrap();
+            std::thread::sleep(Duration::from_millis(5));
+        }
+
+        client.join().unwrap()
+    };
+
+    let servers = discover();
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].addr, "127.0.0.1:7600".parse().unwrap());
+    assert_eq!(servers[0].info, info);
+
+    // A probe that isn't padded out goes unanswered.
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    client.send_to(PROBE, probe_addr).unwrap();
+    responder.answer_probes(|| info.clone()).unwrap();
+    assert!(client.recv(&mut [0u8; MAX_RESPONSE_SIZE]).is_err());
+}
+
+#[test]
+fn test_largest_response_fits_in_a_probe() {
+    let mut info = ServerInfo {
+        name: "é".repeat(MAX_SERVER_NAME_LEN),
+        protocol: SUPPORTED_PROTOCOL,
+        game_port: 7600,
+        rooms: vec![
+            RoomInfo { player_count: 0, max_players: 16, game_state: GameState::Lobby };
+            1000
+        ],
+    };
+    info.limit();
+
+    assert!(bincode::serialize(&info).unwrap().len() <= PROBE_SIZE);
+}
@@ -0,0 +1,449 @@
+use crate::{cookie::Cookie, protocol::bincode_options};
+use bincode::Options;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{PublicKey, ReusableSecret, SharedSecret, StaticSecret};
+
+// Every payload laminar carries for us is one of these. The client opens with
+// a `ClientHello` carrying a fresh public key. The server answers the first
+// one with a `Cookie`, without keeping anything, and only answers a hello that
+// brings that cookie back, so a hello sent from someone else's address gets
+// nothing more than a cookie sent to them. The server's `ServerHello` carries
+// a fresh public key of its own and its long-term `ServerKey`. After that
+// everything is encrypted and authenticated with ChaCha20-Poly1305, using a
+// separate key for each direction.
+//
+// The session keys come from both fresh keys and from the server's long-term
+// key, so only whoever holds that key's secret can read or send anything in
+// the session. A client that was given the server's key in advance
+// (`SUS_SERVER_KEY`) knows it's talking to the real server. One that takes
+// whatever key the server sends is only protected from people listening in or
+// tampering after the fact, not from someone in the middle of the exchange.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum WireMessage {
+    ClientHello { public: [u8; 32], cookie: Option<Cookie> },
+    Cookie(Cookie),
+    ServerHello { public: [u8; 32], server_key: [u8; 32] },
+    Sealed { counter: u64, ciphertext: Vec<u8> },
+}
+
+impl WireMessage {
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        bincode_options().deserialize(data).ok()
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("WireMessage serializes")
+    }
+}
+
+// The server's long-term key, kept across restarts so clients can be given its
+// public half to check against.
+pub struct ServerKey {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl ServerKey {
+    pub fn generate() -> Self {
+        Self::from_bytes(StaticSecret::random_from_rng(OsRng).to_bytes())
+    }
+
+    pub fn from_bytes(secret: [u8; 32]) -> Self {
+        let secret = StaticSecret::from(secret);
+        let public = PublicKey::from(&secret);
+
+        Self { secret, public }
+    }
+
+    pub fn secret_bytes(&self) -> [u8; 32] {
+        self.secret.to_bytes()
+    }
+
+    pub fn public(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+}
+
+// Keys are written out as 64 hex digits wherever people have to handle them.
+pub fn key_to_hex(key: &[u8; 32]) -> String {
+    key.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn key_from_hex(hex: &str) -> Option<[u8; 32]> {
+    let hex = hex.trim();
+
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+
+    let mut key = [0u8; 32];
+
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+    }
+
+    Some(key)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Side {
+    Client,
+    Server,
+}
+
+pub struct Handshake {
+    secret: ReusableSecret,
+    public: PublicKey,
+}
+
+impl Default for Handshake {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Handshake {
+    pub fn new() -> Self {
+        let secret = ReusableSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+
+        Self { secret, public }
+    }
+
+    pub fn client_hello(&self, cookie: Option<Cookie>) -> WireMessage {
+        WireMessage::ClientHello { public: self.public.to_bytes(), cookie }
+    }
+
+    pub fn server_hello(&self, server_key: &ServerKey) -> WireMessage {
+        WireMessage::ServerHello { public: self.public.to_bytes(), server_key: server_key.public() }
+    }
+
+    // Returns None if the server sent keys that don't lead to a real shared
+    // secret. Checking `server_key` is the caller's job.
+    pub fn finish_client(self, their_public: [u8; 32], server_key: [u8; 32]) -> Option<Session> {
+        let static_secret = self.secret.diffie_hellman(&PublicKey::from(server_key));
+        self.finish(Side::Client, their_public, static_secret, server_key)
+    }
+
+    // Returns None if the client sent a key that doesn't lead to a real shared
+    // secret.
+    pub fn finish_server(self, their_public: [u8; 32], server_key: &ServerKey) -> Option<Session> {
+        let static_secret = server_key.secret.diffie_hellman(&PublicKey::from(their_public));
+        self.finish(Side::Server, their_public, static_secret, server_key.public())
+    }
+
+    fn finish(
+        self,
+        side: Side,
+        their_public: [u8; 32],
+        static_secret: SharedSecret,
+        server_key: [u8; 32],
+    ) -> Option<Session> {
+        let our_public = self.public.to_bytes();
+        let ephemeral_secret = self.secret.diffie_hellman(&PublicKey::from(their_public));
+
+        if !ephemeral_secret.was_contributory() || !static_secret.was_contributory() {
+            return None;
+        }
+
+        let (client_public, server_public) = match side {
+            Side::Client => (our_public, their_public),
+            Side::Server => (their_public, our_public),
+        };
+
+        // All three public keys go into the derivation so the keys are tied to
+        // this exact exchange.
+        let mut info = b"sus transport v2".to_vec();
+        info.extend_from_slice(&client_public);
+        info.extend_from_slice(&server_public);
+        info.extend_from_slice(&server_key);
+
+        let mut shared_secrets = ephemeral_secret.as_bytes().to_vec();
+        shared_secrets.extend_from_slice(static_secret.as_bytes());
+
+        let mut keys = [0u8; 64];
+        Hkdf::<Sha256>::new(None, &shared_secrets)
+            .expand(&info, &mut keys)
+            .expect("64 bytes is a valid HKDF output length");
+
+        let client_to_server = ChaCha20Poly1305::new(Key::from_slice(&keys[..32]));
+        let server_to_client = ChaCha20Poly1305::new(Key::from_slice(&keys[32..]));
+
+        let (send, recv) = match side {
+            Side::Client => (client_to_server, server_to_client),
+            Side::Server => (server_to_client, client_to_server),
+        };
+
+        Some(Session {
+            send,
+            recv,
+            unreliable_counter: 0,
+            reliable_counter: RELIABLE_COUNTERS,
+            unreliable_window: ReplayWindow::default(),
+            reliable_window: ReplayWindow::default(),
+        })
+    }
+}
+
+// Reliable packets are counted separately from the rest, in the top half of
+// the counters. Laminar resends a reliable packet as it was first sealed, and
+// by then plenty of unreliable ones may have gone out after it. The bit is part
+// of the nonce, so moving a packet between the two fails to decrypt.
+const RELIABLE_COUNTERS: u64 = 1 << 63;
+
+// A resend can turn up long after the packets sealed around it, so reliable
+// packets get a much bigger window than the ones nobody resends.
+const UNRELIABLE_WINDOW_WORDS: usize = 1;
+const RELIABLE_WINDOW_WORDS: usize = 64;
+
+pub struct Session {
+    send: ChaCha20Poly1305,
+    recv: ChaCha20Poly1305,
+    unreliable_counter: u64,
+    reliable_counter: u64,
+    unreliable_window: ReplayWindow<UNRELIABLE_WINDOW_WORDS>,
+    reliable_window: ReplayWindow<RELIABLE_WINDOW_WORDS>,
+}
+
+impl Session {
+    // `reliable` is whether laminar will resend this if it's lost.
+    pub fn seal(&mut self, reliable: bool, plaintext: &[u8]) -> Vec<u8> {
+        let send_counter =
+            if reliable { &mut self.reliable_counter } else { &mut self.unreliable_counter };
+        let counter = *send_counter;
+        *send_counter += 1;
+
+        let ciphertext = self
+            .send
+            .encrypt(&nonce(counter), plaintext)
+            .expect("Encrypting into a Vec can't fail");
+
+        WireMessage::Sealed { counter, ciphertext }.encode()
+    }
+
+    // Returns None for anything that wasn't sealed by the other end of this
+    // session, including replays of packets we've already accepted.
+    pub fn open(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        let (counter, ciphertext) = match WireMessage::decode(data)? {
+            WireMessage::Sealed { counter, ciphertext } => (counter, ciphertext),
+            _ => return None,
+        };
+
+        let fresh = if counter & RELIABLE_COUNTERS != 0 {
+            self.reliable_window.is_fresh(counter)
+        } else {
+            self.unreliable_window.is_fresh(counter)
+        };
+
+        if !fresh {
+            return None;
+        }
+
+        let plaintext = self.recv.decrypt(&nonce(counter), ciphertext.as_slice()).ok()?;
+
+        if counter & RELIABLE_COUNTERS != 0 {
+            self.reliable_window.mark(counter);
+        } else {
+            self.unreliable_window.mark(counter);
+        }
+
+        Some(plaintext)
+    }
+}
+
+fn nonce(counter: u64) -> Nonce {
+    let mut nonce = [0u8; 12];
+    nonce[4..].copy_from_slice(&counter.to_le_bytes());
+
+    *Nonce::from_slice(&nonce)
+}
+
+// Packets can arrive out of order, so rather than only accepting counters
+// above the highest one seen, we remember which of the last `64 * WORDS` have
+// been used.
+#[derive(Debug)]
+struct ReplayWindow<const WORDS: usize> {
+    highest: Option<u64>,
+    // Bit `counter % SIZE` is set if `counter` has been accepted, for the
+    // counters still inside the window.
+    seen: [u64; WORDS],
+}
+
+impl<const WORDS: usize> Default for ReplayWindow<WORDS> {
+    fn default() -> Self {
+        Self { highest: None, seen: [0; WORDS] }
+    }
+}
+
+impl<const WORDS: usize> ReplayWindow<WORDS> {
+    const SIZE: u64 = 64 * WORDS as u64;
+
+    fn is_fresh(&self, counter: u64) -> bool {
+        let highest = match self.highest {
+            Some(highest) => highest,
+            None => return true,
+        };
+
+        if counter > highest {
+            return true;
+        }
+
+        highest - counter < Self::SIZE && !self.is_seen(counter)
+    }
+
+    fn mark(&mut self, counter: u64) {
+        match self.highest {
+            Some(highest) if counter <= highest => {},
+            Some(highest) => {
+                // The counters that just fell out of the window shared bits
+                // with the new ones.
+                for newer in (highest + 1..=counter).take(Self::SIZE as usize) {
+                    self.set_seen(newer, false);
+                }
+
+                self.highest = Some(counter);
+            },
+            None => self.highest = Some(counter),
+        }
+
+        self.set_seen(counter, true);
+    }
+
+    fn is_seen(&self, counter: u64) -> bool {
+        let bit = counter % Self::SIZE;
+        self.seen[(bit / 64) as usize] & (1 << (bit % 64)) != 0
+    }
+
+    fn set_seen(&mut self, counter: u64, seen: bool) {
+        let bit = counter % Self::SIZE;
+        let word = &mut self.seen[(bit / 64) as usize];
+
+        if seen {
+            *word |= 1 << (bit % 64);
+        } else {
+            *word &= !(1 << (bit % 64));
+        }
+    }
+}
+
+#[cfg(test)]
+fn test_sessions() -> (Session, Session) {
+    let server_key = ServerKey::generate();
+    let client = Handshake::new();
+    let server = Handshake::new();
+
+    let client_public = match client.client_hello(None) {
+        WireMessage::ClientHello { public, .. } => public,
+        _ => unreachable!(),
+    };
+    let (server_public, public_server_key) = match server.server_hello(&server_key) {
+        WireMessage::ServerHello { public, server_key } => (public, server_key),
+        _ => unreachable!(),
+    };
+
+    (
+        client.finish_client(server_public, public_server_key).unwrap(),
+        server.finish_server(client_public, &server_key).unwrap(),
+    )
+}
+
+#[test]
+fn test_handshake_and_seal() {
+    let (mut client, mut server) = test_sessions();
+
+    let first = client.seal(false, b"hello");
+    let second = client.seal(false, b"world");
+
+    // Out of order is fine, replays and tampering aren't.
+    assert_eq!(server.open(&second), Some(b"world".to_vec()));
+    assert_eq!(server.open(&first), Some(b"hello".to_vec()));
+    assert_eq!(server.open(&first), None);
+
+    let mut tampered = client.seal(false, b"move left");
+    let last = tampered.len() - 1;
+    tampered[last] ^= 1;
+    assert_eq!(server.open(&tampered), None);
+
+    // Each direction has its own key, so a client packet can't be reflected
+    // back at the client.
+    let reflected = client.seal(false, b"ping");
+    assert_eq!(client.open(&reflected), None);
+
+    let reply = server.seal(false, b"pong");
+    assert_eq!(client.open(&reply), Some(b"pong".to_vec()));
+
+    // A low-order point would make the shared secret all zeroes.
+    assert!(Handshake::new().finish_server([0u8; 32], &ServerKey::generate()).is_none());
+    assert!(Handshake::new().finish_client(ServerKey::generate().public(), [0u8; 32]).is_none());
+}
+
+#[test]
+fn test_only_the_server_key_holder_can_finish() {
+    // Someone in the middle passes on the real server's key, but has to answer
+    // with a secret of their own.
+    let real_key = ServerKey::generate();
+    let client = Handshake::new();
+    let client_public = match client.client_hello(None) {
+        WireMessage::ClientHello { public, .. } => public,
+        _ => unreachable!(),
+    };
+
+    let impostor = Handshake::new();
+    let impostor_public = match impostor.server_hello(&real_key) {
+        WireMessage::ServerHello { public, .. } => public,
+        _ => unreachable!(),
+    };
+
+    let mut client = client.finish_client(impostor_public, real_key.public()).unwrap();
+    let mut impostor = impostor.finish_server(client_public, &ServerKey::generate()).unwrap();
+
+    assert_eq!(impostor.open(&client.seal(false, b"hello")), None);
+    assert_eq!(client.open(&impostor.seal(false, b"hello")), None);
+
+    // And keys survive being written out.
+    let hex = key_to_hex(&real_key.public());
+    assert_eq!(key_from_hex(&hex), Some(real_key.public()));
+    assert_eq!(key_from_hex(&hex[1..]), None);
+    assert_eq!(key_from_hex(&"é".repeat(32)), None);
+}
+
+#[test]
+fn test_late_resends_are_accepted() {
+    let (mut client, mut server) = test_sessions();
+
+    // The first go at a reliable packet is lost, and a few seconds of inputs
+    // and other reliable packets get through before laminar resends it.
+    let lost = client.seal(true, b"ready");
+    let late_unreliable = client.seal(false, b"old input");
+
+    for _ in 0..1000 {
+        assert!(server.open(&client.seal(false, b"input")).is_some());
+    }
+
+    for _ in 0..100 {
+        assert!(server.open(&client.seal(true, b"chat")).is_some());
+    }
+
+    assert_eq!(server.open(&lost), Some(b"ready".to_vec()));
+    assert_eq!(server.open(&lost), None);
+
+    // Nobody resends unreliable packets, so one this old is only a replay.
+    assert_eq!(server.open(&late_unreliable), None);
+
+    // Moving a packet between the two counters breaks it.
+    let moved = match WireMessage::decode(&client.seal(false, b"input")).unwrap() {
+        WireMessage::Sealed { counter, ciphertext } => {
+            WireMessage::Sealed { counter: counter | RELIABLE_COUNTERS, ciphertext }
+        },
+        _ => unreachable!(),
+    }
+    .encode();
+    assert_eq!(server.open(&moved), None);
+}
@@ -1,15 +1,28 @@
 use crate::network::PlayerInputPacket;
-use simple_game::bevy::{bevy_ecs, Resource, States};
+use serde::{Deserialize, Serialize};
+use simple_game::bevy::{bevy_ecs, Component, Resource, States};
 
 pub mod components;
+pub mod cookie;
+pub mod discovery;
+pub mod encryption;
+pub mod logging;
+pub mod map;
+pub mod master_server;
 pub mod math;
+pub mod movement;
 pub mod network;
+pub mod protocol;
 pub mod resources;
+pub mod settings;
+pub mod vision;
 
+pub use crossbeam_channel;
 pub use laminar;
 pub use simple_game;
+pub use tracing;
 
-#[derive(States, Default, Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(States, Default, Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub enum GameState {
     #[default]
     Lobby,
@@ -18,11 +31,13 @@ pub enum GameState {
     End,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Component, Serialize, Deserialize)]
 pub enum PlayerType {
     Crew,
     Impostor,
 }
 
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Component, Serialize, Deserialize)]
 pub enum PlayerState {
     Alive,
     Dead,
@@ -0,0 +1,38 @@
+use tracing_subscriber::{fmt, prelude::*, EnvFilter};
+
+// Everything logs through `tracing`, to stderr so it stays out of the way of
+// anything a binary prints for its user. What gets logged is set with SUS_LOG,
+// which takes the same directives as RUST_LOG, like
+// "info,sus_server::systems::movement=trace". Each binary has its own default
+// for when it isn't set.
+pub const LOG_FILTER_VAR: &str = "SUS_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    // A line per event, with the fields of the spans it's in.
+    Text,
+    // A JSON object per event, with its fields at the top level and the spans
+    // it's in listed under "spans", for grepping and feeding to other tools.
+    Json,
+}
+
+pub fn init_logging(default_filter: &str, format: LogFormat) {
+    let filter =
+        EnvFilter::try_from_env(LOG_FILTER_VAR).unwrap_or_else(|_| EnvFilter::new(default_filter));
+    let registry = tracing_subscriber::registry().with(filter);
+
+    // Something else may have set up logging first, in which case it stays.
+    let _ = match format {
+        LogFormat::Text => registry.with(fmt::layer().with_writer(std::io::stderr)).try_init(),
+        LogFormat::Json => registry
+            .with(
+                fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(false)
+                    .with_span_list(true)
+                    .with_writer(std::io::stderr),
+            )
+            .try_init(),
+    };
+}
@@ -0,0 +1,237 @@
+use crate::{math::segments_intersect, network::ConsoleKind, resources::ClosedDoors};
+use simple_game::{
+    bevy::{bevy_ecs, Resource},
+    glam::{vec2, Vec2},
+};
+
+// How close a player needs to be to a task or console to use it.
+pub const INTERACT_DISTANCE: f32 = 5.0;
+
+// How far a security camera can see.
+pub const CAMERA_RADIUS: f32 = 20.0;
+
+// A straight wall segment which blocks vision.
+#[derive(Debug, Clone, Copy)]
+pub struct Wall {
+    pub start: Vec2,
+    pub end: Vec2,
+}
+
+impl Wall {
+    pub fn new(start: (f32, f32), end: (f32, f32)) -> Self {
+        Self { start: start.into(), end: end.into() }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct TaskStation {
+    pub name: &'static str,
+    pub pos: Vec2,
+}
+
+// A door blocks movement and vision while its group is closed. Impostors
+// close all the doors in a group at once.
+#[derive(Debug, Clone, Copy)]
+pub struct Door {
+    pub start: Vec2,
+    pub end: Vec2,
+    pub group: u8,
+}
+
+// Impostors can travel between vents that are linked together.
+#[derive(Debug, Clone)]
+pub struct Vent {
+    pub pos: Vec2,
+    pub links: Vec<u8>,
+}
+
+// An axis aligned area of the map, used by the admin table to count players.
+#[derive(Debug, Clone, Copy)]
+pub struct Room {
+    pub name: &'static str,
+    pub min: Vec2,
+    pub max: Vec2,
+}
+
+impl Room {
+    pub fn contains(&self, pos: Vec2) -> bool {
+        pos.cmpge(self.min).all() && pos.cmplt(self.max).all()
+    }
+}
+
+// The static layout of the level. Both the server and the client build this
+// from code, so nothing here goes over the network.
+#[derive(Debug, Clone, Resource)]
+pub struct Map {
+    pub walls: Vec<Wall>,
+    pub tasks: Vec<TaskStation>,
+    pub lights_panel: Vec2,
+    pub comms_panel: Vec2,
+    pub reactor_stations: [Vec2; 2],
+    pub oxygen_stations: [Vec2; 2],
+    pub vents: Vec<Vent>,
+    pub doors: Vec<Door>,
+    pub rooms: Vec<Room>,
+    pub cameras: Vec<Vec2>,
+    pub security_panel: Vec2,
+    pub admin_table: Vec2,
+    pub vitals_panel: Vec2,
+}
+
+impl Map {
+    // Returns true if no wall or closed door blocks the straight line between
+    // `from` and `to`.
+    pub fn line_of_sight(&self, closed_doors: &ClosedDoors, from: Vec2, to: Vec2) -> bool {
+        !self
+            .blocking_segments(closed_doors)
+            .any(|(start, end)| segments_intersect(from, to, start, end))
+    }
+
+    // All the walls, plus any doors which are currently closed.
+    pub fn blocking_segments<'a>(
+        &'a self,
+        closed_doors: &'a ClosedDoors,
+    ) -> impl Iterator<Item = (Vec2, Vec2)> + 'a {
+        let walls = self.walls.iter().map(|wall| (wall.start, wall.end));
+        let doors = self
+            .doors
+            .iter()
+            .filter(move |door| closed_doors.is_closed(door.group))
+            .map(|door| (door.start, door.end));
+
+        walls.chain(doors)
+    }
+
+    pub fn console_pos(&self, kind: ConsoleKind) -> Vec2 {
+        match kind {
+            ConsoleKind::Cameras => self.security_panel,
+            ConsoleKind::Admin => self.admin_table,
+            ConsoleKind::Vitals => self.vitals_panel,
+        }
+    }
+
+    pub fn room_at(&self, pos: Vec2) -> Option<usize> {
+        self.rooms.iter().position(|room| room.contains(pos))
+    }
+
+    pub fn door_group_count(&self) -> usize {
+        self.doors.iter().map(|door| door.group as usize + 1).max().unwrap_or(0)
+    }
+}
+
+pub fn within_interact_distance(player: Vec2, target: Vec2) -> bool {
+    player.distance_squared(target) <= INTERACT_DISTANCE * INTERACT_DISTANCE
+}
+
+impl Default for Map {
+    // A small test level with three rooms side by side and a storage room to
+    // the north of the middle one. From west to east the rooms are the reactor,
+    // the cafeteria (with storage above it), and electrical. Players spawn at the
+    // origin.
+    fn default() -> Self {
+        let walls = vec![
+            // Outer walls
+            Wall::new((-60.0, -40.0), (60.0, -40.0)),
+            Wall::new((60.0, -40.0), (60.0, 40.0)),
+            Wall::new((60.0, 40.0), (-60.0, 40.0)),
+            Wall::new((-60.0, 40.0), (-60.0, -40.0)),
+            // West room, with a doorway at y = 0
+            Wall::new((-20.0, -40.0), (-20.0, -5.0)),
+            Wall::new((-20.0, 5.0), (-20.0, 40.0)),
+            // East room, with a doorway at y = 0
+            Wall::new((20.0, -40.0), (20.0, -5.0)),
+            Wall::new((20.0, 5.0), (20.0, 40.0)),
+            // Storage, with a doorway at x = 0
+            Wall::new((-20.0, 15.0), (-5.0, 15.0)),
+            Wall::new((5.0, 15.0), (20.0, 15.0)),
+        ];
+
+        let tasks = vec![
+            TaskStation { name: "Align engine output", pos: vec2(-40.0, -35.0) },
+            TaskStation { name: "Fuel engines", pos: vec2(-40.0, 35.0) },
+            TaskStation { name: "Download data", pos: vec2(-15.0, -35.0) },
+            TaskStation { name: "Swipe card", pos: vec2(15.0, -35.0) },
+            TaskStation { name: "Empty garbage", pos: vec2(0.0, 35.0) },
+            TaskStation { name: "Fix wiring", pos: vec2(50.0, -35.0) },
+            TaskStation { name: "Calibrate distributor", pos: vec2(50.0, 35.0) },
+        ];
+
+        let vents = vec![
+            Vent { pos: vec2(-50.0, 0.0), links: vec![1] },
+            Vent { pos: vec2(-10.0, 25.0), links: vec![0, 3] },
+            Vent { pos: vec2(50.0, -10.0), links: vec![3] },
+            Vent { pos: vec2(10.0, -20.0), links: vec![1, 2] },
+        ];
+
+        // One door group per doorway: reactor, electrical, then storage.
+        let doors = vec![
+            Door { start: vec2(-20.0, -5.0), end: vec2(-20.0, 5.0), group: 0 },
+            Door { start: vec2(20.0, -5.0), end: vec2(20.0, 5.0), group: 1 },
+            Door { start: vec2(-5.0, 15.0), end: vec2(5.0, 15.0), group: 2 },
+        ];
+
+        let rooms = vec![
+            Room { name: "Reactor", min: vec2(-60.0, -40.0), max: vec2(-20.0, 40.0) },
+            Room { name: "Cafeteria", min: vec2(-20.0, -40.0), max: vec2(20.0, 15.0) },
+            Room { name: "Storage", min: vec2(-20.0, 15.0), max: vec2(20.0, 40.0) },
+            Room { name: "Electrical", min: vec2(20.0, -40.0), max: vec2(60.0, 40.0) },
+        ];
+
+        // Each camera watches one of the doorways out of the cafeteria.
+        let cameras = vec![vec2(-30.0, 0.0), vec2(30.0, 0.0), vec2(0.0, 25.0)];
+
+        Self {
+            walls,
+            tasks,
+            lights_panel: vec2(55.0, 0.0),
+            comms_panel: vec2(15.0, 35.0),
+            reactor_stations: [vec2(-55.0, -30.0), vec2(-55.0, 30.0)],
+            oxygen_stations: [vec2(-15.0, 35.0), vec2(0.0, -35.0)],
+            vents,
+            doors,
+            rooms,
+            cameras,
+            security_panel: vec2(-15.0, -15.0),
+            admin_table: vec2(0.0, -10.0),
+            vitals_panel: vec2(40.0, 20.0),
+        }
+    }
+}
+
+#[test]
+fn test_line_of_sight() {
+    let map = Map::default();
+    let open_doors = ClosedDoors::default();
+
+    // Straight through the west doorway.
+    assert!(map.line_of_sight(&open_doors, vec2(0.0, 0.0), vec2(-30.0, 0.0)));
+
+    // Through the wall between the middle and west rooms.
+    assert!(!map.line_of_sight(&open_doors, vec2(0.0, -20.0), vec2(-30.0, -20.0)));
+
+    // The west doorway with its door closed.
+    let closed_doors = ClosedDoors(vec![true, false, false]);
+    assert!(!map.line_of_sight(&closed_doors, vec2(0.0, 0.0), vec2(-30.0, 0.0)));
+}
+
+#[test]
+fn test_vent_links_are_symmetric() {
+    let map = Map::default();
+
+    for (index, vent) in map.vents.iter().enumerate() {
+        for link in &vent.links {
+            assert!(map.vents[*link as usize].links.contains(&(index as u8)));
+        }
+    }
+}
+
+#[test]
+fn test_rooms() {
+    let map = Map::default();
+
+    assert_eq!(map.room_at(vec2(0.0, 0.0)), Some(1));
+    assert_eq!(map.room_at(vec2(0.0, 20.0)), Some(2));
+    assert_eq!(map.room_at(vec2(-40.0, 0.0)), Some(0));
+    assert_eq!(map.room_at(vec2(40.0, 0.0)), Some(3));
+    assert_eq!(map.room_at(vec2(100.0, 0.0)), None);
+}
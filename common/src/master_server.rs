@@ -0,0 +1,195 @@
+use crate::{
+    cookie::Cookie,
+    discovery::{DiscoveredServer, ServerInfo},
+    protocol::{bincode_options, ProtocolVersions},
+};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+pub const MASTER_SERVER_PORT: u16 = 7602;
+
+// Game servers send a heartbeat this often, and the master server forgets
+// about them once it hasn't heard one for `HEARTBEAT_TIMEOUT`.
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(5);
+pub const HEARTBEAT_TIMEOUT: Duration = Duration::from_secs(15);
+
+// Heartbeats can be this big, with every room code a server has.
+pub const MAX_MESSAGE_SIZE: usize = 65_507;
+
+// Anyone can ask for the server list from any address, including one they're
+// pretending to have. So no list reply is ever bigger than this, and a query
+// has to be padded out to at least this size to get one, which means the
+// master server never sends more than it was sent. Longer lists come back a
+// page at a time. The test below checks the biggest server still fits.
+pub const LIST_PACKET_SIZE: usize = 1200;
+
+// How many servers are listed at most, across every page.
+pub const MAX_LISTED_SERVERS: usize = 64;
+
+// How many of a server's room codes the master server keeps to look it up by.
+pub const MAX_ROOM_CODES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ServerQuery {
+    // Only servers sharing a protocol version with this range are listed.
+    pub protocol: ProtocolVersions,
+    pub joinable_only: bool,
+    // Only the server running this room is listed.
+    pub room_code: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum ToMasterServer {
+    // The server's address is taken from where the heartbeat came from, and
+    // the cookie shows it really is there. Without a cookie the master server
+    // answers with a `Challenge`, and the next heartbeat carries the cookie
+    // from that. Room codes are never listed, only used to find the server
+    // running a room.
+    Heartbeat { info: ServerInfo, room_codes: Vec<String>, cookie: Cookie },
+    // Asks for the servers from `offset` onwards. See `LIST_PACKET_SIZE`.
+    ListServers { query: ServerQuery, offset: u16 },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FromMasterServer {
+    // `next` is the offset to ask for the next page with, if there is one.
+    ServerList { servers: Vec<DiscoveredServer>, next: Option<u16> },
+    Challenge(Cookie),
+}
+
+pub fn send_heartbeat(
+    socket: &UdpSocket,
+    master_addr: SocketAddr,
+    info: ServerInfo,
+    room_codes: Vec<String>,
+    cookie: Cookie,
+) -> io::Result<()> {
+    let data = bincode::serialize(&ToMasterServer::Heartbeat { info, room_codes, cookie })
+        .expect("Heartbeat serializes");
+    socket.send_to(&data, master_addr)?;
+
+    Ok(())
+}
+
+// Returns the latest challenge the master server has sent to `socket`, out of
+// everything received on it without waiting.
+pub fn receive_challenge(
+    socket: &UdpSocket,
+    master_addr: SocketAddr,
+) -> io::Result<Option<Cookie>> {
+    let mut buf = [0u8; 64];
+    let mut challenge = None;
+
+    loop {
+        let (len, addr) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                return Ok(challenge);
+            },
+            Err(e) => return Err(e),
+        };
+
+        if addr != master_addr {
+            continue;
+        }
+
+        if let Ok(FromMasterServer::Challenge(cookie)) = bincode_options().deserialize(&buf[..len])
+        {
+            challenge = Some(cookie);
+        }
+    }
+}
+
+// Asks the master server for its list of servers, a page at a time, waiting up
+// to `timeout` for all of it.
+pub fn list_servers(
+    master_addr: SocketAddr,
+    query: ServerQuery,
+    timeout: Duration,
+) -> io::Result<Vec<DiscoveredServer>> {
+    let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+    socket.connect(master_addr)?;
+
+    let deadline = Instant::now() + timeout;
+    let mut servers = vec![];
+    let mut offset = 0;
+    let mut buf = vec![0u8; LIST_PACKET_SIZE];
+
+    while servers.len() < MAX_LISTED_SERVERS {
+        let list_servers = ToMasterServer::ListServers { query: query.clone(), offset };
+        let mut data = bincode::serialize(&list_servers).expect("Query serializes");
+
+        if data.len() < LIST_PACKET_SIZE {
+            data.resize(LIST_PACKET_SIZE, 0);
+        }
+
+        socket.send(&data)?;
+
+        let time_left = deadline.saturating_duration_since(Instant::now());
+
+        if time_left.is_zero() {
+            return Err(io::ErrorKind::TimedOut.into());
+        }
+
+        socket.set_read_timeout(Some(time_left))?;
+        let len = socket.recv(&mut buf)?;
+
+        match bincode_options().deserialize::<FromMasterServer>(&buf[..len]) {
+            Ok(FromMasterServer::ServerList { servers: page, next }) => {
+                // The list can change between pages.
+                for server in page {
+                    if !servers.iter().any(|listed: &DiscoveredServer| listed.addr == server.addr) {
+                        servers.push(server);
+                    }
+                }
+
+                match next {
+                    Some(next) => offset = next,
+                    None => break,
+                }
+            },
+            Ok(FromMasterServer::Challenge(_)) => {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "Unexpected challenge"));
+            },
+            Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    servers.truncate(MAX_LISTED_SERVERS);
+
+    Ok(servers)
+}
+
+#[test]
+fn test_largest_reply_fits() {
+    use crate::{
+        discovery::{RoomInfo, MAX_LISTED_ROOMS, MAX_SERVER_NAME_LEN},
+        protocol::SUPPORTED_PROTOCOL,
+        GameState,
+    };
+
+    let mut info = ServerInfo {
+        name: "é".repeat(MAX_SERVER_NAME_LEN),
+        protocol: SUPPORTED_PROTOCOL,
+        game_port: 7600,
+        rooms: vec![
+            RoomInfo { player_count: 0, max_players: 16, game_state: GameState::Lobby };
+            1000
+        ],
+    };
+    info.limit();
+
+    assert_eq!(info.name.len(), MAX_SERVER_NAME_LEN);
+    assert_eq!(info.rooms.len(), MAX_LISTED_ROOMS);
+
+    // A page has room for a few of them.
+    let server = DiscoveredServer { addr: "[2001:db8::1]:7600".parse().unwrap(), info };
+    let reply = FromMasterServer::ServerList { servers: vec![server; 4], next: Some(u16::MAX) };
+
+    assert!(bincode::serialize(&reply).unwrap().len() <= LIST_PACKET_SIZE);
+}
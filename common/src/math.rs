@@ -1,3 +1,5 @@
+use simple_game::glam::Vec2;
+
 pub trait NormalizedInt {
     fn normalized(&self) -> f32;
 }
@@ -21,3 +23,42 @@ impl NormalizedInt for i8 {
         }
     }
 }
+
+// Returns true if the segment from `a_start` to `a_end` crosses the segment
+// from `b_start` to `b_end`. Parallel segments are treated as not crossing.
+pub fn segments_intersect(a_start: Vec2, a_end: Vec2, b_start: Vec2, b_end: Vec2) -> bool {
+    let a = a_end - a_start;
+    let b = b_end - b_start;
+    let denominator = a.perp_dot(b);
+
+    if denominator == 0.0 {
+        return false;
+    }
+
+    let offset = b_start - a_start;
+    let t = offset.perp_dot(b) / denominator;
+    let u = offset.perp_dot(a) / denominator;
+
+    (0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&u)
+}
+
+pub fn distance_to_segment(point: Vec2, start: Vec2, end: Vec2) -> f32 {
+    let segment = end - start;
+    let length_squared = segment.length_squared();
+
+    if length_squared == 0.0 {
+        return point.distance(start);
+    }
+
+    let t = ((point - start).dot(segment) / length_squared).clamp(0.0, 1.0);
+    point.distance(start + segment * t)
+}
+
+#[test]
+fn test_segments_intersect() {
+    use simple_game::glam::vec2;
+
+    assert!(segments_intersect(vec2(-1.0, 0.0), vec2(1.0, 0.0), vec2(0.0, -1.0), vec2(0.0, 1.0)));
+    assert!(!segments_intersect(vec2(-1.0, 0.0), vec2(1.0, 0.0), vec2(2.0, -1.0), vec2(2.0, 1.0)));
+    assert!(!segments_intersect(vec2(0.0, 0.0), vec2(1.0, 0.0), vec2(0.0, 1.0), vec2(1.0, 1.0)));
+}
@@ -0,0 +1,89 @@
+use crate::{
+    map::Map,
+    math::{distance_to_segment, NormalizedInt},
+    resources::ClosedDoors,
+};
+use simple_game::glam::{vec2, Vec2};
+
+// Default distance moved per fixed update at full input.
+pub const PLAYER_SPEED: f32 = 0.1;
+pub const PLAYER_RADIUS: f32 = 1.5;
+
+// Moves a player one fixed update in the direction of their input, sliding
+// along walls and closed doors. The server and client prediction both use
+// this so they agree on where a player ends up.
+pub fn move_player(
+    map: &Map,
+    closed_doors: &ClosedDoors,
+    speed: f32,
+    pos: Vec2,
+    x: i16,
+    y: i16,
+) -> Vec2 {
+    let velocity = vec2(x.normalized(), y.normalized()) * speed;
+
+    // Only block moves which push further into something, so a player caught
+    // in a doorway when the door closes can still walk out.
+    let blocked = |from: Vec2, to: Vec2| {
+        map.blocking_segments(closed_doors).any(|(start, end)| {
+            let distance = distance_to_segment(to, start, end);
+            distance < PLAYER_RADIUS && distance < distance_to_segment(from, start, end)
+        })
+    };
+
+    let mut new_pos = pos;
+
+    let moved_x = vec2(new_pos.x + velocity.x, new_pos.y);
+    if !blocked(new_pos, moved_x) {
+        new_pos = moved_x;
+    }
+
+    let moved_y = vec2(new_pos.x, new_pos.y + velocity.y);
+    if !blocked(new_pos, moved_y) {
+        new_pos = moved_y;
+    }
+
+    new_pos
+}
+
+// Ghosts float straight through walls and doors.
+pub fn move_ghost(speed: f32, pos: Vec2, x: i16, y: i16) -> Vec2 {
+    pos + vec2(x.normalized(), y.normalized()) * speed
+}
+
+#[test]
+fn test_walls_block_movement() {
+    let map = Map::default();
+    let mut closed_doors = ClosedDoors::default();
+
+    // Walk left into the wall between the cafeteria and the reactor.
+    let mut pos = vec2(-15.0, -20.0);
+    for _ in 0..200 {
+        pos = move_player(&map, &closed_doors, PLAYER_SPEED, pos, i16::MIN, 0);
+    }
+    assert!(pos.x > -20.0 + PLAYER_RADIUS - PLAYER_SPEED);
+
+    // Walk left through the open reactor doorway.
+    let mut pos = vec2(-15.0, 0.0);
+    for _ in 0..200 {
+        pos = move_player(&map, &closed_doors, PLAYER_SPEED, pos, i16::MIN, 0);
+    }
+    assert!(pos.x < -20.0);
+
+    // And again with the reactor doors closed.
+    closed_doors.0 = vec![true];
+    let mut pos = vec2(-15.0, 0.0);
+    for _ in 0..200 {
+        pos = move_player(&map, &closed_doors, PLAYER_SPEED, pos, i16::MIN, 0);
+    }
+    assert!(pos.x > -20.0);
+
+    // Ghosts walk straight through the closed doors and the wall beside them.
+    for y in [0.0, -20.0] {
+        let mut pos = vec2(-15.0, y);
+        for _ in 0..200 {
+            pos = move_ghost(PLAYER_SPEED, pos, i16::MIN, 0);
+        }
+        assert!(pos.x < -20.0);
+    }
+}
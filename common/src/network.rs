@@ -1,13 +1,31 @@
-use laminar::Packet;
+use crate::{
+    protocol::{Capabilities, DecodeError, ProtocolVersions, SUPPORTED_PROTOCOL},
+    settings::GameSettings,
+    GameState, PlayerState, PlayerType,
+};
+use laminar::{DeliveryGuarantee, OrderingGuarantee, Packet};
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
 
-pub const GAME_VERSION: u32 = 0;
 pub const INPUT_STREAM: u8 = 0;
 pub const GAME_STATE_STREAM: u8 = 1;
 pub const CHAT_STREAM: u8 = 2;
 pub const VOICE_STREAM: u8 = 3;
 
+pub const LIGHT_SWITCH_COUNT: usize = 5;
+pub const MAX_CHAT_MESSAGE_LEN: usize = 200;
+
+// Limits on what a single message can hold. Anything over these is dropped
+// when it's decoded.
+pub const MAX_PLAYERS: usize = 16;
+pub const MAX_NAME_LEN: usize = 32;
+pub const MAX_ROOM_CODE_LEN: usize = 16;
+// Movement is simulated at 60Hz and sent at 10Hz, so this leaves plenty of room
+// for a slow server tick.
+pub const MAX_POSITION_HISTORY: usize = 64;
+// Tasks, door groups and rooms on the map.
+pub const MAX_MAP_ITEMS: usize = 64;
+
 #[allow(unused)]
 #[derive(Debug, Copy, Clone)]
 pub enum DeliveryType {
@@ -18,23 +36,101 @@ pub enum DeliveryType {
     UnreliableSequenced,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Sent with `encode`/`decode`, never with bincode directly, see `protocol`.
+#[derive(Debug, Clone)]
 pub enum ServerToClient {
     ConnectAck(ConnectAckPacket),
     NewPlayer(NewPlayerPacket),
     FullGameState(FullGameStatePacket),
     LobbyTick(LobbyTickPacket),
+    RoleAssignment(RoleAssignmentPacket),
+    TaskList(TaskListPacket),
+    SabotageStatus(SabotageStatusPacket),
+    GameOver(GameOverPacket),
+    VentStatus(VentStatusPacket),
+    DoorStatus(DoorStatusPacket),
+    PlayerStatus(PlayerStatusPacket),
+    Chat(ChatPacket),
+    ConsoleInfo(ConsoleInfoPacket),
+    Settings(SettingsPacket),
+    LobbyStatus(LobbyStatusPacket),
+    JoinRoomFailed(JoinRoomFailedPacket),
+    SpectateAck(SpectateAckPacket),
+    SpectatorTick(SpectatorTickPacket),
+    SpectatorCount(SpectatorCountPacket),
+    Disconnected(DisconnectedPacket),
+    SystemMessage(SystemMessagePacket),
+    Ping(PingPacket),
+    PlayerLeft(PlayerLeftPacket),
+    ServerShutdown(ServerShutdownPacket),
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug)]
 pub enum ClientToServer {
     Connect(ConnectPacket),
     PlayerInput(PlayerInputPacket),
+    CompleteTask(u8),
+    Sabotage(SabotageKind),
+    ToggleLightSwitch(u8),
+    FixComms,
+    HoldReactor(HoldReactorPacket),
+    EnterOxygenCode(OxygenCodePacket),
+    EnterVent(u8),
+    MoveVent(u8),
+    ExitVent,
+    CloseDoors(u8),
+    Kill(u16),
+    Chat(String),
+    UseConsole(ConsoleKind),
+    LeaveConsole,
+    UpdateSettings(GameSettings),
+    SetReady(bool),
+    StartGame,
+    CancelStart,
+    ConnectAsSpectator(ConnectPacket),
+    AddBot,
+    RemoveBot,
+    Pong(PingPacket),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectAckPacket {
     pub id: u16,
+    pub room_code: String,
+    // What the server picked from the ranges in the `ConnectPacket`.
+    pub protocol_version: u16,
+    pub capabilities: Capabilities,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JoinRoomError {
+    NotFound,
+    Full,
+    // The server and client have no protocol version in common.
+    IncompatibleProtocol,
+    Banned,
+    // Players can only join while the room is in its lobby.
+    InProgress,
+    // The server, or the address asking, already has as many rooms as it's
+    // allowed.
+    TooManyRooms,
+}
+
+// Sent instead of a `ConnectAck` when the requested room can't be joined.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JoinRoomFailedPacket {
+    pub room_code: String,
+    pub error: JoinRoomError,
+}
+
+// Sent instead of a `ConnectAck` to someone who connected as a spectator.
+// Their state stream runs `delay_secs` behind the game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectateAckPacket {
+    pub room_code: String,
+    pub protocol_version: u16,
+    pub capabilities: Capabilities,
+    pub delay_secs: u16,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,6 +145,13 @@ impl NewPlayerPacket {
     }
 }
 
+// A player or bot is gone from the room, whether they left, timed out, or were
+// removed.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct PlayerLeftPacket {
+    pub id: u16,
+}
+
 // Only needed for players joining the lobby, because currently
 // players can't join a session in progress.
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -67,6 +170,8 @@ impl FullGameStatePacket {
 pub struct LobbyTickPacket {
     pub last_input_counter: u16,
     pub players: Vec<LobbyPlayer>,
+    // The room's update count, since protocol version 2.
+    pub tick: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -76,15 +181,226 @@ pub struct LobbyPlayer {
     pub pos_history: Vec<(f32, f32)>,
 }
 
+// What spectators get instead of a `LobbyTick`: everyone, wherever they are on
+// the map, including who the impostors are.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectatorTickPacket {
+    pub game_state: GameState,
+    pub players: Vec<SpectatedPlayer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectatedPlayer {
+    pub id: u16,
+    pub pos: (f32, f32),
+    pub player_type: PlayerType,
+    pub player_state: PlayerState,
+    pub in_vent: bool,
+}
+
+// Broadcast in the lobby to players whose clients have
+// `Capabilities::SPECTATORS`, whenever the number of spectators changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpectatorCountPacket {
+    pub count: u8,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DisconnectReason {
+    Kicked,
+    Banned,
+}
+
+// The last thing the server sends to someone it's dropping.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisconnectedPacket {
+    pub reason: DisconnectReason,
+}
+
+// Sent to everyone, spectators too, when the server is going away. The reason
+// is whatever the operator gave, so is limited like a chat message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServerShutdownPacket {
+    pub reason: String,
+}
+
+// An announcement from whoever runs the server, rather than from a player.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SystemMessagePacket {
+    pub message: String,
+}
+
+// Sent every so often to players whose clients have `Capabilities::PING`,
+// which send the same nonce straight back in a `ClientToServer::Pong`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PingPacket {
+    pub nonce: u16,
+}
+
+// Sent to each player when the intro screen starts. Impostors are told who
+// the other impostors are, crew get an empty list.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoleAssignmentPacket {
+    pub player_type: PlayerType,
+    pub impostors: Vec<u16>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TaskProgress {
+    pub task: u8,
+    pub done: bool,
+}
+
+// `tasks` is None while comms are sabotaged.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskListPacket {
+    pub tasks: Option<Vec<TaskProgress>>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SabotageKind {
+    Lights,
+    Comms,
+    Reactor,
+    Oxygen,
+}
+
+impl SabotageKind {
+    // Critical sabotages win the game for the impostors if they aren't fixed in time.
+    pub fn is_critical(&self) -> bool {
+        matches!(self, SabotageKind::Reactor | SabotageKind::Oxygen)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SabotageStatus {
+    Lights { switches: [bool; LIGHT_SWITCH_COUNT] },
+    Comms,
+    Reactor { time_left_secs: f32, stations_held: [bool; 2] },
+    Oxygen { time_left_secs: f32, code: u16, stations_fixed: [bool; 2] },
+}
+
+// Broadcast whenever a sabotage starts, progresses, or gets fixed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SabotageStatusPacket {
+    pub sabotage: Option<SabotageStatus>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GameOverPacket {
+    pub winner: PlayerType,
+}
+
+// Sent to an impostor when they enter, move between, or leave vents.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VentStatusPacket {
+    pub vent: Option<u8>,
+}
+
+// Broadcast whenever a door group opens or closes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DoorStatusPacket {
+    pub closed_groups: Vec<bool>,
+}
+
+// Broadcast whenever the settings or the host change, and sent to each new
+// player when they join.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SettingsPacket {
+    pub settings: GameSettings,
+    pub host: Option<u16>,
+}
+
+// Broadcast in the lobby whenever someone readies up, and every second while
+// the game is counting down to start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LobbyStatusPacket {
+    pub ready: Vec<u16>,
+    pub countdown_secs: Option<u8>,
+}
+
+// Sent to a player whenever they die, or come back to life for a new game.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerStatusPacket {
+    pub player_state: PlayerState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChatPacket {
+    pub id: u16,
+    pub message: String,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ConsoleKind {
+    Cameras,
+    Admin,
+    Vitals,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CameraPlayer {
+    pub id: u16,
+    pub pos: (f32, f32),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlayerVitals {
+    pub id: u16,
+    pub player_state: PlayerState,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ConsoleInfo {
+    // Everyone currently in view of at least one camera.
+    Cameras { players: Vec<CameraPlayer> },
+    // How many players are in each of the map's rooms, in the same order.
+    Admin { room_counts: Vec<u8> },
+    Vitals { players: Vec<PlayerVitals> },
+}
+
+// Sent every tick to a player using a console, and with `info` set to None
+// once they stop.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsoleInfoPacket {
+    pub info: Option<ConsoleInfo>,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct HoldReactorPacket {
+    pub station: u8,
+    pub holding: bool,
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct OxygenCodePacket {
+    pub station: u8,
+    pub code: u16,
+}
+
+// Which room a player wants to play in when they connect.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoomRequest {
+    Create,
+    Join(String),
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ConnectPacket {
-    pub version: u32,
+    pub protocol: ProtocolVersions,
+    pub capabilities: Capabilities,
     pub name: String,
+    pub room: RoomRequest,
 }
 
 impl ConnectPacket {
-    pub fn new(name: &str) -> Self {
-        Self { version: GAME_VERSION, name: name.to_string() }
+    pub fn new(name: &str, room: RoomRequest) -> Self {
+        Self {
+            protocol: SUPPORTED_PROTOCOL,
+            capabilities: Capabilities::SUPPORTED,
+            name: name.to_string(),
+            room,
+        }
     }
 }
 
@@ -101,6 +417,134 @@ impl PlayerInputPacket {
     }
 }
 
+// Everything sent or received by either binary goes through these four, so
+// the size limits from `protocol` and the collection limits here always apply.
+// Decoding rejects anything over them. Sending something over them is our bug,
+// as the other side would throw it away, so debug builds panic instead.
+pub fn encode_client_message(message: &ClientToServer) -> Vec<u8> {
+    debug_assert_eq!(message.validate(), Ok(()), "Encoding {:?}", message);
+    message.encode()
+}
+
+pub fn encode_server_message(message: &ServerToClient) -> Vec<u8> {
+    debug_assert_eq!(message.validate(), Ok(()), "Encoding {:?}", message);
+    message.encode()
+}
+
+pub fn decode_client_message(data: &[u8]) -> Result<ClientToServer, DecodeError> {
+    let message = ClientToServer::decode(data)?;
+    message.validate().map_err(DecodeError::Invalid)?;
+
+    Ok(message)
+}
+
+pub fn decode_server_message(data: &[u8]) -> Result<ServerToClient, DecodeError> {
+    let message = ServerToClient::decode(data)?;
+    message.validate().map_err(DecodeError::Invalid)?;
+
+    Ok(message)
+}
+
+fn check(ok: bool, error: &'static str) -> Result<(), &'static str> {
+    if ok {
+        Ok(())
+    } else {
+        Err(error)
+    }
+}
+
+fn check_name(name: &str) -> Result<(), &'static str> {
+    check(name.len() <= MAX_NAME_LEN, "Name is too long")
+}
+
+fn check_room_code(room_code: &str) -> Result<(), &'static str> {
+    check(room_code.len() <= MAX_ROOM_CODE_LEN, "Room code is too long")
+}
+
+fn check_players<T>(players: &[T]) -> Result<(), &'static str> {
+    check(players.len() <= MAX_PLAYERS, "Too many players")
+}
+
+fn check_chat(message: &str) -> Result<(), &'static str> {
+    check(message.chars().count() <= MAX_CHAT_MESSAGE_LEN, "Chat message is too long")
+}
+
+impl ClientToServer {
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            ClientToServer::Connect(connect) | ClientToServer::ConnectAsSpectator(connect) => {
+                check_name(&connect.name)?;
+
+                match &connect.room {
+                    RoomRequest::Create => Ok(()),
+                    RoomRequest::Join(room_code) => check_room_code(room_code),
+                }
+            },
+            ClientToServer::Chat(message) => check_chat(message),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl ServerToClient {
+    fn validate(&self) -> Result<(), &'static str> {
+        match self {
+            ServerToClient::ConnectAck(connect_ack) => check_room_code(&connect_ack.room_code),
+            ServerToClient::NewPlayer(new_player) => check_name(&new_player.name),
+            ServerToClient::FullGameState(full_game_state) => {
+                check_players(&full_game_state.players)?;
+                full_game_state.players.iter().try_for_each(|player| check_name(&player.name))
+            },
+            ServerToClient::LobbyTick(lobby_tick) => {
+                check_players(&lobby_tick.players)?;
+                check(
+                    lobby_tick
+                        .players
+                        .iter()
+                        .all(|player| player.pos_history.len() <= MAX_POSITION_HISTORY),
+                    "Position history is too long",
+                )
+            },
+            ServerToClient::RoleAssignment(role_assignment) => {
+                check_players(&role_assignment.impostors)
+            },
+            ServerToClient::TaskList(task_list) => check(
+                task_list.tasks.as_ref().map_or(0, Vec::len) <= MAX_MAP_ITEMS,
+                "Too many tasks",
+            ),
+            ServerToClient::DoorStatus(door_status) => {
+                check(door_status.closed_groups.len() <= MAX_MAP_ITEMS, "Too many door groups")
+            },
+            ServerToClient::Chat(chat) => check_chat(&chat.message),
+            ServerToClient::ConsoleInfo(console_info) => match &console_info.info {
+                Some(ConsoleInfo::Cameras { players }) => check_players(players),
+                Some(ConsoleInfo::Admin { room_counts }) => {
+                    check(room_counts.len() <= MAX_MAP_ITEMS, "Too many rooms")
+                },
+                Some(ConsoleInfo::Vitals { players }) => check_players(players),
+                None => Ok(()),
+            },
+            ServerToClient::LobbyStatus(lobby_status) => check_players(&lobby_status.ready),
+            ServerToClient::JoinRoomFailed(join_room_failed) => {
+                check_room_code(&join_room_failed.room_code)
+            },
+            ServerToClient::SpectateAck(spectate_ack) => check_room_code(&spectate_ack.room_code),
+            ServerToClient::SpectatorTick(spectator_tick) => check_players(&spectator_tick.players),
+            ServerToClient::SystemMessage(system_message) => check_chat(&system_message.message),
+            ServerToClient::ServerShutdown(server_shutdown) => check_chat(&server_shutdown.reason),
+            ServerToClient::SabotageStatus(_)
+            | ServerToClient::GameOver(_)
+            | ServerToClient::VentStatus(_)
+            | ServerToClient::PlayerStatus(_)
+            | ServerToClient::Settings(_)
+            | ServerToClient::SpectatorCount(_)
+            | ServerToClient::Disconnected(_)
+            | ServerToClient::Ping(_)
+            | ServerToClient::PlayerLeft(_) => Ok(()),
+        }
+    }
+}
+
 pub fn make_packet(
     delivery_type: DeliveryType,
     data: Vec<u8>,
@@ -116,6 +560,49 @@ pub fn make_packet(
     }
 }
 
+// A copy of `packet` going to the same place with the same delivery guarantees,
+// but carrying `payload` instead.
+pub fn with_payload(packet: &Packet, payload: Vec<u8>) -> Packet {
+    let addr = packet.addr();
+
+    match (packet.delivery_guarantee(), packet.order_guarantee()) {
+        (DeliveryGuarantee::Reliable, OrderingGuarantee::Ordered(stream)) => {
+            Packet::reliable_ordered(addr, payload, stream)
+        },
+        (DeliveryGuarantee::Reliable, OrderingGuarantee::Sequenced(stream)) => {
+            Packet::reliable_sequenced(addr, payload, stream)
+        },
+        (DeliveryGuarantee::Reliable, OrderingGuarantee::None) => {
+            Packet::reliable_unordered(addr, payload)
+        },
+        (DeliveryGuarantee::Unreliable, OrderingGuarantee::Sequenced(stream)) => {
+            Packet::unreliable_sequenced(addr, payload, stream)
+        },
+        (DeliveryGuarantee::Unreliable, _) => Packet::unreliable(addr, payload),
+    }
+}
+
+// Whether laminar keeps resending `packet` until it gets through.
+pub fn is_reliable(packet: &Packet) -> bool {
+    matches!(packet.delivery_guarantee(), DeliveryGuarantee::Reliable)
+}
+
+// A name for the stream `packet` is on, for stats and metrics.
+pub fn stream_name(packet: &Packet) -> &'static str {
+    let stream = match packet.order_guarantee() {
+        OrderingGuarantee::Ordered(stream) | OrderingGuarantee::Sequenced(stream) => stream,
+        OrderingGuarantee::None => return "none",
+    };
+
+    match stream {
+        Some(INPUT_STREAM) => "input",
+        Some(GAME_STATE_STREAM) => "game_state",
+        Some(CHAT_STREAM) => "chat",
+        Some(VOICE_STREAM) => "voice",
+        _ => "other",
+    }
+}
+
 // Helper trait for handling u16 wraparound.
 pub trait SequenceCmp {
     fn sequentially_greater_than(&self, other: u16) -> bool;
@@ -125,21 +612,82 @@ pub trait SequenceCmp {
 
 impl SequenceCmp for u16 {
     fn sequentially_greater_than(&self, other: u16) -> bool {
-        let max_half = (u16::max_value() / 2) - 1;
+        let max_half = (u16::MAX / 2) - 1;
         ((*self > other) && *self - other <= max_half)
             || ((other > *self) && other - *self > max_half)
     }
 
     fn sequentially_greater_than_or_equal_to(&self, other: u16) -> bool {
-        let max_half = (u16::max_value() / 2) - 1;
+        let max_half = (u16::MAX / 2) - 1;
         (*self == other)
             || ((*self > other) && *self - other <= max_half)
             || ((other > *self) && other - *self > max_half)
     }
 
     fn sequentially_less_than(&self, other: u16) -> bool {
-        let max_half = (u16::max_value() / 2) - 1;
+        let max_half = (u16::MAX / 2) - 1;
         ((*self < other) && other - *self <= max_half)
             || ((*self > other) && *self - other > max_half)
     }
 }
+
+#[cfg(test)]
+proptest::proptest! {
+    #[test]
+    fn test_sequence_cmp_is_antisymmetric(a: u16, b: u16) {
+        // At most one of the two can be ahead, and "a is ahead of b" is the same
+        // question as "b is behind a".
+        proptest::prop_assert!(!(a.sequentially_greater_than(b) && b.sequentially_greater_than(a)));
+        proptest::prop_assert!(!(a.sequentially_less_than(b) && b.sequentially_less_than(a)));
+        proptest::prop_assert_eq!(a.sequentially_greater_than(b), b.sequentially_less_than(a));
+        proptest::prop_assert_eq!(
+            a.sequentially_greater_than_or_equal_to(b),
+            a == b || a.sequentially_greater_than(b)
+        );
+    }
+
+    #[test]
+    fn test_sequence_cmp_across_wraparound(a: u16, step in 1..(u16::MAX / 2)) {
+        // Counting forward from anywhere, including past u16::MAX, always lands
+        // ahead of where we started.
+        let ahead = a.wrapping_add(step);
+
+        proptest::prop_assert!(ahead.sequentially_greater_than(a));
+        proptest::prop_assert!(a.sequentially_less_than(ahead));
+        proptest::prop_assert!(!a.sequentially_greater_than(a));
+        proptest::prop_assert!(!a.sequentially_less_than(a));
+    }
+}
+
+#[test]
+fn test_collection_limits() {
+    let long_name = ClientToServer::Connect(ConnectPacket::new(
+        &"a".repeat(MAX_NAME_LEN + 1),
+        RoomRequest::Create,
+    ));
+    // Only a broken or malicious peer would send these, as our own encoding
+    // won't, so they're encoded by hand.
+    assert!(matches!(decode_client_message(&long_name.encode()), Err(DecodeError::Invalid(_))));
+
+    let connect = ClientToServer::Connect(ConnectPacket::new("Bo", RoomRequest::Create));
+    assert!(decode_client_message(&encode_client_message(&connect)).is_ok());
+
+    let lobby_tick = |history_len| {
+        ServerToClient::LobbyTick(LobbyTickPacket {
+            last_input_counter: 0,
+            players: vec![LobbyPlayer {
+                id: 0,
+                pos: (0.0, 0.0),
+                pos_history: vec![(0.0, 0.0); history_len],
+            }],
+            tick: 0,
+        })
+    };
+    assert!(
+        decode_server_message(&encode_server_message(&lobby_tick(MAX_POSITION_HISTORY))).is_ok()
+    );
+    assert!(matches!(
+        decode_server_message(&lobby_tick(MAX_POSITION_HISTORY + 1).encode()),
+        Err(DecodeError::Invalid(_))
+    ));
+}
@@ -0,0 +1,337 @@
+use crate::{
+    network::{
+        ChatPacket, ClientToServer, ConnectAckPacket, ConnectPacket, ConsoleInfoPacket,
+        ConsoleKind, DisconnectedPacket, DoorStatusPacket, FullGameStatePacket, GameOverPacket,
+        HoldReactorPacket, JoinRoomFailedPacket, LobbyStatusPacket, LobbyTickPacket,
+        NewPlayerPacket, OxygenCodePacket, PingPacket, PlayerInputPacket, PlayerLeftPacket,
+        PlayerStatusPacket, RoleAssignmentPacket, SabotageKind, SabotageStatusPacket,
+        ServerShutdownPacket, ServerToClient, SettingsPacket, SpectateAckPacket,
+        SpectatorCountPacket, SpectatorTickPacket, SystemMessagePacket, TaskListPacket,
+        VentStatusPacket,
+    },
+    settings::GameSettings,
+};
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+
+// Nothing we send comes close to this, so a length prefix asking for more is
+// garbage or an attack, and decoding fails before allocating for it.
+pub const MAX_MESSAGE_SIZE: u64 = 64 * 1024;
+
+// The range of protocol versions this build can speak. Bump `max` when the
+// meaning of a message changes, and `min` once the old behaviour is gone.
+// Version 2 added the tick to LobbyTick, along with changes to discovery and
+// the master server, and a version 1 peer can't read any of them.
+pub const SUPPORTED_PROTOCOL: ProtocolVersions = ProtocolVersions { min: 2, max: 2 };
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersions {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl ProtocolVersions {
+    // The highest version both sides understand, if there is one.
+    pub fn negotiate(&self, other: &ProtocolVersions) -> Option<u16> {
+        let version = self.max.min(other.max);
+
+        if version >= self.min.max(other.min) {
+            Some(version)
+        } else {
+            None
+        }
+    }
+}
+
+// Optional features a client can say it handles. The server only uses the
+// ones both sides have.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Capabilities(pub u32);
+
+impl Capabilities {
+    pub const CHAT: Self = Self(1 << 0);
+    // Answers `ServerToClient::Ping`.
+    pub const PING: Self = Self(1 << 2);
+    // Understands `ServerToClient::SpectatorCount`.
+    pub const SPECTATORS: Self = Self(1 << 1);
+    pub const SUPPORTED: Self = Self(Self::CHAT.0 | Self::SPECTATORS.0 | Self::PING.0);
+
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+}
+
+// For reading anything that comes off the network. The layout is the same as
+// `bincode::serialize`, which is what we write with.
+pub fn bincode_options() -> impl Options {
+    bincode::options().with_fixint_encoding().allow_trailing_bytes().with_limit(MAX_MESSAGE_SIZE)
+}
+
+// Every message goes over the wire as its ID followed by its bincode-encoded
+// contents. IDs are assigned by hand below, so reordering or adding variants
+// doesn't change what existing messages look like, and a peer can skip
+// messages it doesn't know instead of misreading them.
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    message_id: u16,
+    body: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum DecodeError {
+    Malformed(bincode::Error),
+    UnknownMessage(u16),
+    TooLarge { message_id: u16, size: usize },
+    // Well formed, but breaks one of the limits in `network`.
+    Invalid(&'static str),
+}
+
+impl From<bincode::Error> for DecodeError {
+    fn from(e: bincode::Error) -> Self {
+        DecodeError::Malformed(e)
+    }
+}
+
+macro_rules! message_ids {
+    ($name:ident { $($id:literal => $variant:ident $(($ty:ty))? max $max:expr,)* }) => {
+        impl $name {
+            pub fn message_id(&self) -> u16 {
+                match self {
+                    $($name::$variant { .. } => $id,)*
+                }
+            }
+
+            pub(crate) fn encode(&self) -> Vec<u8> {
+                let body = match self {
+                    $(message_ids!(@bind $name $variant msg $(($ty))?) => {
+                        message_ids!(@encode msg $(($ty))?)
+                    },)*
+                };
+
+                let envelope = Envelope { message_id: self.message_id(), body };
+                bincode::serialize(&envelope).expect("Messages always serialize")
+            }
+
+            pub(crate) fn decode(data: &[u8]) -> Result<Self, DecodeError> {
+                let envelope: Envelope = bincode_options().deserialize(data)?;
+                let body = &envelope.body;
+
+                Ok(match envelope.message_id {
+                    $($id => {
+                        if body.len() > $max {
+                            let message_id = envelope.message_id;
+                            return Err(DecodeError::TooLarge { message_id, size: body.len() });
+                        }
+
+                        message_ids!(@decode $name $variant body $max, $(($ty))?)
+                    },)*
+                    id => return Err(DecodeError::UnknownMessage(id)),
+                })
+            }
+        }
+    };
+    (@bind $name:ident $variant:ident $msg:ident ($ty:ty)) => { $name::$variant($msg) };
+    (@bind $name:ident $variant:ident $msg:ident) => { $name::$variant };
+    (@encode $msg:ident ($ty:ty)) => { bincode::serialize($msg).expect("Messages always serialize") };
+    (@encode $msg:ident) => { Vec::new() };
+    (@decode $name:ident $variant:ident $body:ident $max:expr, ($ty:ty)) => {
+        $name::$variant(bincode_options().with_limit($max as u64).deserialize::<$ty>($body)?)
+    };
+    (@decode $name:ident $variant:ident $body:ident $max:expr,) => { $name::$variant };
+}
+
+// Never change or reuse an ID once it has shipped, only add new ones. Each
+// message also has a cap on the size of its body, comfortably above anything
+// we'd legitimately send.
+message_ids!(ServerToClient {
+    1 => ConnectAck(ConnectAckPacket) max 64,
+    2 => NewPlayer(NewPlayerPacket) max 64,
+    3 => FullGameState(FullGameStatePacket) max 2048,
+    4 => LobbyTick(LobbyTickPacket) max 16 * 1024,
+    5 => RoleAssignment(RoleAssignmentPacket) max 128,
+    6 => TaskList(TaskListPacket) max 1024,
+    7 => SabotageStatus(SabotageStatusPacket) max 128,
+    8 => GameOver(GameOverPacket) max 64,
+    9 => VentStatus(VentStatusPacket) max 64,
+    10 => DoorStatus(DoorStatusPacket) max 256,
+    11 => PlayerStatus(PlayerStatusPacket) max 64,
+    12 => Chat(ChatPacket) max 1024,
+    13 => ConsoleInfo(ConsoleInfoPacket) max 1024,
+    14 => Settings(SettingsPacket) max 128,
+    15 => LobbyStatus(LobbyStatusPacket) max 128,
+    16 => JoinRoomFailed(JoinRoomFailedPacket) max 64,
+    17 => SpectateAck(SpectateAckPacket) max 64,
+    18 => SpectatorTick(SpectatorTickPacket) max 1024,
+    19 => SpectatorCount(SpectatorCountPacket) max 64,
+    20 => Disconnected(DisconnectedPacket) max 64,
+    21 => SystemMessage(SystemMessagePacket) max 1024,
+    22 => Ping(PingPacket) max 64,
+    23 => PlayerLeft(PlayerLeftPacket) max 64,
+    24 => ServerShutdown(ServerShutdownPacket) max 1024,
+});
+
+message_ids!(ClientToServer {
+    1 => Connect(ConnectPacket) max 256,
+    2 => PlayerInput(PlayerInputPacket) max 64,
+    3 => CompleteTask(u8) max 64,
+    4 => Sabotage(SabotageKind) max 64,
+    5 => ToggleLightSwitch(u8) max 64,
+    6 => FixComms max 64,
+    7 => HoldReactor(HoldReactorPacket) max 64,
+    8 => EnterOxygenCode(OxygenCodePacket) max 64,
+    9 => EnterVent(u8) max 64,
+    10 => MoveVent(u8) max 64,
+    11 => ExitVent max 64,
+    12 => CloseDoors(u8) max 64,
+    13 => Kill(u16) max 64,
+    14 => Chat(String) max 1024,
+    15 => UseConsole(ConsoleKind) max 64,
+    16 => LeaveConsole max 64,
+    17 => UpdateSettings(GameSettings) max 128,
+    18 => SetReady(bool) max 64,
+    19 => StartGame max 64,
+    20 => CancelStart max 64,
+    21 => ConnectAsSpectator(ConnectPacket) max 256,
+    22 => AddBot max 64,
+    23 => RemoveBot max 64,
+    24 => Pong(PingPacket) max 64,
+});
+
+#[test]
+fn test_negotiate() {
+    let v1_to_3 = ProtocolVersions { min: 1, max: 3 };
+    let v2_to_5 = ProtocolVersions { min: 2, max: 5 };
+    let v4_to_5 = ProtocolVersions { min: 4, max: 5 };
+
+    assert_eq!(v1_to_3.negotiate(&v2_to_5), Some(3));
+    assert_eq!(v2_to_5.negotiate(&v1_to_3), Some(3));
+    assert_eq!(v2_to_5.negotiate(&v4_to_5), Some(5));
+    assert_eq!(v1_to_3.negotiate(&v4_to_5), None);
+}
+
+// These bytes are what's on the wire today. If one of these fails, the format
+// changed: that's only fine together with a protocol version bump.
+#[test]
+fn test_golden_bytes() {
+    use crate::network::{LobbyPlayer, RoomRequest};
+
+    let connect = ClientToServer::Connect(ConnectPacket {
+        protocol: ProtocolVersions { min: 1, max: 1 },
+        capabilities: Capabilities::CHAT,
+        name: "Bo".to_string(),
+        room: RoomRequest::Join("ABCD".to_string()),
+    });
+
+    #[rustfmt::skip]
+    assert_eq!(connect.encode(), vec![
+        1, 0,
+        34, 0, 0, 0, 0, 0, 0, 0,
+        1, 0, 1, 0,
+        1, 0, 0, 0,
+        2, 0, 0, 0, 0, 0, 0, 0, b'B', b'o',
+        1, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0, b'A', b'B', b'C', b'D',
+    ]);
+
+    let input = ClientToServer::PlayerInput(PlayerInputPacket::new(7, -1, 1));
+
+    #[rustfmt::skip]
+    assert_eq!(input.encode(), vec![
+        2, 0,
+        6, 0, 0, 0, 0, 0, 0, 0,
+        7, 0, 255, 255, 1, 0,
+    ]);
+
+    #[rustfmt::skip]
+    assert_eq!(ClientToServer::StartGame.encode(), vec![
+        19, 0,
+        0, 0, 0, 0, 0, 0, 0, 0,
+    ]);
+
+    let chat = ServerToClient::Chat(ChatPacket { id: 3, message: "hi".to_string() });
+
+    #[rustfmt::skip]
+    assert_eq!(chat.encode(), vec![
+        12, 0,
+        12, 0, 0, 0, 0, 0, 0, 0,
+        3, 0,
+        2, 0, 0, 0, 0, 0, 0, 0, b'h', b'i',
+    ]);
+
+    let players = vec![NewPlayerPacket::new("Bo".to_string(), 7)];
+    let full_game_state = ServerToClient::FullGameState(FullGameStatePacket::new(players));
+
+    #[rustfmt::skip]
+    assert_eq!(full_game_state.encode(), vec![
+        3, 0,
+        20, 0, 0, 0, 0, 0, 0, 0,
+        1, 0, 0, 0, 0, 0, 0, 0,
+        2, 0, 0, 0, 0, 0, 0, 0, b'B', b'o', 7, 0,
+    ]);
+
+    let lobby_tick = ServerToClient::LobbyTick(LobbyTickPacket {
+        last_input_counter: 5,
+        players: vec![LobbyPlayer { id: 2, pos: (1.0, -2.0), pos_history: vec![(0.5, 0.0)] }],
+        tick: 300,
+    });
+
+    #[rustfmt::skip]
+    assert_eq!(lobby_tick.encode(), vec![
+        4, 0,
+        44, 0, 0, 0, 0, 0, 0, 0,
+        5, 0,
+        1, 0, 0, 0, 0, 0, 0, 0,
+        2, 0, 0, 0, 128, 63, 0, 0, 0, 192,
+        1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 63, 0, 0, 0, 0,
+        44, 1, 0, 0, 0, 0, 0, 0,
+    ]);
+}
+
+#[test]
+fn test_round_trip_and_unknown_messages() {
+    let input = ClientToServer::PlayerInput(PlayerInputPacket::new(1, 2, 3));
+
+    match ClientToServer::decode(&input.encode()) {
+        Ok(ClientToServer::PlayerInput(decoded)) => {
+            assert_eq!((decoded.counter, decoded.x, decoded.y), (1, 2, 3));
+        },
+        other => panic!("Unexpected decode result: {:?}", other),
+    }
+
+    let from_the_future =
+        bincode::serialize(&Envelope { message_id: 999, body: vec![1, 2] }).unwrap();
+
+    assert!(matches!(
+        ClientToServer::decode(&from_the_future),
+        Err(DecodeError::UnknownMessage(999))
+    ));
+    assert!(matches!(ClientToServer::decode(&[1, 2, 3]), Err(DecodeError::Malformed(_))));
+}
+
+#[test]
+fn test_huge_lengths_are_rejected() {
+    // An envelope claiming a terabyte of body.
+    let mut huge_envelope = vec![14, 0];
+    huge_envelope.extend_from_slice(&(1u64 << 40).to_le_bytes());
+    huge_envelope.extend_from_slice(b"hi");
+
+    assert!(matches!(ClientToServer::decode(&huge_envelope), Err(DecodeError::Malformed(_))));
+
+    // A small envelope holding a chat message that claims to be a terabyte long.
+    let mut huge_string = (1u64 << 40).to_le_bytes().to_vec();
+    huge_string.extend_from_slice(b"hi");
+    let envelope = bincode::serialize(&Envelope { message_id: 14, body: huge_string }).unwrap();
+
+    assert!(matches!(ClientToServer::decode(&envelope), Err(DecodeError::Malformed(_))));
+
+    // Inputs are tiny, so even a modest body is too much for one.
+    let envelope = bincode::serialize(&Envelope { message_id: 2, body: vec![0; 1000] }).unwrap();
+
+    assert!(matches!(
+        ClientToServer::decode(&envelope),
+        Err(DecodeError::TooLarge { message_id: 2, size: 1000 })
+    ));
+}
@@ -4,14 +4,75 @@ use std::collections::HashMap;
 #[derive(Debug, Resource)]
 pub struct PlayerToEntity(pub HashMap<u16, Entity>);
 
+// Which door groups are currently closed, indexed by group. Groups missing
+// from the list are open.
+#[derive(Debug, Default, Resource)]
+pub struct ClosedDoors(pub Vec<bool>);
+
+impl ClosedDoors {
+    pub fn is_closed(&self, group: u8) -> bool {
+        self.0.get(group as usize).copied().unwrap_or(false)
+    }
+}
+
 pub mod network {
     use super::*;
     use crossbeam_channel::{Receiver, Sender};
-    use laminar::SocketEvent;
-    use std::thread::JoinHandle;
+    use laminar::{Socket, SocketEvent};
+    use std::{
+        sync::{
+            atomic::{AtomicBool, Ordering},
+            Arc,
+        },
+        thread::JoinHandle,
+        time::{Duration, Instant},
+    };
 
+    // Polls the socket on its own thread until it's stopped, which happens
+    // when this is dropped if not before.
     #[derive(Debug, Resource)]
-    pub struct NetworkThread(pub JoinHandle<()>);
+    pub struct NetworkThread {
+        handle: Option<JoinHandle<()>>,
+        running: Arc<AtomicBool>,
+    }
+
+    impl NetworkThread {
+        pub fn spawn(mut socket: Socket, poll_interval: Duration) -> Self {
+            let running = Arc::new(AtomicBool::new(true));
+
+            let handle = std::thread::spawn({
+                let running = running.clone();
+
+                move || {
+                    while running.load(Ordering::Relaxed) {
+                        socket.manual_poll(Instant::now());
+                        std::thread::sleep(poll_interval);
+                    }
+
+                    // Sends anything queued since the last poll.
+                    socket.manual_poll(Instant::now());
+                }
+            });
+
+            Self { handle: Some(handle), running }
+        }
+
+        // Waits for the thread to finish, so everything queued before this
+        // has been handed to the OS by the time it returns.
+        pub fn stop(&mut self) {
+            self.running.store(false, Ordering::Relaxed);
+
+            if let Some(handle) = self.handle.take() {
+                let _ = handle.join();
+            }
+        }
+    }
+
+    impl Drop for NetworkThread {
+        fn drop(&mut self) {
+            self.stop();
+        }
+    }
 
     #[derive(Debug, Resource)]
     pub struct NetTx(pub Sender<laminar::Packet>);
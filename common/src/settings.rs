@@ -0,0 +1,94 @@
+use crate::{movement::PLAYER_SPEED, vision::VisionSettings};
+use serde::{Deserialize, Serialize};
+use simple_game::bevy::{bevy_ecs, Resource};
+use std::ops::RangeInclusive;
+
+// The rules for a match. The host can change these from the lobby, and the
+// server sends them to everyone so client prediction uses the same values.
+#[derive(Debug, Clone, PartialEq, Resource, Serialize, Deserialize)]
+pub struct GameSettings {
+    pub impostor_count: u8,
+    pub kill_cooldown_secs: f32,
+    pub kill_distance: f32,
+    pub player_speed: f32,
+    pub vision: VisionSettings,
+    pub tasks_per_player: u8,
+    pub discussion_time_secs: u16,
+    pub voting_time_secs: u16,
+    pub emergency_meetings_per_player: u8,
+    pub confirm_ejects: bool,
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            impostor_count: 1,
+            kill_cooldown_secs: 25.0,
+            kill_distance: 5.0,
+            player_speed: PLAYER_SPEED,
+            vision: VisionSettings::default(),
+            tasks_per_player: 3,
+            discussion_time_secs: 15,
+            voting_time_secs: 120,
+            emergency_meetings_per_player: 1,
+            confirm_ejects: true,
+        }
+    }
+}
+
+const IMPOSTOR_COUNT_RANGE: RangeInclusive<u8> = 1..=3;
+const KILL_COOLDOWN_RANGE: RangeInclusive<f32> = 10.0..=60.0;
+const KILL_DISTANCE_RANGE: RangeInclusive<f32> = 1.0..=10.0;
+const PLAYER_SPEED_RANGE: RangeInclusive<f32> = 0.05..=0.3;
+const VISION_RADIUS_RANGE: RangeInclusive<f32> = 5.0..=60.0;
+const LIGHTS_OUT_MULTIPLIER_RANGE: RangeInclusive<f32> = 0.1..=1.0;
+const TASKS_PER_PLAYER_RANGE: RangeInclusive<u8> = 1..=10;
+const DISCUSSION_TIME_RANGE: RangeInclusive<u16> = 0..=120;
+const VOTING_TIME_RANGE: RangeInclusive<u16> = 15..=300;
+const EMERGENCY_MEETINGS_RANGE: RangeInclusive<u8> = 0..=9;
+
+impl GameSettings {
+    // Returns the name of the first setting that's out of range.
+    pub fn validate(&self) -> Result<(), &'static str> {
+        let vision = &self.vision;
+
+        let checks = [
+            (IMPOSTOR_COUNT_RANGE.contains(&self.impostor_count), "impostor_count"),
+            (KILL_COOLDOWN_RANGE.contains(&self.kill_cooldown_secs), "kill_cooldown_secs"),
+            (KILL_DISTANCE_RANGE.contains(&self.kill_distance), "kill_distance"),
+            (PLAYER_SPEED_RANGE.contains(&self.player_speed), "player_speed"),
+            (VISION_RADIUS_RANGE.contains(&vision.crew_radius), "vision.crew_radius"),
+            (VISION_RADIUS_RANGE.contains(&vision.impostor_radius), "vision.impostor_radius"),
+            (VISION_RADIUS_RANGE.contains(&vision.ghost_radius), "vision.ghost_radius"),
+            (
+                LIGHTS_OUT_MULTIPLIER_RANGE.contains(&vision.lights_out_multiplier),
+                "vision.lights_out_multiplier",
+            ),
+            (TASKS_PER_PLAYER_RANGE.contains(&self.tasks_per_player), "tasks_per_player"),
+            (DISCUSSION_TIME_RANGE.contains(&self.discussion_time_secs), "discussion_time_secs"),
+            (VOTING_TIME_RANGE.contains(&self.voting_time_secs), "voting_time_secs"),
+            (
+                EMERGENCY_MEETINGS_RANGE.contains(&self.emergency_meetings_per_player),
+                "emergency_meetings_per_player",
+            ),
+        ];
+
+        match checks.iter().find(|(valid, _)| !valid) {
+            Some((_, name)) => Err(*name),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_validate_settings() {
+    let mut settings = GameSettings::default();
+    assert_eq!(settings.validate(), Ok(()));
+
+    settings.kill_cooldown_secs = f32::NAN;
+    assert_eq!(settings.validate(), Err("kill_cooldown_secs"));
+
+    settings = GameSettings::default();
+    settings.vision.crew_radius = 1000.0;
+    assert_eq!(settings.validate(), Err("vision.crew_radius"));
+}
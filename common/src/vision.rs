@@ -0,0 +1,65 @@
+use crate::{map::Map, resources::ClosedDoors, PlayerState, PlayerType};
+use serde::{Deserialize, Serialize};
+use simple_game::glam::Vec2;
+
+// How far each kind of player can see, in world units.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VisionSettings {
+    pub crew_radius: f32,
+    pub impostor_radius: f32,
+    pub ghost_radius: f32,
+    // Crew vision is multiplied by this while the lights are out.
+    pub lights_out_multiplier: f32,
+}
+
+impl Default for VisionSettings {
+    fn default() -> Self {
+        Self {
+            crew_radius: 20.0,
+            impostor_radius: 30.0,
+            ghost_radius: 40.0,
+            lights_out_multiplier: 0.25,
+        }
+    }
+}
+
+impl VisionSettings {
+    pub fn radius(
+        &self,
+        player_type: PlayerType,
+        player_state: PlayerState,
+        lights_on: bool,
+    ) -> f32 {
+        match (player_state, player_type) {
+            (PlayerState::Dead, _) => self.ghost_radius,
+            (PlayerState::Alive, PlayerType::Impostor) => self.impostor_radius,
+            (PlayerState::Alive, PlayerType::Crew) if lights_on => self.crew_radius,
+            (PlayerState::Alive, PlayerType::Crew) => self.crew_radius * self.lights_out_multiplier,
+        }
+    }
+}
+
+// Returns true if something at `target` is within `radius` of `viewer` and
+// not hidden behind a wall or closed door.
+pub fn can_see(
+    map: &Map,
+    closed_doors: &ClosedDoors,
+    viewer: Vec2,
+    target: Vec2,
+    radius: f32,
+) -> bool {
+    viewer.distance_squared(target) <= radius * radius
+        && map.line_of_sight(closed_doors, viewer, target)
+}
+
+#[test]
+fn test_vision_radius() {
+    let settings = VisionSettings::default();
+
+    let crew = settings.radius(PlayerType::Crew, PlayerState::Alive, true);
+    let crew_dark = settings.radius(PlayerType::Crew, PlayerState::Alive, false);
+    let impostor_dark = settings.radius(PlayerType::Impostor, PlayerState::Alive, false);
+
+    assert!(crew_dark < crew);
+    assert_eq!(impostor_dark, settings.impostor_radius);
+}
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sus_common::network::{decode_client_message, encode_client_message};
+
+fuzz_target!(|data: &[u8]| {
+    // Anything that decodes has to survive being sent back out again.
+    if let Ok(message) = decode_client_message(data) {
+        let encoded = encode_client_message(&message);
+        let decoded = decode_client_message(&encoded).expect("Re-encoded message should decode");
+
+        assert_eq!(encode_client_message(&decoded), encoded);
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sus_common::network::{decode_server_message, encode_server_message};
+
+fuzz_target!(|data: &[u8]| {
+    // Anything that decodes has to survive being sent back out again.
+    if let Ok(message) = decode_server_message(data) {
+        let encoded = encode_server_message(&message);
+        let decoded = decode_server_message(&encoded).expect("Re-encoded message should decode");
+
+        assert_eq!(encode_server_message(&decoded), encoded);
+    }
+});
@@ -0,0 +1,411 @@
+use bincode::Options;
+use std::{
+    collections::HashMap,
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+use sus_common::{
+    cookie::CookieJar,
+    discovery::{DiscoveredServer, ServerInfo},
+    logging::{init_logging, LogFormat},
+    master_server::{
+        FromMasterServer, ServerQuery, ToMasterServer, HEARTBEAT_TIMEOUT, LIST_PACKET_SIZE,
+        MASTER_SERVER_PORT, MAX_LISTED_SERVERS, MAX_MESSAGE_SIZE, MAX_ROOM_CODES,
+    },
+    network::MAX_ROOM_CODE_LEN,
+    protocol::bincode_options,
+    tracing::{debug, error, info, warn},
+};
+
+// How often expired servers get cleaned out when nothing is arriving.
+const EXPIRE_INTERVAL: Duration = Duration::from_secs(1);
+
+// Heartbeats only show the sender can receive at the address it's registering,
+// so anyone can still register servers of their own that say whatever they
+// like. These keep one source from filling the list, or the list from growing
+// without end.
+const MAX_SERVERS: usize = 4096;
+const MAX_SERVERS_PER_IP: usize = 8;
+
+struct ListedServer {
+    info: ServerInfo,
+    room_codes: Vec<String>,
+    last_heartbeat: Instant,
+}
+
+// Every game server that has sent a heartbeat recently, keyed by the address
+// players should connect to.
+#[derive(Default)]
+struct ServerList {
+    servers: HashMap<SocketAddr, ListedServer>,
+}
+
+impl ServerList {
+    fn heartbeat(
+        &mut self,
+        from: SocketAddr,
+        mut info: ServerInfo,
+        mut room_codes: Vec<String>,
+        now: Instant,
+    ) {
+        let addr = SocketAddr::new(from.ip(), info.game_port);
+        info.limit();
+        room_codes.retain(|code| code.len() <= MAX_ROOM_CODE_LEN);
+        room_codes.truncate(MAX_ROOM_CODES);
+
+        if !self.servers.contains_key(&addr) {
+            let from_ip = self.servers.keys().filter(|listed| listed.ip() == addr.ip()).count();
+
+            if self.servers.len() >= MAX_SERVERS || from_ip >= MAX_SERVERS_PER_IP {
+                debug!(name = ?info.name, %addr, from_ip, "Ignored a server, the list is full");
+                return;
+            }
+
+            info!(name = ?info.name, %addr, "Registered a server");
+        }
+
+        self.servers.insert(addr, ListedServer { info, room_codes, last_heartbeat: now });
+    }
+
+    fn expire(&mut self, now: Instant) {
+        self.servers.retain(|addr, server| {
+            let alive = now.duration_since(server.last_heartbeat) < HEARTBEAT_TIMEOUT;
+
+            if !alive {
+                info!(name = ?server.info.name, %addr, "Server stopped sending heartbeats");
+            }
+
+            alive
+        });
+    }
+
+    fn query(&self, query: &ServerQuery) -> Vec<DiscoveredServer> {
+        let mut servers: Vec<_> = self
+            .servers
+            .iter()
+            .filter(|(_, server)| query.protocol.negotiate(&server.info.protocol).is_some())
+            .filter(|(_, server)| !query.joinable_only || server.info.is_joinable())
+            .filter(|(_, server)| match &query.room_code {
+                Some(code) => server.room_codes.contains(code),
+                None => true,
+            })
+            .map(|(addr, server)| DiscoveredServer { addr: *addr, info: server.info.clone() })
+            .collect();
+
+        // Emptier servers first, they're the ones most likely to have space.
+        servers.sort_by_key(|server| (server.info.player_count(), server.addr));
+        servers.truncate(MAX_LISTED_SERVERS);
+
+        servers
+    }
+
+    // The servers matching `query` from `offset` onwards, as many as fit in
+    // `LIST_PACKET_SIZE`.
+    fn page(&self, query: &ServerQuery, offset: u16) -> FromMasterServer {
+        let servers = self.query(query);
+        let total = servers.len();
+
+        let empty = FromMasterServer::ServerList { servers: vec![], next: Some(0) };
+        let mut size = bincode::serialized_size(&empty).expect("Server list serializes");
+        let mut page = vec![];
+
+        for server in servers.into_iter().skip(offset as usize) {
+            size += bincode::serialized_size(&server).expect("Server serializes");
+
+            if size > LIST_PACKET_SIZE as u64 {
+                break;
+            }
+
+            page.push(server);
+        }
+
+        let end = offset as usize + page.len();
+        let next = (!page.is_empty() && end < total).then_some(end as u16);
+
+        FromMasterServer::ServerList { servers: page, next }
+    }
+}
+
+fn run(socket: UdpSocket) -> io::Result<()> {
+    socket.set_read_timeout(Some(EXPIRE_INTERVAL))?;
+
+    let mut server_list = ServerList::default();
+    let cookies = CookieJar::new();
+    let mut buf = vec![0u8; MAX_MESSAGE_SIZE];
+
+    loop {
+        let received = match socket.recv_from(&mut buf) {
+            Ok((len, addr)) => Some((len, addr)),
+            Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => {
+                None
+            },
+            Err(e) => {
+                error!(error = ?e, "Failed to receive");
+                None
+            },
+        };
+
+        let now = Instant::now();
+        server_list.expire(now);
+
+        let (len, addr) = match received {
+            Some(received) => received,
+            None => continue,
+        };
+
+        match bincode_options().deserialize::<ToMasterServer>(&buf[..len]) {
+            Ok(ToMasterServer::Heartbeat { info, room_codes, cookie }) => {
+                if cookies.check(addr, &cookie, now) {
+                    server_list.heartbeat(addr, info, room_codes, now);
+                    continue;
+                }
+
+                // Smaller than any heartbeat, so there's nothing to gain by
+                // sending one from someone else's address.
+                let challenge = FromMasterServer::Challenge(cookies.cookie(addr, now));
+                let data = bincode::serialize(&challenge).expect("Challenge serializes");
+
+                if let Err(e) = socket.send_to(&data, addr) {
+                    warn!(%addr, error = ?e, "Failed to send a heartbeat challenge");
+                }
+            },
+            Ok(ToMasterServer::ListServers { .. }) if len < LIST_PACKET_SIZE => {
+                debug!(%addr, len, "Ignored a server list query that wasn't padded");
+            },
+            Ok(ToMasterServer::ListServers { query, offset }) => {
+                let reply = server_list.page(&query, offset);
+                let data = bincode::serialize(&reply).expect("Server list serializes");
+
+                if let Err(e) = socket.send_to(&data, addr) {
+                    warn!(%addr, error = ?e, "Failed to send the server list");
+                }
+            },
+            Err(_) => {},
+        }
+    }
+}
+
+fn main() -> io::Result<()> {
+    init_logging("info", LogFormat::Text);
+
+    let bind_addr = std::env::var("SUS_MASTER_SERVER_BIND")
+        .unwrap_or_else(|_| format!("0.0.0.0:{}", MASTER_SERVER_PORT));
+
+    let socket = UdpSocket::bind(&bind_addr)?;
+    info!(addr = %bind_addr, "Master server listening");
+
+    run(socket)
+}
+
+#[cfg(test)]
+fn test_server_info(name: &str, game_state: sus_common::GameState) -> ServerInfo {
+    use sus_common::{discovery::RoomInfo, protocol::SUPPORTED_PROTOCOL};
+
+    ServerInfo {
+        name: name.to_string(),
+        protocol: SUPPORTED_PROTOCOL,
+        game_port: 7600,
+        rooms: vec![RoomInfo { player_count: 2, max_players: 16, game_state }],
+    }
+}
+
+#[test]
+fn test_query_and_expire() {
+    use sus_common::{
+        protocol::{ProtocolVersions, SUPPORTED_PROTOCOL},
+        GameState,
+    };
+
+    let mut server_list = ServerList::default();
+    let start = Instant::now();
+
+    let lobby_addr: SocketAddr = "10.0.0.1:40000".parse().unwrap();
+    let playing_addr: SocketAddr = "10.0.0.2:40000".parse().unwrap();
+
+    let codes = |code: &str| vec![code.to_string()];
+
+    server_list.heartbeat(
+        lobby_addr,
+        test_server_info("Lobby", GameState::Lobby),
+        codes("ABCD"),
+        start,
+    );
+    server_list.heartbeat(
+        playing_addr,
+        test_server_info("Playing", GameState::Main),
+        codes("WXYZ"),
+        start,
+    );
+
+    let all = server_list.query(&ServerQuery {
+        protocol: SUPPORTED_PROTOCOL,
+        joinable_only: false,
+        room_code: None,
+    });
+    assert_eq!(all.len(), 2);
+
+    let joinable = server_list.query(&ServerQuery {
+        protocol: SUPPORTED_PROTOCOL,
+        joinable_only: true,
+        room_code: None,
+    });
+    assert_eq!(joinable.len(), 1);
+    assert_eq!(joinable[0].addr, "10.0.0.1:7600".parse().unwrap());
+
+    let by_room = server_list.query(&ServerQuery {
+        protocol: SUPPORTED_PROTOCOL,
+        joinable_only: false,
+        room_code: Some("WXYZ".to_string()),
+    });
+    assert_eq!(by_room.len(), 1);
+    assert_eq!(by_room[0].addr, "10.0.0.2:7600".parse().unwrap());
+
+    let other_version = server_list.query(&ServerQuery {
+        protocol: ProtocolVersions {
+            min: SUPPORTED_PROTOCOL.max + 1,
+            max: SUPPORTED_PROTOCOL.max + 1,
+        },
+        joinable_only: false,
+        room_code: None,
+    });
+    assert!(other_version.is_empty());
+
+    // Only the lobby server keeps sending heartbeats.
+    server_list.heartbeat(
+        lobby_addr,
+        test_server_info("Lobby", GameState::Lobby),
+        codes("ABCD"),
+        start + HEARTBEAT_TIMEOUT / 2,
+    );
+    server_list.expire(start + HEARTBEAT_TIMEOUT);
+
+    let all = server_list.query(&ServerQuery {
+        protocol: SUPPORTED_PROTOCOL,
+        joinable_only: false,
+        room_code: None,
+    });
+    assert_eq!(all.len(), 1);
+    assert_eq!(all[0].info.name, "Lobby");
+}
+
+#[test]
+fn test_register_and_list_on_localhost() {
+    use sus_common::{
+        master_server::{list_servers, receive_challenge, send_heartbeat},
+        protocol::SUPPORTED_PROTOCOL,
+        GameState,
+    };
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let master_addr = socket.local_addr().unwrap();
+    std::thread::spawn(move || run(socket));
+
+    let query = ServerQuery { protocol: SUPPORTED_PROTOCOL, joinable_only: true, room_code: None };
+    let list = |query: &ServerQuery| {
+        list_servers(master_addr, query.clone(), Duration::from_millis(200)).unwrap()
+    };
+
+    // A heartbeat without a cookie only gets a challenge back.
+    let game_server = UdpSocket::bind("127.0.0.1:0").unwrap();
+    game_server.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
+    let info = test_server_info("Local", GameState::Lobby);
+    send_heartbeat(&game_server, master_addr, info.clone(), vec![], [0; 16]).unwrap();
+
+    let cookie = receive_challenge(&game_server, master_addr).unwrap().unwrap();
+    assert!(list(&query).is_empty());
+
+    send_heartbeat(&game_server, master_addr, info.clone(), vec![], cookie).unwrap();
+
+    // The heartbeat and the query travel separately, so give the heartbeat a
+    // moment to land first.
+    let deadline = Instant::now() + Duration::from_secs(2);
+    let servers = loop {
+        let servers = list(&query);
+
+        if !servers.is_empty() || Instant::now() > deadline {
+            break servers;
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    };
+
+    assert_eq!(servers.len(), 1);
+    assert_eq!(servers[0].addr, "127.0.0.1:7600".parse().unwrap());
+    assert_eq!(servers[0].info, info);
+
+    // A query that isn't padded out goes unanswered.
+    let client = UdpSocket::bind("127.0.0.1:0").unwrap();
+    client.set_read_timeout(Some(Duration::from_millis(200))).unwrap();
+    let data = bincode::serialize(&ToMasterServer::ListServers { query, offset: 0 }).unwrap();
+    client.send_to(&data, master_addr).unwrap();
+    assert!(client.recv(&mut [0u8; LIST_PACKET_SIZE]).is_err());
+}
+
+#[test]
+fn test_list_pages() {
+    use sus_common::{protocol::SUPPORTED_PROTOCOL, GameState};
+
+    let mut server_list = ServerList::default();
+    let now = Instant::now();
+
+    for i in 0..40u8 {
+        let from = SocketAddr::from(([10, 0, 0, i], 40000));
+        server_list.heartbeat(from, test_server_info("Listed", GameState::Lobby), vec![], now);
+    }
+
+    let query = ServerQuery { protocol: SUPPORTED_PROTOCOL, joinable_only: false, room_code: None };
+    let mut listed = vec![];
+    let mut offset = Some(0);
+
+    while let Some(from) = offset {
+        let page = server_list.page(&query, from);
+        assert!(bincode::serialize(&page).unwrap().len() <= LIST_PACKET_SIZE);
+
+        match page {
+            FromMasterServer::ServerList { servers, next } => {
+                assert!(!servers.is_empty());
+                listed.extend(servers);
+                offset = next;
+            },
+            FromMasterServer::Challenge(_) => panic!("Expected a server list"),
+        }
+    }
+
+    assert_eq!(listed, server_list.query(&query));
+    assert_eq!(listed.len(), 40);
+}
+
+#[test]
+fn test_list_limits() {
+    use sus_common::GameState;
+
+    let mut server_list = ServerList::default();
+    let now = Instant::now();
+
+    // One machine pretending to be lots of servers.
+    for port in 0..MAX_SERVERS_PER_IP as u16 + 10 {
+        let mut info = test_server_info("Spam", GameState::Lobby);
+        info.game_port = 7600 + port;
+        server_list.heartbeat("10.0.0.1:40000".parse().unwrap(), info, vec![], now);
+    }
+
+    assert_eq!(server_list.servers.len(), MAX_SERVERS_PER_IP);
+
+    // The ones already listed can still keep themselves there.
+    server_list.heartbeat(
+        "10.0.0.1:40000".parse().unwrap(),
+        test_server_info("Spam", GameState::Lobby),
+        vec![],
+        now,
+    );
+    assert_eq!(server_list.servers.len(), MAX_SERVERS_PER_IP);
+
+    server_list.heartbeat(
+        "10.0.0.2:40000".parse().unwrap(),
+        test_server_info("Other", GameState::Lobby),
+        vec![],
+        now,
+    );
+    assert_eq!(server_list.servers.len(), MAX_SERVERS_PER_IP + 1);
+}
@@ -0,0 +1,493 @@
+use crate::{
+    components::{Bot, Ping},
+    events::AdminAction,
+    resources::{Bans, Disconnecting, Host},
+    rooms::{update_rooms, Rooms},
+    shutdown::{shut_down, Shutdown},
+};
+use std::{
+    io::BufRead,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    time::{Duration, SystemTime},
+};
+use sus_common::{
+    components::player::{PlayerId, PlayerName, PlayerNetworkAddr},
+    crossbeam_channel::{self, Receiver},
+    network::{DisconnectReason, MAX_CHAT_MESSAGE_LEN},
+    settings::GameSettings,
+    simple_game::bevy::{
+        bevy_ecs, schedule::State, App, Commands, IntoSystemConfig, NonSendMut, Plugin, Res,
+        ResMut, Resource, World,
+    },
+    GameState,
+};
+
+// Lets whoever runs the server manage it by typing commands into its
+// terminal. Being local is the authentication.
+pub struct AdminConsolePlugin;
+
+impl Plugin for AdminConsolePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_startup_system(start_console)
+            .add_system(run_admin_commands.before(shut_down).before(update_rooms));
+    }
+}
+
+const HELP: &str = "\
+Commands:
+  rooms                        List rooms
+  players [ROOM]               List players with their IDs, addresses and ping
+  kick ROOM ID                 Remove a player
+  ban IP [TIME] [REASON]       Remove and keep out everyone at an address, for
+                               good or for a TIME like 30m, 12h or 7d. Players
+                               pick their own names and get a new key each
+                               time, so bans can only go by address, and they
+                               also keep out anyone sharing it
+  unban IP                     Lift a ban
+  bans                         List bans
+  state ROOM lobby|intro|main|end
+                               Force a room into another state
+  set ROOM SETTING VALUE       Change a setting, in the lobby
+  say MESSAGE                  Send a message to everyone
+  shutdown [REASON]            Disconnect everyone, telling them why, and stop
+                               the server";
+
+#[derive(Debug, PartialEq)]
+enum AdminCommand {
+    Help,
+    Rooms,
+    Players(Option<String>),
+    Kick { room: String, id: u16 },
+    Ban { ip: IpAddr, duration: Option<Duration>, reason: String },
+    Unban(IpAddr),
+    Bans,
+    SetState { room: String, state: GameState },
+    Set { room: String, setting: String, value: String },
+    Say(String),
+    Shutdown(String),
+}
+
+impl FromStr for AdminCommand {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let line = line.trim();
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let args: Vec<_> = rest.split_whitespace().collect();
+        let room = |code: &str| code.to_uppercase();
+
+        let command = match (command, args.as_slice()) {
+            ("help", []) => Self::Help,
+            ("rooms", []) => Self::Rooms,
+            ("players", []) => Self::Players(None),
+            ("players", [code]) => Self::Players(Some(room(code))),
+            ("kick", [code, id]) => {
+                let id = id.parse().map_err(|_| format!("{} isn't a player ID", id))?;
+                Self::Kick { room: room(code), id }
+            },
+            ("ban", [target, ..]) => {
+                let rest = rest.trim_start()[target.len()..].trim_start();
+                let (duration, reason) = match rest.split_once(' ').unwrap_or((rest, "")) {
+                    (duration, reason) if parse_duration(duration).is_some() => {
+                        (parse_duration(duration), reason)
+                    },
+                    _ => (None, rest),
+                };
+
+                Self::Ban { ip: parse_ip(target)?, duration, reason: reason.trim().to_string() }
+            },
+            ("unban", [target]) => Self::Unban(parse_ip(target)?),
+            ("bans", []) => Self::Bans,
+            ("state", [code, state]) => {
+                let state = match *state {
+                    "lobby" => GameState::Lobby,
+                    "intro" => GameState::IntroScreen,
+                    "main" => GameState::Main,
+                    "end" => GameState::End,
+                    _ => return Err(format!("{} isn't a state", state)),
+                };
+
+                Self::SetState { room: room(code), state }
+            },
+            ("set", [code, setting, value]) => Self::Set {
+                room: room(code),
+                setting: setting.to_string(),
+                value: value.to_string(),
+            },
+            ("shutdown", _) => Self::Shutdown(rest.trim().to_string()),
+            ("say", _) if !rest.trim().is_empty() => Self::Say(rest.trim().to_string()),
+            _ => return Err(format!("Didn't understand \"{}\", try \"help\"", line)),
+        };
+
+        Ok(command)
+    }
+}
+
+// Lines typed into the terminal, read on their own thread.
+#[derive(Resource)]
+struct AdminConsole(Receiver<String>);
+
+fn start_console(mut commands: Commands) {
+    let (tx, rx) = crossbeam_channel::unbounded();
+
+    // Without a terminal this just finds the end of stdin straight away.
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let sent = line.map(|line| tx.send(line).is_ok());
+
+            if !matches!(sent, Ok(true)) {
+                break;
+            }
+        }
+    });
+
+    commands.insert_resource(AdminConsole(rx));
+}
+
+fn run_admin_commands(
+    console: Res<AdminConsole>,
+    mut rooms: NonSendMut<Rooms>,
+    mut bans: ResMut<Bans>,
+    mut disconnecting: ResMut<Disconnecting>,
+    mut shutdown: ResMut<Shutdown>,
+) {
+    for line in console.0.try_iter() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let command = match line.parse() {
+            Ok(command) => command,
+            Err(e) => {
+                println!("{}", e);
+                continue;
+            },
+        };
+
+        match command {
+            AdminCommand::Help => println!("{}", HELP),
+            AdminCommand::Rooms => {
+                let mut codes: Vec<_> = rooms.iter_mut().map(|(code, _)| code.clone()).collect();
+                codes.sort();
+
+                for code in codes {
+                    if let Some(room) = rooms.get_mut(&code) {
+                        let world = room.world_mut();
+                        let state = world.resource::<State<GameState>>().0.clone();
+                        let players = list_players(world).len();
+                        println!("{}: {:?}, {} players", code, state, players);
+                    }
+                }
+            },
+            AdminCommand::Players(code) => {
+                let mut found: Vec<_> = rooms
+                    .iter_mut()
+                    .filter(|(room_code, _)| code.as_ref().is_none_or(|code| code == *room_code))
+                    .map(|(room_code, room)| (room_code.clone(), list_players(room.world_mut())))
+                    .collect();
+                found.sort_by(|a, b| a.0.cmp(&b.0));
+
+                if found.is_empty() {
+                    println!("No rooms");
+                }
+
+                for (room_code, players) in found {
+                    println!("{}:", room_code);
+
+                    for player in players {
+                        println!("  {}", player);
+                    }
+                }
+            },
+            AdminCommand::Kick { room, id } => match rooms.get_mut(&room) {
+                Some(room) => kick(room.world_mut(), id, &mut disconnecting)
+                    .map(|action| room.send_admin(action))
+                    .unwrap_or_else(|| println!("No player {} in that room", id)),
+                None => println!("No room {}", room),
+            },
+            AdminCommand::Ban { ip, duration, reason } => {
+                let now = SystemTime::now();
+
+                match duration {
+                    Some(duration) => println!("Banned {} for {}", ip, describe(duration)),
+                    None => println!("Banned {}", ip),
+                }
+
+                bans.0.ban(ip, reason, duration.map(|duration| now + duration));
+
+                for (_, room) in rooms.iter_mut() {
+                    let banned: Vec<_> = list_players(room.world_mut())
+                        .into_iter()
+                        .filter(|player| !player.bot && bans.0.find(player.addr, now).is_some())
+                        .collect();
+
+                    for player in banned {
+                        disconnecting.0.push(player.addr);
+                        room.send_admin(AdminAction::Kick {
+                            id: player.id,
+                            reason: DisconnectReason::Banned,
+                        });
+                    }
+
+                    let banned: Vec<_> = room
+                        .spectators()
+                        .filter(|addr| bans.0.find(*addr, now).is_some())
+                        .collect();
+
+                    for addr in banned {
+                        disconnecting.0.push(addr);
+                        room.send_admin(AdminAction::RemoveSpectator {
+                            addr,
+                            reason: DisconnectReason::Banned,
+                        });
+                    }
+                }
+            },
+            AdminCommand::Unban(ip) => {
+                if bans.0.unban(ip) {
+                    println!("Unbanned {}", ip);
+                } else {
+                    println!("{} isn't banned", ip);
+                }
+            },
+            AdminCommand::Bans => {
+                let now = SystemTime::now();
+                let mut all: Vec<_> = bans.0.iter().filter(|ban| !ban.expired(now)).collect();
+                all.sort_by_key(|ban| ban.ip);
+
+                if all.is_empty() {
+                    println!("Nobody is banned");
+                }
+
+                for ban in all {
+                    let time_left = match ban.expires_at {
+                        Some(expires_at) => {
+                            let left = expires_at.duration_since(now).unwrap_or_default();
+                            format!("for another {}", describe(left))
+                        },
+                        None => "for good".to_string(),
+                    };
+
+                    print!("  {:<20} {}", ban.ip.to_string(), time_left);
+
+                    if ban.reason.is_empty() {
+                        println!();
+                    } else {
+                        println!(": {}", ban.reason);
+                    }
+                }
+            },
+            AdminCommand::SetState { room, state } => match rooms.get_mut(&room) {
+                Some(room) => room.send_admin(AdminAction::SetState(state)),
+                None => println!("No room {}", room),
+            },
+            AdminCommand::Set { room, setting, value } => {
+                let room = match rooms.get_mut(&room) {
+                    Some(room) => room,
+                    None => {
+                        println!("No room {}", room);
+                        continue;
+                    },
+                };
+
+                let world = room.world_mut();
+
+                if world.resource::<State<GameState>>().0 != GameState::Lobby {
+                    println!("Settings can only be changed in the lobby");
+                    continue;
+                }
+
+                let mut settings = world.resource::<GameSettings>().clone();
+
+                match change_setting(&mut settings, &setting, &value) {
+                    Ok(()) => room.send_admin(AdminAction::UpdateSettings(settings)),
+                    Err(e) => println!("{}", e),
+                }
+            },
+            AdminCommand::Say(message) => {
+                if message.chars().count() > MAX_CHAT_MESSAGE_LEN {
+                    println!("That's longer than {} characters", MAX_CHAT_MESSAGE_LEN);
+                    continue;
+                }
+
+                for (_, room) in rooms.iter_mut() {
+                    room.send_admin(AdminAction::Announce(message.clone()));
+                }
+            },
+            AdminCommand::Shutdown(reason) => {
+                if reason.chars().count() > MAX_CHAT_MESSAGE_LEN {
+                    println!("That's longer than {} characters", MAX_CHAT_MESSAGE_LEN);
+                    continue;
+                }
+
+                shutdown.requested = Some(reason);
+            },
+        }
+    }
+}
+
+struct PlayerListing {
+    id: u16,
+    name: String,
+    addr: SocketAddr,
+    ping: Option<Duration>,
+    bot: bool,
+    host: bool,
+}
+
+impl std::fmt::Display for PlayerListing {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:>3} {:<20}", self.id, self.name)?;
+
+        if self.bot {
+            write!(f, " (bot)")?;
+        } else {
+            write!(f, " {}", self.addr)?;
+
+            match self.ping {
+                Some(ping) => write!(f, " {} ms", ping.as_millis())?,
+                None => write!(f, " ? ms")?,
+            }
+        }
+
+        if self.host {
+            write!(f, " (host)")?;
+        }
+
+        Ok(())
+    }
+}
+
+fn list_players(world: &mut World) -> Vec<PlayerListing> {
+    let host = world.resource::<Host>().0;
+
+    let mut players: Vec<_> = world
+        .query::<(&PlayerId, &PlayerName, &PlayerNetworkAddr, &Ping, Option<&Bot>)>()
+        .iter(world)
+        .map(|(id, name, network_addr, ping, bot)| PlayerListing {
+            id: id.0,
+            name: name.0.clone(),
+            addr: network_addr.0,
+            ping: ping.0,
+            bot: bot.is_some(),
+            host: host == Some(id.0),
+        })
+        .collect();
+
+    players.sort_by_key(|player| player.id);
+    players
+}
+
+fn kick(world: &mut World, id: u16, disconnecting: &mut Disconnecting) -> Option<AdminAction> {
+    let player = list_players(world).into_iter().find(|player| player.id == id)?;
+
+    if !player.bot {
+        disconnecting.0.push(player.addr);
+    }
+
+    Some(AdminAction::Kick { id, reason: DisconnectReason::Kicked })
+}
+
+fn parse_ip(ip: &str) -> Result<IpAddr, String> {
+    ip.parse().map_err(|_| format!("{} isn't an IP address, \"players\" lists them", ip))
+}
+
+// A number of seconds, minutes, hours or days, like "90s" or "7d".
+fn parse_duration(duration: &str) -> Option<Duration> {
+    let unit = match duration.chars().last()? {
+        's' => 1,
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        _ => return None,
+    };
+
+    let count: u64 = duration[..duration.len() - 1].parse().ok()?;
+    Some(Duration::from_secs(count.checked_mul(unit)?))
+}
+
+// Roughly how long a duration is, in its two biggest units.
+fn describe(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    let (days, hours, mins) = (secs / 86400, secs / 3600 % 24, secs / 60 % 60);
+
+    match (days, hours, mins) {
+        (0, 0, 0) => format!("{}s", secs),
+        (0, 0, _) => format!("{}m {}s", mins, secs % 60),
+        (0, _, _) => format!("{}h {}m", hours, mins),
+        _ => format!("{}d {}h", days, hours),
+    }
+}
+
+fn parse<T: FromStr>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Couldn't understand {}", value))
+}
+
+// Settings are named the same way `GameSettings::validate` names them.
+fn change_setting(settings: &mut GameSettings, name: &str, value: &str) -> Result<(), String> {
+    match name {
+        "impostor_count" => settings.impostor_count = parse(value)?,
+        "kill_cooldown_secs" => settings.kill_cooldown_secs = parse(value)?,
+        "kill_distance" => settings.kill_distance = parse(value)?,
+        "player_speed" => settings.player_speed = parse(value)?,
+        "vision.crew_radius" => settings.vision.crew_radius = parse(value)?,
+        "vision.impostor_radius" => settings.vision.impostor_radius = parse(value)?,
+        "vision.ghost_radius" => settings.vision.ghost_radius = parse(value)?,
+        "vision.lights_out_multiplier" => settings.vision.lights_out_multiplier = parse(value)?,
+        "tasks_per_player" => settings.tasks_per_player = parse(value)?,
+        "discussion_time_secs" => settings.discussion_time_secs = parse(value)?,
+        "voting_time_secs" => settings.voting_time_secs = parse(value)?,
+        "emergency_meetings_per_player" => settings.emergency_meetings_per_player = parse(value)?,
+        "confirm_ejects" => settings.confirm_ejects = parse(value)?,
+        _ => return Err(format!("There's no setting called {}", name)),
+    }
+
+    settings.validate().map_err(|name| format!("{} is out of range", name))
+}
+
+#[test]
+fn test_parse_admin_commands() {
+    assert_eq!("players".parse(), Ok(AdminCommand::Players(None)));
+    assert_eq!("players abcd".parse(), Ok(AdminCommand::Players(Some("ABCD".into()))));
+    assert_eq!("kick ABCD 3".parse(), Ok(AdminCommand::Kick { room: "ABCD".into(), id: 3 }));
+    assert_eq!(
+        "state abcd main".parse(),
+        Ok(AdminCommand::SetState { room: "ABCD".into(), state: GameState::Main })
+    );
+    assert_eq!(
+        "say  Restarting in  5 minutes ".parse(),
+        Ok(AdminCommand::Say("Restarting in  5 minutes".into()))
+    );
+    assert_eq!(
+        "shutdown Back in 5 minutes".parse(),
+        Ok(AdminCommand::Shutdown("Back in 5 minutes".into()))
+    );
+
+    assert_eq!(
+        "ban 2001:db8::1 12h  Kept killing in the lobby".parse(),
+        Ok(AdminCommand::Ban {
+            ip: "2001:db8::1".parse().unwrap(),
+            duration: Some(Duration::from_secs(12 * 60 * 60)),
+            reason: "Kept killing in the lobby".into(),
+        })
+    );
+    assert_eq!(
+        "ban 203.0.113.7 Spam".parse(),
+        Ok(AdminCommand::Ban {
+            ip: "203.0.113.7".parse().unwrap(),
+            duration: None,
+            reason: "Spam".into(),
+        })
+    );
+    assert!("ban Griefer".parse::<AdminCommand>().is_err());
+
+    assert!("kick ABCD someone".parse::<AdminCommand>().is_err());
+    assert!("state ABCD paused".parse::<AdminCommand>().is_err());
+    assert!("say".parse::<AdminCommand>().is_err());
+
+    let mut settings = GameSettings::default();
+    assert!(change_setting(&mut settings, "vision.crew_radius", "25").is_ok());
+    assert_eq!(settings.vision.crew_radius, 25.0);
+    assert!(change_setting(&mut settings, "impostor_count", "9").is_err());
+}
@@ -0,0 +1,215 @@
+use std::{
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use sus_common::tracing::error;
+
+// The ban list lives in a plain text file so it survives restarts and can be
+// edited by hand while the server is stopped. Each line is a ban, with tabs
+// between the fields:
+//
+//   TARGET    EXPIRES    REASON
+//
+// where TARGET is "ip:" followed by an IP address, and EXPIRES is when the ban
+// runs out in seconds since the Unix epoch, or "never". A file that doesn't
+// load stops the server from starting, rather than have it run with bans
+// missing and then save over them.
+pub const DEFAULT_BAN_FILE: &str = "bans.txt";
+
+const HEADER: &str = "# target (ip:ADDRESS)\texpires (unix seconds or \"never\")\treason";
+
+// Bans are only ever by IP address. Clients make up a new key for every
+// connection and pick their own names, so nothing else about a player stays
+// the same when they come back, and banning a name would keep out whoever
+// picked it next instead. Matching by IP means coming back from another port
+// doesn't get around a ban, but everyone else behind the same address is kept
+// out too.
+fn ip_to_field(ip: IpAddr) -> String {
+    format!("ip:{}", ip)
+}
+
+fn ip_from_field(field: &str) -> Result<IpAddr, String> {
+    match field.strip_prefix("ip:") {
+        Some(ip) => ip.parse().map_err(|_| format!("{} isn't an IP address", ip)),
+        None => Err(format!("{} should start with ip:", field)),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Ban {
+    pub ip: IpAddr,
+    pub reason: String,
+    // None for a ban that lasts until it's lifted.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl Ban {
+    pub fn expired(&self, now: SystemTime) -> bool {
+        self.expires_at.is_some_and(|expires_at| expires_at <= now)
+    }
+
+    fn to_line(&self) -> String {
+        let expires = match self.expires_at {
+            Some(expires_at) => unix_secs(expires_at).to_string(),
+            None => "never".to_string(),
+        };
+
+        // Tabs and newlines would break up the line.
+        let reason: String =
+            self.reason.chars().map(|c| if c.is_control() { ' ' } else { c }).collect();
+
+        format!("{}\t{}\t{}", ip_to_field(self.ip), expires, reason.trim())
+    }
+
+    fn from_line(line: &str) -> Result<Self, String> {
+        let mut fields = line.splitn(3, '\t');
+        let ip = ip_from_field(fields.next().unwrap_or_default())?;
+
+        let expires_at = match fields.next().map(str::trim) {
+            None | Some("never") => None,
+            Some(secs) => {
+                let secs = secs.parse().map_err(|_| format!("{} isn't a time", secs))?;
+                Some(UNIX_EPOCH + Duration::from_secs(secs))
+            },
+        };
+
+        let reason = fields.next().unwrap_or_default().trim().to_string();
+
+        Ok(Self { ip, reason, expires_at })
+    }
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+// Every ban, along with the file they're saved to after each change. Without a
+// file they only last as long as the server.
+#[derive(Debug, Default)]
+pub struct BanList {
+    path: Option<PathBuf>,
+    bans: Vec<Ban>,
+}
+
+impl BanList {
+    // A missing file is an empty list, and gets created by the first ban.
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let text = match std::fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(format!("Couldn't read {}: {}", path.display(), e)),
+        };
+
+        let bans = Self::parse(&text)
+            .map_err(|e| format!("Couldn't load bans from {}: {}", path.display(), e))?;
+
+        Ok(Self { path: Some(path), bans })
+    }
+
+    fn parse(text: &str) -> Result<Vec<Ban>, String> {
+        text.lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| Ban::from_line(line).map_err(|e| format!("line {}: {}", i + 1, e)))
+            .collect()
+    }
+
+    fn to_text(&self) -> String {
+        let mut text = format!("{}\n", HEADER);
+
+        for ban in &self.bans {
+            text.push_str(&ban.to_line());
+            text.push('\n');
+        }
+
+        text
+    }
+
+    fn save(&self) {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return,
+        };
+
+        // Written alongside and then moved over the old one, so a crash halfway
+        // through can't lose the list.
+        let temp_path = path.with_extension("tmp");
+        let result = std::fs::write(&temp_path, self.to_text())
+            .and_then(|_| std::fs::rename(&temp_path, path));
+
+        if let Err(e) = result {
+            error!(path = %path.display(), error = %e, "Failed to save bans");
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Ban> {
+        self.bans.iter()
+    }
+
+    // Banning someone who's already banned replaces their old ban.
+    pub fn ban(&mut self, ip: IpAddr, reason: String, expires_at: Option<SystemTime>) {
+        self.bans.retain(|ban| ban.ip != ip);
+        self.bans.push(Ban { ip, reason, expires_at });
+        self.save();
+    }
+
+    pub fn unban(&mut self, ip: IpAddr) -> bool {
+        let count = self.bans.len();
+        self.bans.retain(|ban| ban.ip != ip);
+
+        let removed = self.bans.len() != count;
+
+        if removed {
+            self.save();
+        }
+
+        removed
+    }
+
+    pub fn find(&self, addr: SocketAddr, now: SystemTime) -> Option<&Ban> {
+        self.bans.iter().find(|ban| !ban.expired(now) && ban.ip == addr.ip())
+    }
+
+    // Takes out bans that have run out, returning them.
+    pub fn remove_expired(&mut self, now: SystemTime) -> Vec<Ban> {
+        if !self.bans.iter().any(|ban| ban.expired(now)) {
+            return vec![];
+        }
+
+        let (expired, bans) = self.bans.drain(..).partition(|ban| ban.expired(now));
+        self.bans = bans;
+        self.save();
+
+        expired
+    }
+}
+
+#[test]
+fn test_ban_list() {
+    let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+    let addr: SocketAddr = "203.0.113.7:5000".parse().unwrap();
+
+    let mut bans = BanList::default();
+    bans.ban("203.0.113.7".parse().unwrap(), "Spamming chat".into(), None);
+    bans.ban("2001:db8::1".parse().unwrap(), "".into(), Some(now + Duration::from_secs(60)));
+
+    assert!(bans.find("203.0.113.7:6000".parse().unwrap(), now).is_some());
+    assert_eq!(bans.find("[2001:db8::1]:5000".parse().unwrap(), now).unwrap().reason, "");
+    assert!(bans.find("198.51.100.1:5000".parse().unwrap(), now).is_none());
+
+    // What's saved loads back the same.
+    assert_eq!(BanList::parse(&bans.to_text()), Ok(bans.bans.clone()));
+
+    let later = now + Duration::from_secs(61);
+    assert!(bans.find("[2001:db8::1]:5000".parse().unwrap(), later).is_none());
+    assert_eq!(bans.remove_expired(later).len(), 1);
+    assert_eq!(bans.iter().count(), 1);
+
+    assert!(bans.unban("203.0.113.7".parse().unwrap()));
+    assert!(bans.find(addr, now).is_none());
+
+    assert!(BanList::parse("ip:203.0.113.7\tsoon\tbecause").is_err());
+    assert!(BanList::parse("203.0.113.7\tnever\tbecause").is_err());
+    assert!(BanList::parse("name:someone\tnever\tbecause").is_err());
+}
@@ -0,0 +1,265 @@
+use rand::{rngs::StdRng, Rng};
+use sus_common::{
+    map::{within_interact_distance, Map},
+    math::distance_to_segment,
+    movement::PLAYER_RADIUS,
+    network::TaskProgress,
+    resources::ClosedDoors,
+    settings::GameSettings,
+    simple_game::glam::Vec2,
+    GameState, PlayerState, PlayerType,
+};
+
+// Bots are ordinary players whose inputs come from a `BotBrain` on the server
+// instead of from a client. They're useful for filling out a lobby, and for
+// tests that need players to do things.
+
+// What a bot knows when it decides what to do, which is roughly what a client
+// would show a player in its place.
+pub struct BotView<'a> {
+    pub game_state: &'a GameState,
+    pub pos: Vec2,
+    pub player_type: PlayerType,
+    pub player_state: PlayerState,
+    pub tasks: &'a [TaskProgress],
+    pub can_kill: bool,
+    pub vent: Option<u8>,
+    pub can_vent: bool,
+    pub visible_players: &'a [VisiblePlayer],
+    pub map: &'a Map,
+    pub closed_doors: &'a ClosedDoors,
+    pub settings: &'a GameSettings,
+}
+
+// Someone else the bot can see. Impostors know who the other impostors are.
+#[derive(Debug, Clone, Copy)]
+pub struct VisiblePlayer {
+    pub id: u16,
+    pub pos: Vec2,
+    pub player_state: PlayerState,
+    pub teammate: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BotAction {
+    CompleteTask(u8),
+    Kill(u16),
+    EnterVent(u8),
+    ExitVent,
+}
+
+// A tick's worth of decisions. `x` and `y` are the same stick input a client
+// sends.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BotOutput {
+    pub x: i16,
+    pub y: i16,
+    pub action: Option<BotAction>,
+}
+
+impl BotOutput {
+    pub fn action(action: BotAction) -> Self {
+        Self { action: Some(action), ..Self::default() }
+    }
+
+    // Full speed towards `target`.
+    pub fn towards(from: Vec2, target: Vec2) -> Self {
+        let direction = (target - from).normalize_or_zero() * i16::MAX as f32;
+
+        Self { x: direction.x as i16, y: direction.y as i16, action: None }
+    }
+}
+
+// Decides what a bot does each tick. Anything random should come from `rng`,
+// which is seeded with the room, so that recorded matches with bots replay.
+pub trait BotBrain: Send + Sync + 'static {
+    fn think(&mut self, view: &BotView, rng: &mut StdRng) -> BotOutput;
+}
+
+// Close enough to a point to stop walking to it.
+const ARRIVED_DISTANCE: f32 = 1.5;
+
+// Ticks without moving before a bot gives up on where it was going.
+const STUCK_TICKS: u32 = 30;
+
+// Ticks an impostor bot spends hiding in a vent.
+const VENT_TICKS: u32 = 90;
+
+// The chance each tick that an impostor bot on a vent with nobody around
+// climbs in.
+const VENT_CHANCE: f64 = 0.02;
+
+// The default bot. Crew walk from task to task, impostors wander between
+// rooms, kill anyone they catch alone and sometimes hide in vents.
+#[derive(Debug, Default)]
+pub struct WanderingBot {
+    destination: Option<Vec2>,
+    last_pos: Option<Vec2>,
+    stuck_ticks: u32,
+    vent_ticks: u32,
+}
+
+impl BotBrain for WanderingBot {
+    fn think(&mut self, view: &BotView, rng: &mut StdRng) -> BotOutput {
+        if view.vent.is_some() {
+            self.vent_ticks += 1;
+
+            if self.vent_ticks < VENT_TICKS {
+                return BotOutput::default();
+            }
+
+            self.vent_ticks = 0;
+            return BotOutput::action(BotAction::ExitVent);
+        }
+
+        let in_game = *view.game_state == GameState::Main;
+        let is_impostor = view.player_type == PlayerType::Impostor;
+
+        if in_game && is_impostor && view.player_state == PlayerState::Alive {
+            if let Some(output) = self.impostor_action(view, rng) {
+                return output;
+            }
+        } else if in_game {
+            let next_task = view.tasks.iter().find(|task| !task.done).and_then(|task| {
+                view.map.tasks.get(task.task as usize).map(|station| (task.task, station.pos))
+            });
+
+            if let Some((task, pos)) = next_task {
+                if within_interact_distance(view.pos, pos) {
+                    return BotOutput::action(BotAction::CompleteTask(task));
+                }
+
+                self.destination = Some(pos);
+            }
+        }
+
+        self.walk(view, rng)
+    }
+}
+
+impl WanderingBot {
+    fn impostor_action(&mut self, view: &BotView, rng: &mut StdRng) -> Option<BotOutput> {
+        let mut victims: Vec<_> = view
+            .visible_players
+            .iter()
+            .filter(|player| !player.teammate && player.player_state == PlayerState::Alive)
+            .collect();
+        victims.sort_by(|a, b| a.pos.distance(view.pos).total_cmp(&b.pos.distance(view.pos)));
+
+        if let Some(victim) = victims.first() {
+            if !view.can_kill {
+                return None;
+            }
+
+            // Nobody else around to see it.
+            if victims.len() == 1 && victim.pos.distance(view.pos) <= view.settings.kill_distance {
+                return Some(BotOutput::action(BotAction::Kill(victim.id)));
+            }
+
+            self.destination = Some(victim.pos);
+            return None;
+        }
+
+        if !view.can_vent || !rng.gen_bool(VENT_CHANCE) {
+            return None;
+        }
+
+        view.map
+            .vents
+            .iter()
+            .position(|vent| within_interact_distance(view.pos, vent.pos))
+            .map(|vent| BotOutput::action(BotAction::EnterVent(vent as u8)))
+    }
+
+    fn walk(&mut self, view: &BotView, rng: &mut StdRng) -> BotOutput {
+        match self.last_pos {
+            Some(last_pos) if last_pos.distance(view.pos) < 0.01 => self.stuck_ticks += 1,
+            _ => self.stuck_ticks = 0,
+        }
+        self.last_pos = Some(view.pos);
+
+        let arrived = match self.destination {
+            Some(destination) => destination.distance(view.pos) < ARRIVED_DISTANCE,
+            None => true,
+        };
+
+        if arrived || self.stuck_ticks > STUCK_TICKS {
+            self.stuck_ticks = 0;
+            self.destination = random_room_centre(view.map, rng);
+        }
+
+        match self.destination {
+            Some(destination) => BotOutput::towards(
+                view.pos,
+                next_waypoint(view.map, view.closed_doors, view.pos, destination),
+            ),
+            None => BotOutput::default(),
+        }
+    }
+}
+
+fn random_room_centre(map: &Map, rng: &mut StdRng) -> Option<Vec2> {
+    if map.rooms.is_empty() {
+        return None;
+    }
+
+    let room = &map.rooms[rng.gen_range(0..map.rooms.len())];
+    Some((room.min + room.max) / 2.0)
+}
+
+// Whether a bot can walk straight from `from` to `to` without catching on the
+// end of a wall as it goes through a doorway.
+fn clear_path(map: &Map, closed_doors: &ClosedDoors, from: Vec2, to: Vec2) -> bool {
+    map.line_of_sight(closed_doors, from, to)
+        && map.blocking_segments(closed_doors).all(|(start, end)| {
+            distance_to_segment(start, from, to) > PLAYER_RADIUS
+                && distance_to_segment(end, from, to) > PLAYER_RADIUS
+        })
+}
+
+// Rooms only connect through doorways, so a bot that can't see where it's going
+// takes the shortest route through the open ones. There are only a handful, so
+// this searches all of them every time.
+fn next_waypoint(map: &Map, closed_doors: &ClosedDoors, pos: Vec2, destination: Vec2) -> Vec2 {
+    if clear_path(map, closed_doors, pos, destination) {
+        return destination;
+    }
+
+    // The destination comes first, then every open doorway the bot isn't
+    // already standing in.
+    let mut points = vec![destination];
+    points.extend(
+        map.doors
+            .iter()
+            .filter(|door| !closed_doors.is_closed(door.group))
+            .map(|door| (door.start + door.end) / 2.0)
+            .filter(|doorway| doorway.distance(pos) > ARRIVED_DISTANCE),
+    );
+
+    // How far each point is from the destination, going through doorways.
+    let mut distances = vec![f32::INFINITY; points.len()];
+    let mut visited = vec![false; points.len()];
+    distances[0] = 0.0;
+
+    while let Some(next) = (0..points.len())
+        .filter(|&i| !visited[i] && distances[i].is_finite())
+        .min_by(|&a, &b| distances[a].total_cmp(&distances[b]))
+    {
+        visited[next] = true;
+
+        for i in 0..points.len() {
+            if !visited[i] && clear_path(map, closed_doors, points[next], points[i]) {
+                let distance = distances[next] + points[next].distance(points[i]);
+                distances[i] = distances[i].min(distance);
+            }
+        }
+    }
+
+    (1..points.len())
+        .filter(|&i| distances[i].is_finite() && clear_path(map, closed_doors, pos, points[i]))
+        .min_by(|&a, &b| {
+            let cost = |i: usize| pos.distance(points[i]) + distances[i];
+            cost(a).total_cmp(&cost(b))
+        })
+        .map_or(destination, |i| points[i])
+}
@@ -1,11 +1,65 @@
+use crate::bots::BotBrain;
+use std::{collections::VecDeque, net::SocketAddr, time::Duration};
 use sus_common::{
     components::player::{
         LastInputCounter, PlayerId, PlayerName, PlayerNetworkAddr, PositionHistory,
         UnprocessedInputs,
     },
-    simple_game::bevy::{bevy_ecs, Bundle, Transform},
+    network::{ConsoleKind, TaskProgress},
+    protocol::Capabilities,
+    simple_game::{
+        bevy::{bevy_ecs, Bundle, Component, Transform},
+        glam::Vec3,
+    },
+    PlayerState, PlayerType,
 };
 
+#[derive(Debug, Default, Component)]
+pub struct Tasks(pub Vec<TaskProgress>);
+
+// Which vent an impostor is hiding in, if any, and how long until they can
+// enter one again after climbing out.
+#[derive(Debug, Default, Component)]
+pub struct Venting {
+    pub vent: Option<u8>,
+    pub cooldown: Duration,
+}
+
+// How long until an impostor can kill again.
+#[derive(Debug, Default, Component)]
+pub struct KillCooldown(pub Duration);
+
+// The console a player is looking at, if any.
+#[derive(Debug, Default, Component)]
+pub struct UsingConsole(pub Option<ConsoleKind>);
+
+// The optional protocol features agreed with a player's client when they connected.
+#[derive(Debug, Default, Component)]
+pub struct PlayerCapabilities(pub Capabilities);
+
+// Whether a player has said they're ready to start, in the lobby.
+#[derive(Debug, Default, Component)]
+pub struct Ready(pub bool);
+
+// How long the player's client took to answer the last ping it answered, if
+// it answers them at all.
+#[derive(Debug, Default, Component)]
+pub struct Ping(pub Option<Duration>);
+
+// A player played by the server. Bots never have a client, so they keep their
+// own input counter.
+#[derive(Component)]
+pub struct Bot {
+    pub brain: Box<dyn BotBrain>,
+    pub input_counter: u16,
+}
+
+impl Bot {
+    pub fn new(brain: impl BotBrain) -> Self {
+        Self { brain: Box::new(brain), input_counter: 0 }
+    }
+}
+
 #[derive(Debug, Bundle)]
 pub struct ServerPlayerBundle {
     pub id: PlayerId,
@@ -15,4 +69,37 @@ pub struct ServerPlayerBundle {
     pub position_history: PositionHistory,
     pub last_input_counter: LastInputCounter,
     pub transform: Transform,
+    pub player_type: PlayerType,
+    pub player_state: PlayerState,
+    pub tasks: Tasks,
+    pub venting: Venting,
+    pub kill_cooldown: KillCooldown,
+    pub using_console: UsingConsole,
+    pub ready: Ready,
+    pub capabilities: PlayerCapabilities,
+    pub ping: Ping,
+}
+
+impl ServerPlayerBundle {
+    // A fresh player standing at the spawn point.
+    pub fn new(id: u16, name: String, addr: SocketAddr, capabilities: Capabilities) -> Self {
+        Self {
+            id: PlayerId(id),
+            name: PlayerName(name),
+            network_addr: PlayerNetworkAddr(addr),
+            unprocessed_inputs: UnprocessedInputs(VecDeque::new()),
+            position_history: PositionHistory(Vec::new()),
+            last_input_counter: LastInputCounter(0),
+            transform: Transform::from_translation(Vec3::ZERO),
+            player_type: PlayerType::Crew,
+            player_state: PlayerState::Alive,
+            tasks: Tasks::default(),
+            venting: Venting::default(),
+            kill_cooldown: KillCooldown::default(),
+            using_console: UsingConsole::default(),
+            ready: Ready::default(),
+            capabilities: PlayerCapabilities(capabilities),
+            ping: Ping::default(),
+        }
+    }
 }
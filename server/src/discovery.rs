@@ -0,0 +1,44 @@
+use crate::{resources::ServerName, rooms::Rooms};
+use std::net::SocketAddr;
+use sus_common::{
+    discovery::{DiscoveryResponder, DISCOVERY_PORT},
+    simple_game::bevy::{App, NonSend, Plugin, Res},
+    tracing::{info, warn},
+};
+
+// Answers LAN discovery probes so clients can find this server without being
+// told its address.
+pub struct DiscoveryPlugin;
+
+impl Plugin for DiscoveryPlugin {
+    fn build(&self, app: &mut App) {
+        let bind_addr = SocketAddr::from(([0, 0, 0, 0], DISCOVERY_PORT));
+
+        match DiscoveryResponder::bind(bind_addr) {
+            Ok(responder) => {
+                info!(addr = %bind_addr, "Answering discovery probes");
+
+                app.insert_non_send_resource(DiscoveryResponderSocket(responder))
+                    .add_system(answer_discovery_probes);
+            },
+            Err(e) => {
+                // Not being discoverable shouldn't stop people joining directly.
+                warn!(error = ?e, "Couldn't bind discovery socket, LAN discovery is disabled");
+            },
+        }
+    }
+}
+
+struct DiscoveryResponderSocket(DiscoveryResponder);
+
+fn answer_discovery_probes(
+    responder: NonSend<DiscoveryResponderSocket>,
+    server_name: Res<ServerName>,
+    rooms: NonSend<Rooms>,
+) {
+    let answered = responder.0.answer_probes(|| rooms.server_info(&server_name.0));
+
+    if let Err(e) = answered {
+        warn!(error = ?e, "Failed to answer discovery probe");
+    }
+}
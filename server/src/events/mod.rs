@@ -1,18 +1,148 @@
 use crate::systems::network::PacketDestination;
+use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
-use sus_common::network::{ConnectPacket, DeliveryType, PlayerInputPacket, ServerToClient};
+use sus_common::{
+    network::{
+        ConnectPacket, ConsoleKind, DeliveryType, DisconnectReason, HoldReactorPacket,
+        OxygenCodePacket, PlayerInputPacket, SabotageKind, ServerToClient,
+    },
+    settings::GameSettings,
+    GameState, PlayerType,
+};
 
 pub struct NewPlayer {
     pub addr: SocketAddr,
     pub connect_packet: ConnectPacket,
 }
 
+pub struct NewSpectator {
+    pub addr: SocketAddr,
+    pub connect_packet: ConnectPacket,
+}
+
+// A player or bot is gone, and their entity should go with them. Whoever sends
+// this has already forgotten their address.
+#[derive(Debug)]
+pub struct PlayerLeft {
+    pub id: u16,
+}
+
 #[derive(Debug)]
 pub struct PlayerInput {
     pub id: u16,
     pub input: PlayerInputPacket,
 }
 
+#[derive(Debug)]
+pub struct CompleteTask {
+    pub id: u16,
+    pub task: u8,
+}
+
+#[derive(Debug)]
+pub struct StartSabotage {
+    pub id: u16,
+    pub kind: SabotageKind,
+}
+
+#[derive(Debug)]
+pub enum SabotageFix {
+    ToggleLightSwitch(u8),
+    Comms,
+    HoldReactor(HoldReactorPacket),
+    EnterOxygenCode(OxygenCodePacket),
+}
+
+#[derive(Debug)]
+pub struct FixSabotage {
+    pub id: u16,
+    pub fix: SabotageFix,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum VentAction {
+    Enter(u8),
+    Move(u8),
+    Exit,
+}
+
+#[derive(Debug)]
+pub struct UseVent {
+    pub id: u16,
+    pub action: VentAction,
+}
+
+#[derive(Debug)]
+pub struct CloseDoors {
+    pub id: u16,
+    pub group: u8,
+}
+
+#[derive(Debug)]
+pub struct Kill {
+    pub id: u16,
+    pub target: u16,
+}
+
+#[derive(Debug)]
+pub struct Chat {
+    pub id: u16,
+    pub message: String,
+}
+
+// `kind` is None when the player walks away from the console.
+#[derive(Debug)]
+pub struct UseConsole {
+    pub id: u16,
+    pub kind: Option<ConsoleKind>,
+}
+
+#[derive(Debug)]
+pub struct UpdateSettings {
+    pub id: u16,
+    pub settings: GameSettings,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum LobbyAction {
+    SetReady(bool),
+    Start,
+    CancelStart,
+    AddBot,
+    RemoveBot,
+}
+
+#[derive(Debug)]
+pub struct LobbyRequest {
+    pub id: u16,
+    pub action: LobbyAction,
+}
+
+#[derive(Debug)]
+pub struct Pong {
+    pub id: u16,
+    pub nonce: u16,
+}
+
+// Something the server operator asked a room to do. These are recorded along
+// with everything players send, so replays see them too.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AdminAction {
+    Kick { id: u16, reason: DisconnectReason },
+    SetState(GameState),
+    UpdateSettings(GameSettings),
+    Announce(String),
+    // Tells everyone in the room, spectators too, that the server is going
+    // away and why.
+    ShutDown(String),
+    RemoveSpectator { addr: SocketAddr, reason: DisconnectReason },
+}
+
+#[derive(Debug)]
+pub struct GameOver {
+    pub winner: PlayerType,
+}
+
 pub struct OutgoingPacket {
     pub destination: PacketDestination,
     pub packet: ServerToClient,
@@ -1,20 +1,39 @@
-use crate::systems::{LobbyPlugin, ServerNetworkPlugin};
-use std::time::Duration;
+use crate::{
+    admin::AdminConsolePlugin,
+    discovery::DiscoveryPlugin,
+    master_server::MasterServerPlugin,
+    metrics::MetricsPlugin,
+    recording::Recording,
+    resources::ServerName,
+    rooms::{replay, RoomsPlugin},
+    shutdown::ShutdownPlugin,
+};
+use std::{path::Path, time::Duration};
 use sus_common::{
+    components::player::PlayerId,
+    logging::{init_logging, LogFormat},
     simple_game::bevy::{
-        App, FixedTime, HeadlessBevyGame, ScheduleRunnerPlugin, ScheduleRunnerSettings,
-        SimpleGamePlugin,
+        App, HeadlessBevyGame, ScheduleRunnerPlugin, ScheduleRunnerSettings, SimpleGamePlugin,
+        Transform,
     },
-    GameState,
 };
 
+mod admin;
+mod bans;
+mod bots;
 mod components;
+mod discovery;
 mod events;
+mod master_server;
+mod metrics;
+mod recording;
 mod resources;
+mod rooms;
+mod shutdown;
 mod systems;
 
 pub const TICK_RATE_HZ: usize = 10;
-pub const MAX_PLAYERS: usize = 16;
+const DEFAULT_SERVER_NAME: &str = "Sus Server";
 
 pub struct SusServer;
 
@@ -26,21 +45,79 @@ impl HeadlessBevyGame for SusServer {
     fn init_systems() -> App {
         let mut ecs_world_builder = App::new();
 
+        let server_name =
+            std::env::var("SUS_SERVER_NAME").unwrap_or_else(|_| DEFAULT_SERVER_NAME.to_string());
+
         ecs_world_builder
             .add_plugin(SimpleGamePlugin)
-            .insert_resource(FixedTime::new_from_secs(1.0 / Self::desired_fps() as f32))
-            .add_state::<GameState>()
             .insert_resource(ScheduleRunnerSettings::run_loop(Duration::from_secs_f64(
                 1.0 / TICK_RATE_HZ as f64,
             )))
-            .add_plugin(ScheduleRunnerPlugin::default())
-            .add_plugin(ServerNetworkPlugin)
-            .add_plugin(LobbyPlugin::new(Self::desired_fps()));
+            .add_plugin(ScheduleRunnerPlugin)
+            .insert_resource(ServerName(server_name))
+            .add_plugin(RoomsPlugin)
+            .add_plugin(DiscoveryPlugin)
+            .add_plugin(MasterServerPlugin)
+            .add_plugin(MetricsPlugin)
+            .add_plugin(AdminConsolePlugin)
+            .add_plugin(ShutdownPlugin);
 
         ecs_world_builder
     }
 }
 
+// Re-runs a recorded match without opening any sockets and reports where
+// everyone ended up.
+fn replay_match(path: &Path) {
+    let recording = match Recording::read(path) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("Couldn't read recording {:?}: {:?}", path, e);
+            std::process::exit(1);
+        },
+    };
+
+    let room_code = recording.header.room_code.clone();
+    let simulated: Duration = recording.frames.iter().map(|frame| frame.delta).sum();
+    let mut updates = 0;
+    let mut packets_sent = 0;
+    let mut positions = vec![];
+
+    replay(recording, |world, packets| {
+        updates += 1;
+        packets_sent += packets.len();
+
+        positions = world
+            .query::<(&PlayerId, &Transform)>()
+            .iter(world)
+            .map(|(id, transform)| (id.0, transform.translation))
+            .collect();
+    });
+
+    println!(
+        "Replayed {} updates of room {} ({:?} simulated), {} packets sent",
+        updates, room_code, simulated, packets_sent
+    );
+
+    positions.sort_by_key(|(id, _)| *id);
+
+    for (id, position) in positions {
+        println!("Player {}: ({:.2}, {:.2})", id, position.x, position.y);
+    }
+}
+
 fn main() {
-    sus_common::simple_game::bevy::run_headless_bevy_game::<SusServer>();
+    // SUS_LOG_FORMAT=json gives a JSON object per line instead.
+    let log_format = match std::env::var("SUS_LOG_FORMAT").as_deref() {
+        Ok("json") => LogFormat::Json,
+        _ => LogFormat::Text,
+    };
+    init_logging("info", log_format);
+
+    let mut args = std::env::args().skip(1);
+
+    match (args.next().as_deref(), args.next()) {
+        (Some("--replay"), Some(path)) => replay_match(Path::new(&path)),
+        _ => sus_common::simple_game::bevy::run_headless_bevy_game::<SusServer>(),
+    }
 }
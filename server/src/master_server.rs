@@ -0,0 +1,83 @@
+use crate::{resources::ServerName, rooms::Rooms};
+use std::{
+    net::{SocketAddr, UdpSocket},
+    time::Instant,
+};
+use sus_common::{
+    cookie::Cookie,
+    master_server::{receive_challenge, send_heartbeat, HEARTBEAT_INTERVAL},
+    simple_game::bevy::{App, Local, NonSend, NonSendMut, Plugin, Res},
+    tracing::{debug, info, warn},
+};
+
+// Registers this server with a master server so it shows up in server
+// browsers outside the LAN. Only enabled when SUS_MASTER_SERVER is set to the
+// master server's address.
+pub struct MasterServerPlugin;
+
+impl Plugin for MasterServerPlugin {
+    fn build(&self, app: &mut App) {
+        let master_addr = match std::env::var("SUS_MASTER_SERVER") {
+            Ok(addr) => {
+                addr.parse().expect("SUS_MASTER_SERVER should be an address like 1.2.3.4:7602")
+            },
+            Err(_) => return,
+        };
+
+        let socket = UdpSocket::bind(("0.0.0.0", 0)).expect("Couldn't bind heartbeat socket");
+        socket.set_nonblocking(true).expect("Couldn't make the heartbeat socket non-blocking");
+
+        info!(addr = %master_addr, "Sending heartbeats to the master server");
+
+        app.insert_non_send_resource(MasterServer { socket, master_addr, cookie: [0; 16] })
+            .add_system(send_heartbeats);
+    }
+}
+
+struct MasterServer {
+    socket: UdpSocket,
+    master_addr: SocketAddr,
+    // From the master server's last challenge. Until there's been one, the
+    // heartbeats we send only get a challenge back.
+    cookie: Cookie,
+}
+
+fn send_heartbeats(
+    mut last_heartbeat: Local<Option<Instant>>,
+    mut master_server: NonSendMut<MasterServer>,
+    server_name: Res<ServerName>,
+    rooms: NonSend<Rooms>,
+) {
+    // A new cookie means the last heartbeat wasn't accepted, so send another
+    // straight away instead of waiting out the interval.
+    match receive_challenge(&master_server.socket, master_server.master_addr) {
+        Ok(Some(cookie)) => {
+            debug!("Master server sent a new cookie");
+            master_server.cookie = cookie;
+            *last_heartbeat = None;
+        },
+        Ok(None) => {},
+        Err(e) => warn!(error = ?e, "Failed to receive from the master server"),
+    }
+
+    let now = Instant::now();
+
+    if matches!(*last_heartbeat, Some(last) if now.duration_since(last) < HEARTBEAT_INTERVAL) {
+        return;
+    }
+
+    *last_heartbeat = Some(now);
+
+    let info = rooms.server_info(&server_name.0);
+    let sent = send_heartbeat(
+        &master_server.socket,
+        master_server.master_addr,
+        info,
+        rooms.codes(),
+        master_server.cookie,
+    );
+
+    if let Err(e) = sent {
+        warn!(error = ?e, "Failed to send heartbeat to the master server");
+    }
+}
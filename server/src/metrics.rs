@@ -0,0 +1,427 @@
+use crate::{
+    components::{Bot, Ping},
+    resources::{AddrToPlayer, DecodeViolations, Spectators},
+    rooms::{update_rooms, Rooms},
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use sus_common::{
+    components::player::{PlayerId, UnprocessedInputs},
+    laminar::Packet,
+    network::stream_name,
+    simple_game::bevy::{
+        bevy_ecs, schedule::State, App, IntoSystemConfig, NonSendMut, Plugin, Res, ResMut,
+        Resource, Without,
+    },
+    tracing::{debug, info, warn},
+    GameState,
+};
+
+// Serves what the server is up to in the Prometheus text format, for watching
+// playtests and catching regressions. Only local connections can reach it by
+// default, set SUS_METRICS_ADDR to listen somewhere else.
+pub struct MetricsPlugin;
+
+impl Plugin for MetricsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Metrics>();
+
+        let bind_addr = std::env::var("SUS_METRICS_ADDR")
+            .ok()
+            .and_then(|addr| addr.parse().ok())
+            .unwrap_or_else(|| SocketAddr::from(([127, 0, 0, 1], METRICS_PORT)));
+
+        match TcpListener::bind(bind_addr) {
+            Ok(listener) => {
+                info!(addr = %bind_addr, "Serving metrics");
+
+                let endpoint = MetricsEndpoint::default();
+                let text = endpoint.0.clone();
+                std::thread::spawn(move || serve(listener, &text));
+
+                app.insert_resource(endpoint).add_system(publish_metrics.after(update_rooms));
+            },
+            Err(e) => {
+                warn!(
+                    addr = %bind_addr,
+                    error = ?e,
+                    "Couldn't bind metrics socket, metrics are disabled"
+                );
+            },
+        }
+    }
+}
+
+pub const METRICS_PORT: u16 = 7603;
+
+// Generous for a scraper on the same machine, but stops a stuck connection
+// holding up everyone else for long. It covers the whole request, so one
+// trickling in a byte at a time can't keep resetting it.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+// Upper bounds in seconds. A server tick is 100ms, so rooms should stay well
+// under the last one.
+const TICK_BUCKETS: [f64; 9] = [0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25];
+
+#[derive(Debug, Default)]
+struct Histogram {
+    // Observations falling in each bucket, plus one for anything slower.
+    counts: [u64; TICK_BUCKETS.len() + 1],
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        let bucket = TICK_BUCKETS.iter().position(|le| secs <= *le).unwrap_or(TICK_BUCKETS.len());
+
+        self.counts[bucket] += 1;
+        self.sum += secs;
+    }
+
+    fn write(&self, out: &mut String, name: &str) {
+        let mut cumulative = 0;
+
+        for (le, count) in TICK_BUCKETS.iter().zip(self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, le, cumulative);
+        }
+
+        cumulative += self.counts[TICK_BUCKETS.len()];
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, cumulative);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, cumulative);
+    }
+}
+
+// Packets and bytes by stream, as they are on the wire.
+#[derive(Debug, Default)]
+struct Traffic(BTreeMap<&'static str, (u64, u64)>);
+
+impl Traffic {
+    fn add(&mut self, packet: &Packet) {
+        let (packets, bytes) = self.0.entry(stream_name(packet)).or_default();
+        *packets += 1;
+        *bytes += packet.payload().len() as u64;
+    }
+}
+
+// Counts since the server started.
+#[derive(Debug, Default, Resource)]
+pub struct Metrics {
+    room_tick_duration: Histogram,
+    received: Traffic,
+    sent: Traffic,
+    decode_failures: u64,
+}
+
+impl Metrics {
+    pub fn packet_received(&mut self, packet: &Packet) {
+        self.received.add(packet);
+    }
+
+    pub fn packet_sent(&mut self, packet: &Packet) {
+        self.sent.add(packet);
+    }
+
+    fn render(&self, rooms: &[RoomSnapshot]) -> String {
+        let mut out = String::new();
+
+        describe(
+            &mut out,
+            "sus_room_tick_duration_seconds",
+            "histogram",
+            "Time taken by each room update.",
+        );
+        self.room_tick_duration.write(&mut out, "sus_room_tick_duration_seconds");
+
+        for (name, help, traffic, count_bytes) in [
+            ("sus_packets_received_total", "Packets received by stream.", &self.received, false),
+            ("sus_packets_sent_total", "Packets sent by stream.", &self.sent, false),
+            ("sus_bytes_received_total", "Bytes received by stream.", &self.received, true),
+            ("sus_bytes_sent_total", "Bytes sent by stream.", &self.sent, true),
+        ] {
+            describe(&mut out, name, "counter", help);
+
+            for (stream, (packets, bytes)) in &traffic.0 {
+                let value = if count_bytes { bytes } else { packets };
+                let _ = writeln!(out, "{}{{stream=\"{}\"}} {}", name, stream, value);
+            }
+        }
+
+        describe(
+            &mut out,
+            "sus_decode_failures_total",
+            "counter",
+            "Packets that failed to decode or broke a limit.",
+        );
+        let _ = writeln!(out, "sus_decode_failures_total {}", self.decode_failures);
+
+        describe(&mut out, "sus_rooms", "gauge", "Open rooms.");
+        let _ = writeln!(out, "sus_rooms {}", rooms.len());
+
+        describe(&mut out, "sus_players", "gauge", "Connected players by their room's state.");
+
+        for state in [GameState::Lobby, GameState::IntroScreen, GameState::Main, GameState::End] {
+            let players: usize =
+                rooms.iter().filter(|room| room.state == state).map(|room| room.players).sum();
+            let _ = writeln!(out, "sus_players{{state=\"{}\"}} {}", state_name(&state), players);
+        }
+
+        describe(&mut out, "sus_spectators", "gauge", "Connected spectators.");
+        let spectators: usize = rooms.iter().map(|room| room.spectators).sum();
+        let _ = writeln!(out, "sus_spectators {}", spectators);
+
+        describe(&mut out, "sus_player_rtt_seconds", "gauge", "Latest round trip to each player.");
+
+        for room in rooms {
+            for player in &room.player_stats {
+                if let Some(rtt) = player.rtt {
+                    let _ = writeln!(
+                        out,
+                        "sus_player_rtt_seconds{{room=\"{}\",player=\"{}\"}} {}",
+                        room.code,
+                        player.id,
+                        rtt.as_secs_f64()
+                    );
+                }
+            }
+        }
+
+        describe(
+            &mut out,
+            "sus_player_input_queue_depth",
+            "gauge",
+            "Inputs received from each player and not yet applied.",
+        );
+
+        for room in rooms {
+            for player in &room.player_stats {
+                let _ = writeln!(
+                    out,
+                    "sus_player_input_queue_depth{{room=\"{}\",player=\"{}\"}} {}",
+                    room.code, player.id, player.input_queue
+                );
+            }
+        }
+
+        out
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// The same names the admin console uses.
+fn state_name(state: &GameState) -> &'static str {
+    match state {
+        GameState::Lobby => "lobby",
+        GameState::IntroScreen => "intro",
+        GameState::Main => "main",
+        GameState::End => "end",
+    }
+}
+
+// How a room looks right now, for the gauges.
+#[derive(Debug)]
+struct RoomSnapshot {
+    code: String,
+    state: GameState,
+    players: usize,
+    spectators: usize,
+    player_stats: Vec<PlayerStats>,
+}
+
+#[derive(Debug)]
+struct PlayerStats {
+    id: u16,
+    rtt: Option<Duration>,
+    input_queue: usize,
+}
+
+// The latest rendering, read by the thread answering requests.
+#[derive(Debug, Default, Resource)]
+struct MetricsEndpoint(Arc<Mutex<String>>);
+
+fn publish_metrics(
+    mut metrics: ResMut<Metrics>,
+    mut violations: ResMut<DecodeViolations>,
+    mut rooms: NonSendMut<Rooms>,
+    endpoint: Res<MetricsEndpoint>,
+) {
+    metrics.decode_failures += std::mem::take(&mut violations.uncollected);
+
+    let mut snapshots = vec![];
+
+    for (code, room) in rooms.iter_mut() {
+        metrics.room_tick_duration.observe(room.last_update_time());
+
+        let world = room.world_mut();
+        metrics.decode_failures +=
+            std::mem::take(&mut world.resource_mut::<DecodeViolations>().uncollected);
+
+        // Bots are neither connected nor waiting on the network, and anyone
+        // who has just left is still around until the end of the update.
+        let connected: HashSet<u16> =
+            world.resource::<AddrToPlayer>().0.values().copied().collect();
+        let mut players =
+            world.query_filtered::<(&PlayerId, &Ping, &UnprocessedInputs), Without<Bot>>();
+        let mut player_stats: Vec<_> = players
+            .iter(world)
+            .filter(|(id, _, _)| connected.contains(&id.0))
+            .map(|(id, ping, inputs)| PlayerStats {
+                id: id.0,
+                rtt: ping.0,
+                input_queue: inputs.0.len(),
+            })
+            .collect();
+        player_stats.sort_by_key(|player| player.id);
+
+        snapshots.push(RoomSnapshot {
+            code: code.clone(),
+            state: world.resource::<State<GameState>>().0.clone(),
+            players: player_stats.len(),
+            spectators: world.resource::<Spectators>().0.len(),
+            player_stats,
+        });
+    }
+
+    snapshots.sort_by(|a, b| a.code.cmp(&b.code));
+
+    let text = metrics.render(&snapshots);
+
+    if let Ok(mut published) = endpoint.0.lock() {
+        *published = text;
+    }
+}
+
+fn serve(listener: TcpListener, text: &Mutex<String>) {
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| answer(stream, text));
+
+        if let Err(e) = result {
+            debug!(error = ?e, "Failed to answer a metrics request");
+        }
+    }
+}
+
+// Just enough HTTP for a scraper or curl. Every connection gets one response
+// and is then closed.
+fn answer(mut stream: TcpStream, text: &Mutex<String>) -> io::Result<()> {
+    let deadline = Instant::now() + REQUEST_TIMEOUT;
+
+    let mut reader =
+        BufReader::new(UntilDeadline { stream: &stream, deadline }.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // The headers don't matter, but closing before they're read could cut off
+    // the response.
+    let mut header = String::new();
+
+    while reader.read_line(&mut header)? > 0 && !header.trim().is_empty() {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some("/metrics")) => {
+            ("200 OK", text.lock().map(|text| text.clone()).unwrap_or_default())
+        },
+        _ => ("404 Not Found", "Metrics are at /metrics\n".to_string()),
+    };
+
+    stream.set_write_timeout(Some(time_left(deadline)?))?;
+
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    )
+}
+
+// Reads from a stream, giving up once `deadline` has passed however much has
+// arrived so far.
+struct UntilDeadline<'a> {
+    stream: &'a TcpStream,
+    deadline: Instant,
+}
+
+impl Read for UntilDeadline<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.stream.set_read_timeout(Some(time_left(self.deadline)?))?;
+        self.stream.read(buf)
+    }
+}
+
+fn time_left(deadline: Instant) -> io::Result<Duration> {
+    match deadline.saturating_duration_since(Instant::now()) {
+        left if left.is_zero() => Err(io::ErrorKind::TimedOut.into()),
+        left => Ok(left),
+    }
+}
+
+#[test]
+fn test_render_metrics() {
+    use sus_common::network::INPUT_STREAM;
+
+    let mut metrics = Metrics::default();
+    metrics.room_tick_duration.observe(Duration::from_micros(800));
+    metrics.room_tick_duration.observe(Duration::from_millis(300));
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 9000));
+    metrics.packet_received(&Packet::unreliable_sequenced(addr, vec![0; 10], Some(INPUT_STREAM)));
+    metrics.packet_received(&Packet::unreliable_sequenced(addr, vec![0; 12], Some(INPUT_STREAM)));
+    metrics.packet_sent(&Packet::unreliable(addr, vec![0; 4]));
+
+    let rooms = [RoomSnapshot {
+        code: "ABCD".to_string(),
+        state: GameState::Main,
+        players: 2,
+        spectators: 1,
+        player_stats: vec![
+            PlayerStats { id: 0, rtt: Some(Duration::from_millis(40)), input_queue: 3 },
+            PlayerStats { id: 1, rtt: None, input_queue: 0 },
+        ],
+    }];
+
+    let text = metrics.render(&rooms);
+    let lines: Vec<_> = text.lines().collect();
+
+    for expected in [
+        "sus_room_tick_duration_seconds_bucket{le=\"0.0005\"} 0",
+        "sus_room_tick_duration_seconds_bucket{le=\"0.001\"} 1",
+        "sus_room_tick_duration_seconds_bucket{le=\"0.25\"} 1",
+        "sus_room_tick_duration_seconds_bucket{le=\"+Inf\"} 2",
+        "sus_room_tick_duration_seconds_count 2",
+        "sus_packets_received_total{stream=\"input\"} 2",
+        "sus_bytes_received_total{stream=\"input\"} 22",
+        "sus_bytes_sent_total{stream=\"none\"} 4",
+        "sus_players{state=\"main\"} 2",
+        "sus_players{state=\"lobby\"} 0",
+        "sus_spectators 1",
+        "sus_player_rtt_seconds{room=\"ABCD\",player=\"0\"} 0.04",
+        "sus_player_input_queue_depth{room=\"ABCD\",player=\"0\"} 3",
+        "sus_player_input_queue_depth{room=\"ABCD\",player=\"1\"} 0",
+    ] {
+        assert!(lines.contains(&expected), "{} missing from\n{}", expected, text);
+    }
+
+    // No round trip measured yet means no sample, rather than a zero.
+    assert!(!lines
+        .iter()
+        .any(|line| line.starts_with("sus_player_rtt_seconds{room=\"ABCD\",player=\"1\"}")));
+}
@@ -0,0 +1,152 @@
+use crate::events::AdminAction;
+use bincode::Options;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    net::SocketAddr,
+    path::Path,
+    time::Duration,
+};
+use sus_common::laminar::{Packet, SocketEvent};
+
+// Match recordings hold everything a room's simulation depends on: the RNG
+// seed, how much time passed between updates and every event and admin action
+// handed to the room before each update. Feeding them back into a fresh room
+// reproduces the match exactly.
+pub const RECORDING_EXTENSION: &str = "susrec";
+const MAGIC: [u8; 4] = *b"SUSR";
+const FORMAT_VERSION: u16 = 4;
+// Far more than a room is handed between two updates, so a corrupt length
+// can't have a replay try to allocate without end.
+const MAX_FRAME_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    magic: [u8; 4],
+    version: u16,
+    pub room_code: String,
+    pub seed: u64,
+    pub spectator_delay: Duration,
+}
+
+impl RecordingHeader {
+    pub fn new(room_code: &str, seed: u64, spectator_delay: Duration) -> Self {
+        Self {
+            magic: MAGIC,
+            version: FORMAT_VERSION,
+            room_code: room_code.to_string(),
+            seed,
+            spectator_delay,
+        }
+    }
+}
+
+// A socket event as the room received it, after decryption.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum RecordedEvent {
+    Connect(SocketAddr),
+    Packet(SocketAddr, Vec<u8>),
+    Timeout(SocketAddr),
+    Disconnect(SocketAddr),
+}
+
+impl RecordedEvent {
+    pub fn new(event: &SocketEvent) -> Self {
+        match event {
+            SocketEvent::Connect(addr) => Self::Connect(*addr),
+            SocketEvent::Packet(packet) => Self::Packet(packet.addr(), packet.payload().to_vec()),
+            SocketEvent::Timeout(addr) => Self::Timeout(*addr),
+            SocketEvent::Disconnect(addr) => Self::Disconnect(*addr),
+        }
+    }
+
+    // Rooms never look at delivery guarantees on incoming packets, so they
+    // aren't recorded.
+    pub fn into_socket_event(self) -> SocketEvent {
+        match self {
+            Self::Connect(addr) => SocketEvent::Connect(addr),
+            Self::Packet(addr, payload) => SocketEvent::Packet(Packet::unreliable(addr, payload)),
+            Self::Timeout(addr) => SocketEvent::Timeout(addr),
+            Self::Disconnect(addr) => SocketEvent::Disconnect(addr),
+        }
+    }
+}
+
+// One room update.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub delta: Duration,
+    pub events: Vec<RecordedEvent>,
+    pub admin_actions: Vec<AdminAction>,
+}
+
+// Varints keep the per-frame overhead down to a few bytes.
+fn recording_options() -> impl Options {
+    bincode::DefaultOptions::new()
+}
+
+fn to_io_error(error: bincode::ErrorKind) -> io::Error {
+    match error {
+        bincode::ErrorKind::Io(e) => e,
+        e => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+// Appends frames to a recording file as the match goes.
+pub struct Recorder {
+    writer: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &RecordingHeader) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        recording_options().serialize_into(&mut writer, header).map_err(|e| to_io_error(*e))?;
+
+        Ok(Self { writer })
+    }
+
+    pub fn write_frame(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        recording_options().serialize_into(&mut self.writer, frame).map_err(|e| to_io_error(*e))
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub header: RecordingHeader,
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let header: RecordingHeader = recording_options()
+            .with_limit(MAX_FRAME_SIZE)
+            .deserialize_from(&mut reader)
+            .map_err(|e| to_io_error(*e))?;
+
+        if header.magic != MAGIC || header.version != FORMAT_VERSION {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Not a supported recording"));
+        }
+
+        let mut frames = vec![];
+
+        // A server that was killed mid-frame leaves a partial frame at the
+        // end, which is an error rather than something to guess around.
+        while !reader.fill_buf()?.is_empty() {
+            frames.push(
+                recording_options()
+                    .with_limit(MAX_FRAME_SIZE)
+                    .deserialize_from(&mut reader)
+                    .map_err(|e| to_io_error(*e))?,
+            );
+        }
+
+        Ok(Self { header, frames })
+    }
+}
@@ -1,5 +1,161 @@
-use std::{collections::HashMap, net::SocketAddr};
-use sus_common::simple_game::bevy::{bevy_ecs, Resource};
+use crate::bans::BanList;
+use rand::rngs::StdRng;
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+use sus_common::{
+    protocol::DecodeError,
+    simple_game::bevy::{bevy_ecs, Resource},
+    tracing::warn,
+};
 
 #[derive(Debug, Resource)]
 pub struct AddrToPlayer(pub HashMap<SocketAddr, u16>);
+
+// Everyone watching this room. They have no player entity, and nothing they
+// send besides connecting is accepted.
+#[derive(Debug, Default, Resource)]
+pub struct Spectators(pub BTreeSet<SocketAddr>);
+
+// How far behind the game spectators see it, so watching can't be used to
+// feed information to someone who's playing.
+#[derive(Debug, Default, Resource)]
+pub struct SpectatorDelay(pub Duration);
+
+// The IDs of the bots in this room, oldest first.
+#[derive(Debug, Default, Resource)]
+pub struct Bots(pub Vec<u16>);
+
+// After the first bad packet from an address is logged, the rest are only
+// counted and summed up this often, so a flood of them can't flood the log.
+pub const VIOLATION_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub struct AddrViolations {
+    pub count: u32,
+    // Violations since the last time this address was logged.
+    pub unlogged: u32,
+    pub last_logged: Instant,
+}
+
+// How many packets from each address failed to decode or broke a limit.
+#[derive(Debug, Default, Resource)]
+pub struct DecodeViolations {
+    pub by_addr: HashMap<SocketAddr, AddrViolations>,
+    // Every violation since the metrics last collected them.
+    pub uncollected: u64,
+}
+
+impl DecodeViolations {
+    pub fn record(&mut self, addr: SocketAddr, error: &DecodeError, now: Instant) {
+        self.uncollected += 1;
+
+        match self.by_addr.get_mut(&addr) {
+            None => {
+                self.by_addr
+                    .insert(addr, AddrViolations { count: 1, unlogged: 0, last_logged: now });
+                warn!(%addr, ?error, "Dropped a bad packet");
+            },
+            Some(violations) => {
+                violations.count += 1;
+                violations.unlogged += 1;
+
+                if now.saturating_duration_since(violations.last_logged) >= VIOLATION_LOG_INTERVAL {
+                    warn!(
+                        %addr,
+                        dropped = violations.unlogged,
+                        total = violations.count,
+                        ?error,
+                        "Still dropping bad packets"
+                    );
+                    violations.unlogged = 0;
+                    violations.last_logged = now;
+                }
+            },
+        }
+    }
+
+    // Addresses that have gone quiet are forgotten, after logging anything
+    // not yet logged, so spoofed senders don't pile up here forever.
+    pub fn forget_quiet(&mut self, now: Instant) {
+        self.by_addr.retain(|addr, violations| {
+            if now.saturating_duration_since(violations.last_logged) < VIOLATION_LOG_INTERVAL {
+                return true;
+            }
+
+            if violations.unlogged > 0 {
+                warn!(
+                    %addr,
+                    dropped = violations.unlogged,
+                    total = violations.count,
+                    "Dropped bad packets"
+                );
+            }
+
+            false
+        });
+    }
+}
+
+// Who isn't allowed into any room.
+#[derive(Debug, Default, Resource)]
+pub struct Bans(pub BanList);
+
+// Addresses to forget about once the packets already queued for them have
+// gone out, so someone being kicked still hears why.
+#[derive(Debug, Default, Resource)]
+pub struct Disconnecting(pub Vec<SocketAddr>);
+
+// What this server calls itself in discovery replies and the master server list.
+#[derive(Debug, Resource)]
+pub struct ServerName(pub String);
+
+// Updates the room has had so far, one per server tick.
+#[derive(Debug, Default, Resource)]
+pub struct RoomTick(pub u64);
+
+// The join code of the room this world is running.
+#[derive(Debug, Resource)]
+pub struct RoomCode(pub String);
+
+// The player allowed to change the settings and start the game.
+#[derive(Debug, Default, Resource)]
+pub struct Host(pub Option<u16>);
+
+// Whether the lights are currently on. Crew vision shrinks while they're out.
+#[derive(Debug, Resource)]
+pub struct LightsOn(pub bool);
+
+// All game logic randomness (roles, tasks, sabotages) goes through this.
+#[derive(Debug, Resource)]
+pub struct GameRng(pub StdRng);
+
+// Bots draw from their own generator, so adding one doesn't change how roles,
+// tasks or sabotages roll.
+#[derive(Debug, Resource)]
+pub struct BotRng(pub StdRng);
+
+#[test]
+fn test_violations_are_logged_once_per_interval() {
+    let mut violations = DecodeViolations::default();
+    let addr: SocketAddr = "10.0.0.1:5000".parse().unwrap();
+    let now = Instant::now();
+
+    for _ in 0..100 {
+        violations.record(addr, &DecodeError::UnknownMessage(0xffff), now);
+    }
+
+    let logged = &violations.by_addr[&addr];
+    assert_eq!((logged.count, logged.unlogged), (100, 99));
+
+    violations.record(addr, &DecodeError::UnknownMessage(0xffff), now + VIOLATION_LOG_INTERVAL);
+
+    let logged = &violations.by_addr[&addr];
+    assert_eq!((logged.count, logged.unlogged), (101, 0));
+
+    violations.forget_quiet(now + VIOLATION_LOG_INTERVAL * 2);
+    assert!(violations.by_addr.is_empty());
+    assert_eq!(violations.uncollected, 101);
+}
@@ -0,0 +1,1309 @@
+use crate::{
+    bans::{BanList, DEFAULT_BAN_FILE},
+    events::AdminAction,
+    metrics::Metrics,
+    recording::{
+        RecordedEvent, RecordedFrame, Recorder, Recording, RecordingHeader, RECORDING_EXTENSION,
+    },
+    resources::{
+        Bans, BotRng, Bots, DecodeViolations, Disconnecting, GameRng, LightsOn, RoomCode, RoomTick,
+        SpectatorDelay,
+    },
+    systems::{
+        AdminPlugin, BotsPlugin, ChatPlugin, ConsolesPlugin, DoorsPlugin, GamePlugin, GhostsPlugin,
+        LobbyPlugin, MovementPlugin, PingPlugin, SabotagePlugin, ServerNetworkPlugin,
+        SettingsPlugin, SpectatorsPlugin, TasksPlugin, VentsPlugin,
+    },
+    SusServer, TICK_RATE_HZ,
+};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::{
+    collections::{HashMap, HashSet},
+    net::{IpAddr, SocketAddr},
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+use sus_common::{
+    cookie::CookieJar,
+    crossbeam_channel::{self, Receiver, Sender},
+    discovery::{RoomInfo, ServerInfo},
+    encryption::{key_from_hex, key_to_hex, Handshake, ServerKey, Session, WireMessage},
+    laminar::{Config as NetworkConfig, Packet, Socket, SocketEvent},
+    map::Map,
+    network::{
+        decode_client_message, encode_server_message, is_reliable, make_packet, with_payload,
+        ClientToServer, DeliveryType, JoinRoomError, JoinRoomFailedPacket, RoomRequest,
+        ServerToClient, GAME_STATE_STREAM, MAX_PLAYERS,
+    },
+    protocol::SUPPORTED_PROTOCOL,
+    resources::network::{NetRx, NetTx, NetworkThread},
+    simple_game::bevy::{
+        bevy_ecs::{self, schedule::ExecutorKind},
+        bevy_time::TimeUpdateStrategy,
+        App, Commands, CoreSchedule, FixedTime, HeadlessBevyGame, IntoSystemConfig, NonSendMut,
+        Plugin, Res, ResMut, Resource, SimpleGamePlugin, State, Time, World,
+    },
+    tracing::{debug, error, info, info_span},
+    GameState,
+};
+
+pub const GAME_PORT: u16 = 7600;
+const ROOM_CODE_LEN: usize = 4;
+const MAX_SPECTATORS: usize = 8;
+
+// Every room is a whole Bevy app, so nobody gets to open them without limit.
+const MAX_ROOMS: usize = 256;
+const MAX_ROOMS_PER_IP: usize = 4;
+
+// Sessions that have finished the handshake but haven't joined a room yet.
+// Only so many are kept, and only for so long.
+const MAX_PENDING_SESSIONS: usize = 256;
+const PENDING_SESSION_TIMEOUT: Duration = Duration::from_secs(10);
+
+const DEFAULT_SERVER_KEY_FILE: &str = "server.key";
+
+// Owns the server socket and hands every incoming event to the room the
+// sender is playing in. Each room is a separate Bevy app with its own
+// players, settings and game state, which gets updated once per server tick.
+pub struct RoomsPlugin;
+
+impl Plugin for RoomsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_non_send_resource(Rooms(HashMap::new()))
+            .insert_resource(AddrToRoom(HashMap::new()))
+            .insert_resource(RoomCodeRng(StdRng::from_entropy()))
+            .init_resource::<Sessions>()
+            .insert_resource(handshakes_from_env())
+            .insert_resource(RoomPackets::default())
+            .insert_resource(RecordDir(std::env::var_os("SUS_RECORD_DIR").map(PathBuf::from)))
+            .insert_resource(spectator_delay_from_env())
+            .init_resource::<DecodeViolations>()
+            .init_resource::<Metrics>()
+            .insert_resource(bans_from_env())
+            .init_resource::<Disconnecting>()
+            .add_startup_system(setup)
+            .add_system(route_events)
+            .add_system(update_rooms.after(route_events))
+            .add_system(send_room_packets.after(update_rooms))
+            .add_system(forget_disconnected.after(send_room_packets))
+            .add_system(expire_bans);
+    }
+}
+
+pub struct Room {
+    app: App,
+    event_tx: Sender<SocketEvent>,
+    // Whoever asked for the room, which counts towards their limit for as long
+    // as it's open.
+    created_by: Option<IpAddr>,
+    players: HashSet<SocketAddr>,
+    spectators: HashSet<SocketAddr>,
+    // The room's clock only moves when it's told to, so a replay sees exactly
+    // the same time steps as the original match.
+    start: Instant,
+    clock: Duration,
+    recorder: Option<Recorder>,
+    recorded_events: Vec<RecordedEvent>,
+    admin_actions: Vec<AdminAction>,
+    // How long the last update took in real time.
+    last_update_time: Duration,
+}
+
+impl Room {
+    fn new(code: &str, seed: u64, spectator_delay: Duration, net_tx: Sender<Packet>) -> Self {
+        let (event_tx, event_rx) = crossbeam_channel::unbounded();
+
+        let start = Instant::now();
+        let mut app = App::new();
+
+        app.add_plugin(SimpleGamePlugin)
+            .insert_resource(Time::new(start))
+            .insert_resource(TimeUpdateStrategy::ManualInstant(start))
+            .insert_resource(FixedTime::new_from_secs(1.0 / SusServer::desired_fps() as f32))
+            .add_state::<GameState>()
+            .insert_resource(RoomCode(code.to_string()))
+            .init_resource::<RoomTick>()
+            .insert_resource(Map::default())
+            .insert_resource(LightsOn(true))
+            .insert_resource(GameRng(StdRng::seed_from_u64(seed)))
+            .insert_resource(BotRng(StdRng::seed_from_u64(seed.wrapping_add(1))))
+            .insert_resource(SpectatorDelay(spectator_delay))
+            .add_plugin(ServerNetworkPlugin::new(net_tx, event_rx))
+            .add_plugin(SettingsPlugin)
+            .add_plugin(MovementPlugin)
+            .add_plugin(LobbyPlugin::new(SusServer::desired_fps()))
+            .add_plugin(GamePlugin)
+            .add_plugin(TasksPlugin)
+            .add_plugin(SabotagePlugin)
+            .add_plugin(VentsPlugin)
+            .add_plugin(DoorsPlugin)
+            .add_plugin(GhostsPlugin)
+            .add_plugin(ChatPlugin)
+            .add_plugin(ConsolesPlugin)
+            .add_plugin(SpectatorsPlugin)
+            .add_plugin(BotsPlugin)
+            .add_plugin(PingPlugin)
+            .add_plugin(AdminPlugin);
+
+        // Parallel systems could send packets or spawn entities in a different
+        // order from one run to the next.
+        for schedule in [CoreSchedule::Startup, CoreSchedule::Main, CoreSchedule::FixedUpdate] {
+            app.edit_schedule(schedule, |schedule| {
+                schedule.set_executor_kind(ExecutorKind::SingleThreaded);
+            });
+        }
+
+        Self {
+            app,
+            event_tx,
+            created_by: None,
+            players: HashSet::new(),
+            spectators: HashSet::new(),
+            start,
+            clock: Duration::ZERO,
+            recorder: None,
+            recorded_events: vec![],
+            admin_actions: vec![],
+            last_update_time: Duration::ZERO,
+        }
+    }
+
+    // People connected to play, plus any bots the host has added.
+    fn player_count(&self) -> usize {
+        self.players.len() + self.app.world.resource::<Bots>().0.len()
+    }
+
+    pub fn spectators(&self) -> impl Iterator<Item = SocketAddr> + '_ {
+        self.spectators.iter().copied()
+    }
+
+    // Joining is only handled in the lobby, anyone arriving later would wait
+    // for a ConnectAck that never comes.
+    fn in_lobby(&self) -> bool {
+        self.app.world.resource::<State<GameState>>().0 == GameState::Lobby
+    }
+
+    // Starts a recording file for this room in `dir`. Failing to record
+    // shouldn't stop the match from being played.
+    fn record_to(&mut self, dir: &Path, header: &RecordingHeader) {
+        let code = &header.room_code;
+        let started =
+            SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0);
+        let path = dir.join(format!("{}-{}.{}", code, started, RECORDING_EXTENSION));
+
+        match Recorder::create(&path, header) {
+            Ok(recorder) => {
+                info!(room = %code, path = ?path, "Recording room");
+                self.recorder = Some(recorder);
+            },
+            Err(e) => error!(room = %code, path = ?path, error = ?e, "Couldn't record room"),
+        }
+    }
+
+    fn send_event(&mut self, event: SocketEvent) {
+        if self.recorder.is_some() {
+            self.recorded_events.push(RecordedEvent::new(&event));
+        }
+
+        let _ = self.event_tx.send(event);
+    }
+
+    pub fn last_update_time(&self) -> Duration {
+        self.last_update_time
+    }
+
+    // Admin actions are handed over with the next update.
+    pub fn send_admin(&mut self, action: AdminAction) {
+        self.admin_actions.push(action);
+    }
+
+    // For looking around the room. Anything that changes it should go through
+    // `send_admin` so recordings stay complete.
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.app.world
+    }
+
+    // Runs the room's simulation forward by `delta`.
+    fn update(&mut self, delta: Duration) {
+        self.app.world.resource_mut::<RoomTick>().0 += 1;
+
+        // Everything logged during the update says which room and tick it was.
+        let world = &self.app.world;
+        let _span = info_span!(
+            "room",
+            room = %world.resource::<RoomCode>().0,
+            tick = world.resource::<RoomTick>().0,
+            state = ?world.resource::<State<GameState>>().0,
+        )
+        .entered();
+
+        let admin_actions = std::mem::take(&mut self.admin_actions);
+
+        if let Some(recorder) = &mut self.recorder {
+            let frame = RecordedFrame {
+                delta,
+                events: std::mem::take(&mut self.recorded_events),
+                admin_actions: admin_actions.clone(),
+            };
+
+            if let Err(e) = recorder.write_frame(&frame) {
+                error!(error = ?e, "Failed to write to recording, stopping");
+                self.recorder = None;
+            }
+        }
+
+        for action in admin_actions {
+            self.app.world.send_event(action);
+        }
+
+        self.clock += delta;
+        self.app.insert_resource(TimeUpdateStrategy::ManualInstant(self.start + self.clock));
+
+        let started = Instant::now();
+        self.app.update();
+        self.last_update_time = started.elapsed();
+    }
+}
+
+impl Drop for Room {
+    fn drop(&mut self) {
+        if let Some(Err(e)) = self.recorder.as_mut().map(Recorder::flush) {
+            error!(error = ?e, "Failed to finish recording");
+        }
+    }
+}
+
+// Re-runs a recorded match in a fresh room, calling `inspect` after every
+// update with the room's world and the packets it sent during that update.
+pub fn replay(recording: Recording, mut inspect: impl FnMut(&mut World, Vec<Packet>)) {
+    let (net_tx, net_rx) = crossbeam_channel::unbounded();
+    let header = &recording.header;
+    let mut room = Room::new(&header.room_code, header.seed, header.spectator_delay, net_tx);
+
+    for frame in recording.frames {
+        for event in frame.events {
+            room.send_event(event.into_socket_event());
+        }
+
+        for action in frame.admin_actions {
+            room.send_admin(action);
+        }
+
+        room.update(frame.delta);
+        inspect(&mut room.app.world, net_rx.try_iter().collect());
+    }
+}
+
+// Rooms by join code. Apps can't be shared between threads, so this lives
+// outside the normal resources.
+pub struct Rooms(HashMap<String, Room>);
+
+impl Rooms {
+    pub fn get_mut(&mut self, code: &str) -> Option<&mut Room> {
+        self.0.get_mut(code)
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut Room)> {
+        self.0.iter_mut()
+    }
+
+    // Dropping a room finishes off its recording.
+    pub fn close_all(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn codes(&self) -> Vec<String> {
+        self.0.keys().cloned().collect()
+    }
+
+    // What discovery reports about this server.
+    pub fn server_info(&self, name: &str) -> ServerInfo {
+        let rooms = self
+            .0
+            .values()
+            .map(|room| RoomInfo {
+                player_count: room.player_count() as u16,
+                max_players: MAX_PLAYERS as u16,
+                game_state: room.app.world.resource::<State<GameState>>().0.clone(),
+            })
+            .collect();
+
+        let mut info = ServerInfo {
+            name: name.to_string(),
+            protocol: SUPPORTED_PROTOCOL,
+            game_port: GAME_PORT,
+            rooms,
+        };
+        info.limit();
+
+        info
+    }
+}
+
+#[derive(Debug, Resource)]
+struct AddrToRoom(HashMap<SocketAddr, String>);
+
+#[derive(Debug, Resource)]
+struct RoomCodeRng(StdRng);
+
+// Where match recordings go, if they're turned on.
+#[derive(Debug, Resource)]
+struct RecordDir(Option<PathBuf>);
+
+fn spectator_delay_from_env() -> SpectatorDelay {
+    let secs = std::env::var("SUS_SPECTATOR_DELAY_SECS").ok().and_then(|secs| secs.parse().ok());
+    SpectatorDelay(Duration::from_secs(secs.unwrap_or(0)))
+}
+
+// A ban file that can't be read is left alone rather than overwritten, and bans
+// made while running are then forgotten on exit.
+fn bans_from_env() -> Bans {
+    let path =
+        std::env::var_os("SUS_BAN_FILE").map_or(PathBuf::from(DEFAULT_BAN_FILE), PathBuf::from);
+
+    // Starting without them would let banned players straight back in, and
+    // the next ban would save over the file.
+    match BanList::load(path) {
+        Ok(bans) => Bans(bans),
+        Err(e) => {
+            error!("{}, fix or remove the file to start the server", e);
+            std::process::exit(1);
+        },
+    }
+}
+
+// Loads the server's long-term key, or makes one and saves it the first time
+// the server runs. Clients can pin the public half it logs.
+fn handshakes_from_env() -> Handshakes {
+    let path = std::env::var_os("SUS_SERVER_KEY_FILE")
+        .map_or(PathBuf::from(DEFAULT_SERVER_KEY_FILE), PathBuf::from);
+
+    let key = match std::fs::read_to_string(&path) {
+        Ok(text) => match key_from_hex(&text) {
+            Some(secret) => ServerKey::from_bytes(secret),
+            None => {
+                error!(path = %path.display(), "The server key file should hold 64 hex digits");
+                std::process::exit(1);
+            },
+        },
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            let key = ServerKey::generate();
+
+            if let Err(e) = write_secret(&path, &key_to_hex(&key.secret_bytes())) {
+                error!(path = %path.display(), error = %e, "Couldn't save the server key");
+                std::process::exit(1);
+            }
+
+            info!(path = %path.display(), "Made a new server key");
+            key
+        },
+        Err(e) => {
+            error!(path = %path.display(), error = %e, "Couldn't read the server key");
+            std::process::exit(1);
+        },
+    };
+
+    info!(key = %key_to_hex(&key.public()), "Server key, for clients to check with SUS_SERVER_KEY");
+
+    Handshakes { key, cookies: CookieJar::new() }
+}
+
+// Only the server's user gets to read it.
+fn write_secret(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options.open(path)?.write_all(contents.as_bytes())
+}
+
+// What answering a handshake takes: the server's long-term key, and the jar
+// for the cookies a client has to send back before it gets a session.
+#[derive(Resource)]
+struct Handshakes {
+    key: ServerKey,
+    cookies: CookieJar,
+}
+
+// The encrypted session with each address that has completed a handshake.
+// Nothing else from an address is accepted until it has one. A session is
+// pending from the handshake until it joins a room.
+#[derive(Default, Resource)]
+struct Sessions {
+    by_addr: HashMap<SocketAddr, Session>,
+    pending: HashMap<SocketAddr, Instant>,
+}
+
+impl Sessions {
+    fn remove(&mut self, addr: &SocketAddr) {
+        self.by_addr.remove(addr);
+        self.pending.remove(addr);
+    }
+
+    fn expire_pending(&mut self, now: Instant) {
+        let by_addr = &mut self.by_addr;
+
+        self.pending.retain(|addr, started| {
+            let expired = now.duration_since(*started) >= PENDING_SESSION_TIMEOUT;
+
+            if expired {
+                debug!(%addr, "Dropped a session that never joined a room");
+                by_addr.remove(addr);
+            }
+
+            !expired
+        });
+    }
+}
+
+// Rooms send their plaintext packets here, and they're encrypted on the way
+// out to the socket.
+#[derive(Resource)]
+struct RoomPackets {
+    tx: Sender<Packet>,
+    rx: Receiver<Packet>,
+}
+
+impl Default for RoomPackets {
+    fn default() -> Self {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        Self { tx, rx }
+    }
+}
+
+fn setup(mut commands: Commands) {
+    let socket = initialize_network();
+    let (net_tx, net_rx) = (socket.get_packet_sender(), socket.get_event_receiver());
+
+    let poll_interval = Duration::from_millis((1000 / TICK_RATE_HZ / 2) as u64);
+
+    commands.insert_resource(NetworkThread::spawn(socket, poll_interval));
+    commands.insert_resource(NetTx(net_tx));
+    commands.insert_resource(NetRx(net_rx));
+}
+
+fn initialize_network() -> Socket {
+    // TODO(bschwind) - Remove this once we start having a steady flow of packets.
+    let net_config = NetworkConfig {
+        idle_connection_timeout: Duration::from_secs(5),
+        heartbeat_interval: Some(Duration::from_secs(4)),
+        ..NetworkConfig::default()
+    };
+
+    let bind_addr = SocketAddr::from(([0, 0, 0, 0], GAME_PORT));
+    let socket =
+        Socket::bind_with_config(bind_addr, net_config).expect("Couldn't bind to server GAME_PORT");
+
+    info!(addr = %bind_addr, "Listening");
+
+    socket
+}
+
+fn new_room_code(rng: &mut StdRng, rooms: &Rooms) -> String {
+    loop {
+        let code: String = (0..ROOM_CODE_LEN).map(|_| rng.gen_range(b'A'..=b'Z') as char).collect();
+
+        if !rooms.0.contains_key(&code) {
+            return code;
+        }
+    }
+}
+
+fn send_join_failed(
+    room_packets: &RoomPackets,
+    addr: SocketAddr,
+    room_code: String,
+    error: JoinRoomError,
+) {
+    let packet = ServerToClient::JoinRoomFailed(JoinRoomFailedPacket { room_code, error });
+    let data = encode_server_message(&packet);
+
+    let _ = room_packets.tx.send(make_packet(
+        DeliveryType::ReliableOrdered,
+        data,
+        addr,
+        Some(GAME_STATE_STREAM),
+    ));
+}
+
+// Works out which room a new address should go to from its connect packet,
+// creating the room if asked to. Spectators can only join existing rooms, and
+// have their own limit.
+#[allow(clippy::too_many_arguments)]
+fn room_for_new_player(
+    packet: &Packet,
+    room_packets: &RoomPackets,
+    rng: &mut StdRng,
+    rooms: &mut Rooms,
+    record_dir: &RecordDir,
+    spectator_delay: &SpectatorDelay,
+    bans: &Bans,
+    violations: &mut DecodeViolations,
+) -> Option<String> {
+    let (connect_packet, spectating) = match decode_client_message(packet.payload()) {
+        Ok(ClientToServer::Connect(connect_packet)) => (connect_packet, false),
+        Ok(ClientToServer::ConnectAsSpectator(connect_packet)) => (connect_packet, true),
+        Ok(_) => return None,
+        Err(e) => {
+            violations.record(packet.addr(), &e, Instant::now());
+            return None;
+        },
+    };
+
+    if SUPPORTED_PROTOCOL.negotiate(&connect_packet.protocol).is_none() {
+        let room_code = match connect_packet.room {
+            RoomRequest::Create => String::new(),
+            RoomRequest::Join(code) => code,
+        };
+
+        send_join_failed(
+            room_packets,
+            packet.addr(),
+            room_code,
+            JoinRoomError::IncompatibleProtocol,
+        );
+        return None;
+    }
+
+    // Before a room is even looked at, so nothing is spawned for them.
+    if let Some(ban) = bans.0.find(packet.addr(), SystemTime::now()) {
+        let room_code = match connect_packet.room {
+            RoomRequest::Create => String::new(),
+            RoomRequest::Join(code) => code,
+        };
+
+        info!(
+            name = %connect_packet.name,
+            addr = %packet.addr(),
+            reason = %ban.reason,
+            "Turned away a banned player"
+        );
+        send_join_failed(room_packets, packet.addr(), room_code, JoinRoomError::Banned);
+        return None;
+    }
+
+    match connect_packet.room {
+        RoomRequest::Create if spectating => {
+            send_join_failed(room_packets, packet.addr(), String::new(), JoinRoomError::NotFound);
+            None
+        },
+        RoomRequest::Create => {
+            let ip = packet.addr().ip();
+            let created_by_ip = rooms.0.values().filter(|room| room.created_by == Some(ip)).count();
+
+            if rooms.0.len() >= MAX_ROOMS || created_by_ip >= MAX_ROOMS_PER_IP {
+                info!(
+                    addr = %packet.addr(),
+                    rooms = rooms.0.len(),
+                    created_by_ip,
+                    "Refused a new room"
+                );
+                send_join_failed(
+                    room_packets,
+                    packet.addr(),
+                    String::new(),
+                    JoinRoomError::TooManyRooms,
+                );
+                return None;
+            }
+
+            let code = new_room_code(rng, rooms);
+            info!(room = %code, addr = %packet.addr(), "Creating room");
+
+            let seed = rng.gen();
+            let mut room = Room::new(&code, seed, spectator_delay.0, room_packets.tx.clone());
+
+            if let Some(dir) = &record_dir.0 {
+                room.record_to(dir, &RecordingHeader::new(&code, seed, spectator_delay.0));
+            }
+
+            room.created_by = Some(ip);
+            room.players.insert(packet.addr());
+            rooms.0.insert(code.clone(), room);
+            Some(code)
+        },
+        RoomRequest::Join(code) => {
+            let error = match rooms.0.get_mut(&code) {
+                Some(room) if spectating && room.spectators.len() < MAX_SPECTATORS => {
+                    room.spectators.insert(packet.addr());
+                    return Some(code);
+                },
+                Some(room) if !spectating && !room.in_lobby() => JoinRoomError::InProgress,
+                Some(room) if !spectating && room.player_count() < MAX_PLAYERS => {
+                    room.players.insert(packet.addr());
+                    return Some(code);
+                },
+                Some(_) => JoinRoomError::Full,
+                None => JoinRoomError::NotFound,
+            };
+
+            send_join_failed(room_packets, packet.addr(), code, error);
+            None
+        },
+    }
+}
+
+// Answers handshakes and decrypts everything else, dropping packets that don't
+// come from an established session. The rooms only ever see plaintext.
+fn decrypt_event(
+    event: SocketEvent,
+    net_tx: &NetTx,
+    sessions: &mut Sessions,
+    handshakes: &Handshakes,
+    metrics: &mut Metrics,
+    now: Instant,
+) -> Option<SocketEvent> {
+    let packet = match event {
+        SocketEvent::Packet(packet) => packet,
+        SocketEvent::Timeout(addr) | SocketEvent::Disconnect(addr) => {
+            sessions.remove(&addr);
+            return Some(event);
+        },
+        SocketEvent::Connect(_) => return Some(event),
+    };
+
+    let addr = packet.addr();
+
+    if let Some(session) = sessions.by_addr.get_mut(&addr) {
+        let plaintext = session.open(packet.payload())?;
+        return Some(SocketEvent::Packet(with_payload(&packet, plaintext)));
+    }
+
+    let (their_public, cookie) = match WireMessage::decode(packet.payload()) {
+        Some(WireMessage::ClientHello { public, cookie }) => (public, cookie),
+        _ => return None,
+    };
+
+    // Nothing is kept for an address until it has shown it can receive from
+    // us. The cookie is smaller than the hello it answers and never resent.
+    let reply = match cookie {
+        Some(cookie) if handshakes.cookies.check(addr, &cookie, now) => {
+            if sessions.pending.len() >= MAX_PENDING_SESSIONS {
+                debug!(%addr, "Too many sessions waiting to join a room, ignored a handshake");
+                return None;
+            }
+
+            let handshake = Handshake::new();
+            let hello = handshake.server_hello(&handshakes.key).encode();
+            sessions.by_addr.insert(addr, handshake.finish_server(their_public, &handshakes.key)?);
+            sessions.pending.insert(addr, now);
+
+            make_packet(DeliveryType::ReliableOrdered, hello, addr, Some(GAME_STATE_STREAM))
+        },
+        _ => {
+            let cookie = WireMessage::Cookie(handshakes.cookies.cookie(addr, now)).encode();
+            make_packet(DeliveryType::Unreliable, cookie, addr, None)
+        },
+    };
+
+    metrics.packet_sent(&reply);
+    let _ = net_tx.0.send(reply);
+
+    None
+}
+
+#[allow(clippy::too_many_arguments)]
+fn route_events(
+    net_rx: Res<NetRx>,
+    net_tx: Res<NetTx>,
+    room_packets: Res<RoomPackets>,
+    mut sessions: ResMut<Sessions>,
+    handshakes: Res<Handshakes>,
+    mut rng: ResMut<RoomCodeRng>,
+    mut rooms: NonSendMut<Rooms>,
+    mut addr_to_room: ResMut<AddrToRoom>,
+    record_dir: Res<RecordDir>,
+    spectator_delay: Res<SpectatorDelay>,
+    bans: Res<Bans>,
+    mut violations: ResMut<DecodeViolations>,
+    mut metrics: ResMut<Metrics>,
+) {
+    let now = Instant::now();
+    sessions.expire_pending(now);
+    violations.forget_quiet(now);
+
+    for event in net_rx.0.try_iter() {
+        if let SocketEvent::Packet(packet) = &event {
+            metrics.packet_received(packet);
+        }
+
+        let decrypted =
+            decrypt_event(event, &net_tx, &mut sessions, &handshakes, &mut metrics, now);
+        let event = match decrypted {
+            Some(event) => event,
+            None => continue,
+        };
+
+        let addr = match &event {
+            SocketEvent::Packet(packet) => packet.addr(),
+            SocketEvent::Connect(addr)
+            | SocketEvent::Timeout(addr)
+            | SocketEvent::Disconnect(addr) => *addr,
+        };
+
+        let code = match addr_to_room.0.get(&addr) {
+            Some(code) => code.clone(),
+            None => match &event {
+                SocketEvent::Packet(packet) => {
+                    match room_for_new_player(
+                        packet,
+                        &room_packets,
+                        &mut rng.0,
+                        &mut rooms,
+                        &record_dir,
+                        &spectator_delay,
+                        &bans,
+                        &mut violations,
+                    ) {
+                        Some(code) => {
+                            addr_to_room.0.insert(addr, code.clone());
+                            sessions.pending.remove(&addr);
+                            code
+                        },
+                        None => continue,
+                    }
+                },
+                _ => continue,
+            },
+        };
+
+        let room = match rooms.0.get_mut(&code) {
+            Some(room) => room,
+            None => continue,
+        };
+
+        if let SocketEvent::Timeout(_) | SocketEvent::Disconnect(_) = &event {
+            room.players.remove(&addr);
+            room.spectators.remove(&addr);
+            addr_to_room.0.remove(&addr);
+        }
+
+        room.send_event(event);
+    }
+
+    // Rooms are torn down as soon as the last player leaves, and anyone still
+    // watching goes with them.
+    rooms.0.retain(|code, room| {
+        if room.players.is_empty() {
+            info!(room = %code, "Closing room");
+        }
+
+        !room.players.is_empty()
+    });
+
+    addr_to_room.0.retain(|_, code| rooms.0.contains_key(code));
+}
+
+pub fn update_rooms(time: Res<Time>, mut rooms: NonSendMut<Rooms>) {
+    for room in rooms.0.values_mut() {
+        room.update(time.delta());
+    }
+}
+
+fn send_room_packets(
+    net_tx: Res<NetTx>,
+    room_packets: Res<RoomPackets>,
+    mut sessions: ResMut<Sessions>,
+    mut metrics: ResMut<Metrics>,
+) {
+    for packet in room_packets.rx.try_iter() {
+        // The player may have dropped since the room queued this.
+        let session = match sessions.by_addr.get_mut(&packet.addr()) {
+            Some(session) => session,
+            None => continue,
+        };
+
+        let sealed = with_payload(&packet, session.seal(is_reliable(&packet), packet.payload()));
+        metrics.packet_sent(&sealed);
+
+        if let Err(e) = net_tx.0.send(sealed) {
+            error!(addr = %packet.addr(), error = ?e, "Failed to send packet");
+        }
+    }
+}
+
+fn expire_bans(mut bans: ResMut<Bans>) {
+    for ban in bans.0.remove_expired(SystemTime::now()) {
+        info!(ip = %ban.ip, "Ban has run out");
+    }
+}
+
+fn forget_disconnected(
+    mut disconnecting: ResMut<Disconnecting>,
+    mut sessions: ResMut<Sessions>,
+    mut rooms: NonSendMut<Rooms>,
+    mut addr_to_room: ResMut<AddrToRoom>,
+) {
+    for addr in disconnecting.0.drain(..) {
+        sessions.remove(&addr);
+
+        let room = addr_to_room.0.remove(&addr).and_then(|code| rooms.0.get_mut(&code));
+
+        if let Some(room) = room {
+            room.players.remove(&addr);
+            room.spectators.remove(&addr);
+        }
+    }
+}
+
+#[cfg(test)]
+use sus_common::{
+    components::player::PlayerId,
+    network::decode_server_message,
+    simple_game::bevy::{Entity, Transform},
+};
+
+// Everything a replay has to reproduce after an update: every entity's
+// transform, bit for bit, and the packets the room sent. Systems that don't
+// depend on each other can be scheduled in a different order each time an app
+// is built, so packets from different systems are compared as a set.
+#[cfg(test)]
+type Snapshot = (Vec<(Entity, [u32; 10])>, Vec<(SocketAddr, Vec<u8>)>);
+
+#[cfg(test)]
+fn snapshot(world: &mut World, packets: Vec<Packet>) -> Snapshot {
+    let mut transforms: Vec<_> = world
+        .query::<(Entity, &Transform)>()
+        .iter(world)
+        .map(|(entity, transform)| {
+            let mut floats = vec![];
+            floats.extend_from_slice(&transform.translation.to_array());
+            floats.extend_from_slice(&transform.rotation.to_array());
+            floats.extend_from_slice(&transform.scale.to_array());
+
+            let mut bits = [0; 10];
+
+            for (bits, float) in bits.iter_mut().zip(floats) {
+                *bits = float.to_bits();
+            }
+
+            (entity, bits)
+        })
+        .collect();
+
+    transforms.sort_by_key(|(entity, _)| *entity);
+
+    let mut packets: Vec<_> =
+        packets.iter().map(|packet| (packet.addr(), packet.payload().to_vec())).collect();
+    packets.sort();
+
+    (transforms, packets)
+}
+
+#[cfg(test)]
+fn send_message(room: &mut Room, addr: SocketAddr, message: &ClientToServer) {
+    let payload = sus_common::network::encode_client_message(message);
+    room.send_event(SocketEvent::Packet(Packet::unreliable(addr, payload)));
+}
+
+#[test]
+fn test_replay_is_identical() {
+    use sus_common::network::{ConnectPacket, PlayerInputPacket};
+
+    let path = std::env::temp_dir().join(format!(
+        "sus-replay-test-{}.{}",
+        std::process::id(),
+        RECORDING_EXTENSION
+    ));
+    let (code, seed, spectator_delay) = ("TEST", 1234, Duration::from_secs(2));
+    let header = RecordingHeader::new(code, seed, spectator_delay);
+
+    let (net_tx, net_rx) = crossbeam_channel::unbounded();
+    let mut room = Room::new(code, seed, spectator_delay, net_tx);
+    room.recorder = Some(Recorder::create(&path, &header).unwrap());
+
+    let addrs: Vec<_> =
+        (0..3).map(|port| SocketAddr::from(([127, 0, 0, 1], 9000 + port))).collect();
+    let spectator = SocketAddr::from(([127, 0, 0, 1], 9100));
+    let mut recorded = vec![];
+
+    // Everyone joins and readies up, the host starts the game and then they
+    // all wander around, with uneven gaps between updates like a real server.
+    // Someone watches the whole thing.
+    for frame in 0..200u16 {
+        if frame == 0 {
+            let connect = ConnectPacket::new("Watcher", RoomRequest::Join(code.into()));
+            send_message(&mut room, spectator, &ClientToServer::ConnectAsSpectator(connect));
+        }
+
+        for (i, addr) in addrs.iter().enumerate() {
+            match frame {
+                0 => {
+                    room.send_event(SocketEvent::Connect(*addr));
+                    let connect = ConnectPacket::new(
+                        &format!("Player {}", i),
+                        RoomRequest::Join(code.into()),
+                    );
+                    send_message(&mut room, *addr, &ClientToServer::Connect(connect));
+                },
+                2 => send_message(&mut room, *addr, &ClientToServer::SetReady(true)),
+                3 if i == 0 => send_message(&mut room, *addr, &ClientToServer::StartGame),
+                5.. => {
+                    let x = (frame as i16 / 20 + i as i16) % 3 - 1;
+                    let y = (frame as i16 / 30 + i as i16 * 2) % 3 - 1;
+                    let input = PlayerInputPacket::new(frame, x, y);
+                    send_message(&mut room, *addr, &ClientToServer::PlayerInput(input));
+                },
+                _ => {},
+            }
+        }
+
+        room.update(Duration::from_micros(95_000 + (frame as u64 * 7919) % 10_000));
+        recorded.push(snapshot(&mut room.app.world, net_rx.try_iter().collect()));
+    }
+
+    assert_eq!(room.app.world.resource::<State<GameState>>().0, GameState::Main);
+
+    // Spectators never get a player, but do get to watch.
+    assert_eq!(room.app.world.query::<&PlayerId>().iter(&room.app.world).count(), addrs.len());
+    assert!(recorded.iter().flat_map(|(_, packets)| packets).any(|(addr, payload)| {
+        *addr == spectator
+            && matches!(decode_server_message(payload), Ok(ServerToClient::SpectatorTick(_)))
+    }));
+    drop(room);
+
+    let recording = Recording::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(recording.frames.len(), recorded.len());
+
+    let mut replayed = vec![];
+    replay(recording, |world, packets| replayed.push(snapshot(world, packets)));
+
+    assert_eq!(recorded, replayed);
+}
+
+#[test]
+fn test_host_adds_scripted_bot() {
+    use crate::{
+        bots::{BotBrain, BotOutput, BotView},
+        components::Bot,
+    };
+    use rand::rngs::StdRng;
+    use sus_common::{network::ConnectPacket, resources::PlayerToEntity};
+
+    // Walks east and nothing else.
+    struct WalkEast;
+
+    impl BotBrain for WalkEast {
+        fn think(&mut self, _view: &BotView, _rng: &mut StdRng) -> BotOutput {
+            BotOutput { x: i16::MAX, y: 0, action: None }
+        }
+    }
+
+    let (net_tx, _net_rx) = crossbeam_channel::unbounded();
+    let mut room = Room::new("BOTS", 1, Duration::ZERO, net_tx);
+
+    let host = SocketAddr::from(([127, 0, 0, 1], 9000));
+    let guest = SocketAddr::from(([127, 0, 0, 1], 9001));
+
+    for (addr, name) in [(host, "Host"), (guest, "Guest")] {
+        room.send_event(SocketEvent::Connect(addr));
+        let connect = ConnectPacket::new(name, RoomRequest::Join("BOTS".into()));
+        send_message(&mut room, addr, &ClientToServer::Connect(connect));
+    }
+
+    // The room's first update only starts its clock.
+    room.update(Duration::ZERO);
+    room.update(Duration::from_millis(100));
+
+    // Only the host gets to add bots.
+    send_message(&mut room, guest, &ClientToServer::AddBot);
+    send_message(&mut room, host, &ClientToServer::AddBot);
+    send_message(&mut room, host, &ClientToServer::AddBot);
+    room.update(Duration::from_millis(100));
+    send_message(&mut room, host, &ClientToServer::RemoveBot);
+
+    // Messages are picked up after the fixed update has already run.
+    room.update(Duration::from_millis(100));
+    room.update(Duration::from_millis(100));
+
+    let bots = room.app.world.resource::<Bots>().0.clone();
+    assert_eq!(bots.len(), 1);
+    assert_eq!(room.app.world.query::<&PlayerId>().iter(&room.app.world).count(), 3);
+
+    let entity = room.app.world.resource::<PlayerToEntity>().0[&bots[0]];
+    room.app.world.entity_mut(entity).insert(Bot::new(WalkEast));
+
+    let start = room.app.world.get::<Transform>(entity).unwrap().translation;
+
+    for _ in 0..10 {
+        room.update(Duration::from_millis(100));
+    }
+
+    let end = room.app.world.get::<Transform>(entity).unwrap().translation;
+    assert!(end.x > start.x);
+    assert_eq!(end.y, start.y);
+}
+
+#[test]
+fn test_leaving_players_are_removed() {
+    use sus_common::{
+        network::{ConnectPacket, DisconnectReason, MAX_PLAYERS},
+        resources::PlayerToEntity,
+    };
+
+    let (net_tx, net_rx) = crossbeam_channel::unbounded();
+    let mut room = Room::new("BUSY", 1, Duration::ZERO, net_tx);
+    room.update(Duration::ZERO);
+
+    let join = |room: &mut Room, addr: SocketAddr| {
+        room.send_event(SocketEvent::Connect(addr));
+        let connect = ConnectPacket::new("Passing through", RoomRequest::Join("BUSY".into()));
+        send_message(room, addr, &ClientToServer::Connect(connect));
+        room.update(Duration::from_millis(100));
+    };
+
+    // More players come and go than the room could ever hold at once.
+    for port in 0..=MAX_PLAYERS as u16 {
+        let addr = SocketAddr::from(([127, 0, 0, 1], 9000 + port));
+        join(&mut room, addr);
+
+        if port % 2 == 0 {
+            room.send_event(SocketEvent::Disconnect(addr));
+        } else {
+            room.send_event(SocketEvent::Timeout(addr));
+        }
+
+        room.update(Duration::from_millis(100));
+    }
+
+    assert_eq!(room.app.world.query::<&PlayerId>().iter(&room.app.world).count(), 0);
+    assert!(room.app.world.resource::<PlayerToEntity>().0.is_empty());
+
+    // Someone arriving now is only told about themselves, and everything they're
+    // sent is still within what a client accepts.
+    let addr = SocketAddr::from(([127, 0, 0, 1], 9100));
+    net_rx.try_iter().count();
+    join(&mut room, addr);
+    room.update(Duration::from_millis(100));
+
+    let packets: Vec<_> = net_rx.try_iter().filter(|packet| packet.addr() == addr).collect();
+    assert!(!packets.is_empty());
+
+    for packet in packets {
+        match decode_server_message(packet.payload()) {
+            Ok(ServerToClient::FullGameState(full_game_state)) => {
+                assert!(full_game_state.players.is_empty());
+            },
+            Ok(ServerToClient::LobbyTick(lobby_tick)) => assert_eq!(lobby_tick.players.len(), 1),
+            Ok(_) => {},
+            Err(e) => panic!("Client would reject {:?}", e),
+        }
+    }
+
+    // Being kicked goes the same way as leaving, everyone else hears about it.
+    let kicked = *room.app.world.resource::<PlayerToEntity>().0.keys().next().unwrap();
+    let other = SocketAddr::from(([127, 0, 0, 1], 9101));
+    join(&mut room, other);
+    net_rx.try_iter().count();
+
+    room.send_admin(AdminAction::Kick { id: kicked, reason: DisconnectReason::Kicked });
+    room.update(Duration::from_millis(100));
+    room.update(Duration::from_millis(100));
+
+    assert!(net_rx.try_iter().any(|packet| packet.addr() == other
+        && matches!(
+            decode_server_message(packet.payload()),
+            Ok(ServerToClient::PlayerLeft(left)) if left.id == kicked
+        )));
+    assert_eq!(room.app.world.query::<&PlayerId>().iter(&room.app.world).count(), 1);
+}
+
+#[test]
+fn test_room_limits() {
+    use sus_common::network::ConnectPacket;
+
+    let room_packets = RoomPackets::default();
+    let mut rng = StdRng::seed_from_u64(1);
+    let mut rooms = Rooms(HashMap::new());
+    let mut violations = DecodeViolations::default();
+
+    let mut connect = |rooms: &mut Rooms, addr: SocketAddr, room: RoomRequest| {
+        let connect = ClientToServer::Connect(ConnectPacket::new("Player", room));
+        let payload = sus_common::network::encode_client_message(&connect);
+
+        room_for_new_player(
+            &Packet::unreliable(addr, payload),
+            &room_packets,
+            &mut rng,
+            rooms,
+            &RecordDir(None),
+            &SpectatorDelay::default(),
+            &Bans::default(),
+            &mut violations,
+        )
+    };
+
+    let refusal = |room_packets: &RoomPackets| {
+        let packet = room_packets.rx.try_iter().last().unwrap();
+
+        match decode_server_message(packet.payload()) {
+            Ok(ServerToClient::JoinRoomFailed(join_room_failed)) => join_room_failed.error,
+            other => panic!("Expected a refusal, got {:?}", other),
+        }
+    };
+
+    // One address can only open so many rooms, wherever it connects from.
+    for port in 0..MAX_ROOMS_PER_IP as u16 {
+        let addr = SocketAddr::from(([203, 0, 113, 7], 5000 + port));
+        assert!(connect(&mut rooms, addr, RoomRequest::Create).is_some());
+    }
+
+    let addr = SocketAddr::from(([203, 0, 113, 7], 6000));
+    assert_eq!(connect(&mut rooms, addr, RoomRequest::Create), None);
+    assert_eq!(refusal(&room_packets), JoinRoomError::TooManyRooms);
+
+    // Someone else can still join, but not once the game is underway.
+    let code = rooms.0.keys().next().unwrap().clone();
+    let guest = SocketAddr::from(([198, 51, 100, 1], 5000));
+    assert_eq!(connect(&mut rooms, guest, RoomRequest::Join(code.clone())), Some(code.clone()));
+
+    rooms.0.get_mut(&code).unwrap().app.world.insert_resource(State(GameState::Main));
+
+    let late = SocketAddr::from(([198, 51, 100, 2], 5000));
+    assert_eq!(connect(&mut rooms, late, RoomRequest::Join(code)), None);
+    assert_eq!(refusal(&room_packets), JoinRoomError::InProgress);
+}
+
+#[test]
+fn test_repeated_connect_is_ignored() {
+    use sus_common::network::ConnectPacket;
+
+    let (net_tx, net_rx) = crossbeam_channel::unbounded();
+    let mut room = Room::new("ONCE", 1, Duration::ZERO, net_tx);
+    room.update(Duration::ZERO);
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 9000));
+    let connect =
+        ClientToServer::Connect(ConnectPacket::new("Twice", RoomRequest::Join("ONCE".into())));
+
+    // Once in the same update as the first, and again after it was handled.
+    room.send_event(SocketEvent::Connect(addr));
+    send_message(&mut room, addr, &connect);
+    send_message(&mut room, addr, &connect);
+    room.update(Duration::from_millis(100));
+    send_message(&mut room, addr, &connect);
+    room.update(Duration::from_millis(100));
+
+    let acks = net_rx
+        .try_iter()
+        .filter(|packet| {
+            matches!(decode_server_message(packet.payload()), Ok(ServerToClient::ConnectAck(_)))
+        })
+        .count();
+
+    assert_eq!(acks, 1);
+    assert_eq!(room.app.world.query::<&PlayerId>().iter(&room.app.world).count(), 1);
+}
+
+#[test]
+fn test_handshake_needs_a_cookie() {
+    let (net_tx, net_rx) = crossbeam_channel::unbounded();
+    let net_tx = NetTx(net_tx);
+    let mut sessions = Sessions::default();
+    let handshakes = Handshakes { key: ServerKey::generate(), cookies: CookieJar::new() };
+    let mut metrics = Metrics::default();
+    let now = Instant::now();
+
+    let mut hello = |sessions: &mut Sessions, addr: SocketAddr, cookie| {
+        let payload = Handshake::new().client_hello(cookie).encode();
+        let event = SocketEvent::Packet(Packet::unreliable(addr, payload));
+        assert!(decrypt_event(event, &net_tx, sessions, &handshakes, &mut metrics, now).is_none());
+
+        let reply = net_rx.try_iter().last().unwrap();
+        assert_eq!(reply.addr(), addr);
+        WireMessage::decode(reply.payload()).unwrap()
+    };
+
+    // A first hello only gets a cookie, and nothing is kept for it.
+    let addr = SocketAddr::from(([198, 51, 100, 1], 5000));
+    let cookie = match hello(&mut sessions, addr, None) {
+        WireMessage::Cookie(cookie) => cookie,
+        other => panic!("Expected a cookie, got {:?}", other),
+    };
+    assert!(sessions.by_addr.is_empty());
+
+    // Someone else's cookie is no good.
+    let other = SocketAddr::from(([198, 51, 100, 2], 5000));
+    assert!(matches!(hello(&mut sessions, other, Some(cookie)), WireMessage::Cookie(_)));
+    assert!(sessions.by_addr.is_empty());
+
+    assert!(matches!(
+        hello(&mut sessions, addr, Some(cookie)),
+        WireMessage::ServerHello { server_key, .. } if server_key == handshakes.key.public()
+    ));
+    assert!(sessions.by_addr.contains_key(&addr));
+
+    // Sessions that never join a room are capped, and dropped after a while.
+    for i in 1..MAX_PENDING_SESSIONS + 10 {
+        let addr = SocketAddr::from(([203, 0, 113, 1], i as u16));
+        let cookie = handshakes.cookies.cookie(addr, now);
+        let payload = Handshake::new().client_hello(Some(cookie)).encode();
+        let event = SocketEvent::Packet(Packet::unreliable(addr, payload));
+        decrypt_event(event, &net_tx, &mut sessions, &handshakes, &mut metrics, now);
+    }
+
+    assert_eq!(sessions.by_addr.len(), MAX_PENDING_SESSIONS);
+
+    sessions.expire_pending(now + PENDING_SESSION_TIMEOUT);
+    assert!(sessions.by_addr.is_empty());
+    assert!(sessions.pending.is_empty());
+}
+
+#[test]
+fn test_next_game_starts_fresh() {
+    use crate::{
+        components::{KillCooldown, UsingConsole, Venting},
+        events::GameOver,
+    };
+    use sus_common::{
+        network::{ConnectPacket, ConsoleKind},
+        resources::PlayerToEntity,
+        PlayerType,
+    };
+
+    let (net_tx, _net_rx) = crossbeam_channel::unbounded();
+    let mut room = Room::new("AGAIN", 1, Duration::ZERO, net_tx);
+    room.update(Duration::ZERO);
+
+    let addr = SocketAddr::from(([127, 0, 0, 1], 9000));
+    room.send_event(SocketEvent::Connect(addr));
+    let connect = ConnectPacket::new("Impostor", RoomRequest::Join("AGAIN".into()));
+    send_message(&mut room, addr, &ClientToServer::Connect(connect));
+    room.update(Duration::from_millis(100));
+
+    // The new state takes effect on the update after.
+    room.send_admin(AdminAction::SetState(GameState::Main));
+    room.update(Duration::from_millis(100));
+    room.update(Duration::from_millis(100));
+    assert!(!room.in_lobby());
+
+    // The game ends with them hiding in a vent, waiting to kill again and
+    // somehow looking at the cameras too.
+    let entity = *room.app.world.resource::<PlayerToEntity>().0.values().next().unwrap();
+    room.app
+        .world
+        .entity_mut(entity)
+        .insert(Venting { vent: Some(0), cooldown: Duration::ZERO })
+        .insert(KillCooldown(Duration::from_secs(60)))
+        .insert(UsingConsole(Some(ConsoleKind::Cameras)));
+    room.app.world.send_event(GameOver { winner: PlayerType::Impostor });
+
+    for _ in 0..200 {
+        room.update(Duration::from_millis(100));
+
+        if room.in_lobby() {
+            break;
+        }
+    }
+
+    assert!(room.in_lobby());
+    assert_eq!(room.app.world.get::<Venting>(entity).unwrap().vent, None);
+    assert_eq!(room.app.world.get::<KillCooldown>(entity).unwrap().0, Duration::ZERO);
+    assert_eq!(room.app.world.get::<UsingConsole>(entity).unwrap().0, None);
+}
@@ -0,0 +1,108 @@
+use crate::{
+    events::AdminAction,
+    rooms::{update_rooms, Rooms},
+};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+use sus_common::{
+    resources::network::NetworkThread,
+    simple_game::bevy::{bevy_ecs, App, IntoSystemConfig, NonSendMut, Plugin, ResMut, Resource},
+    tracing::info,
+};
+
+// Stops the server without leaving everyone to time out. On Ctrl+C, SIGTERM or
+// the admin console's `shutdown`, every room tells its players and spectators
+// the server is going away, the network thread gets a moment to deliver that,
+// and then recordings are finished off and the socket is closed. A second
+// signal kills the server straight away, in case that gets stuck.
+pub struct ShutdownPlugin;
+
+impl Plugin for ShutdownPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Shutdown>()
+            .add_startup_system(listen_for_signals)
+            .add_system(shut_down.before(update_rooms));
+    }
+}
+
+// Long enough for the goodbyes, and any resends of them, to make it out.
+const SHUTDOWN_GRACE: Duration = Duration::from_millis(500);
+
+// What players are told when the server is stopped by a signal.
+const SIGNAL_REASON: &str = "The server is restarting or going down";
+
+// Set from the signal handler, which can't do much more than this.
+static SIGNALLED: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Default, Resource)]
+pub struct Shutdown {
+    // Once it's been asked for, the reason players are given.
+    pub requested: Option<String>,
+    deadline: Option<Instant>,
+}
+
+#[cfg(unix)]
+fn listen_for_signals() {
+    extern "C" fn on_signal(_signal: libc::c_int) {
+        SIGNALLED.store(true, Ordering::Relaxed);
+
+        // signal() is async-signal-safe, so putting the defaults back from
+        // here is fine.
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+            libc::signal(libc::SIGTERM, libc::SIG_DFL);
+        }
+    }
+
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+
+    // The handler only stores to an atomic and resets the handlers, both of
+    // which are safe to do in one.
+    unsafe {
+        libc::signal(libc::SIGINT, handler);
+        libc::signal(libc::SIGTERM, handler);
+    }
+}
+
+// Elsewhere the process just ends as usual, and the admin console's `shutdown`
+// is the clean way out.
+#[cfg(not(unix))]
+fn listen_for_signals() {}
+
+pub fn shut_down(
+    mut shutdown: ResMut<Shutdown>,
+    mut rooms: NonSendMut<Rooms>,
+    mut network_thread: ResMut<NetworkThread>,
+) {
+    if SIGNALLED.load(Ordering::Relaxed) && shutdown.requested.is_none() {
+        shutdown.requested = Some(SIGNAL_REASON.to_string());
+    }
+
+    let reason = match &shutdown.requested {
+        Some(reason) => reason.clone(),
+        None => return,
+    };
+
+    match shutdown.deadline {
+        None => {
+            info!(%reason, "Shutting down");
+
+            for (_, room) in rooms.iter_mut() {
+                room.send_admin(AdminAction::ShutDown(reason.clone()));
+            }
+
+            shutdown.deadline = Some(Instant::now() + SHUTDOWN_GRACE);
+        },
+        Some(deadline) if Instant::now() >= deadline => {
+            // Dropping the rooms finishes off their recordings.
+            rooms.close_all();
+            network_thread.stop();
+
+            info!("Shut down");
+            std::process::exit(0);
+        },
+        Some(_) => {},
+    }
+}
@@ -0,0 +1,132 @@
+use crate::{
+    events::{AdminAction, OutgoingPacket, PlayerLeft},
+    resources::{AddrToPlayer, Bots, Spectators},
+    systems::{sets, PacketDestination},
+};
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    network::{
+        DeliveryType, DisconnectedPacket, ServerShutdownPacket, ServerToClient,
+        SystemMessagePacket, CHAT_STREAM, GAME_STATE_STREAM,
+    },
+    resources::PlayerToEntity,
+    settings::GameSettings,
+    simple_game::bevy::{
+        schedule::State, App, EventReader, EventWriter, IntoSystemConfig, NextState, Plugin, Query,
+        Res, ResMut,
+    },
+    tracing::info,
+    GameState,
+};
+
+// Carries out what the server operator asks of this room from the admin
+// console.
+pub struct AdminPlugin;
+
+impl Plugin for AdminPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AdminAction>().add_system(
+            handle_admin_actions
+                .after(sets::NetworkSystem::Receive)
+                .before(sets::NetworkSystem::SendPackets),
+        );
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_admin_actions(
+    game_state: Res<State<GameState>>,
+    mut admin_rx: EventReader<AdminAction>,
+    mut connected: ResMut<AddrToPlayer>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut bots: ResMut<Bots>,
+    mut spectators: ResMut<Spectators>,
+    mut settings: ResMut<GameSettings>,
+    mut next_state: ResMut<NextState<GameState>>,
+    players: Query<&PlayerNetworkAddr>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+    mut player_left_tx: EventWriter<PlayerLeft>,
+) {
+    for action in admin_rx.iter() {
+        match action {
+            AdminAction::Kick { id, reason } => {
+                let entity = match player_to_entity.0.get(id) {
+                    Some(entity) => *entity,
+                    None => continue,
+                };
+
+                info!(player = id, ?reason, "Removed by an admin");
+
+                if let Ok(network_addr) = players.get(entity) {
+                    outgoing_packets.send(OutgoingPacket::new(
+                        PacketDestination::Single(network_addr.0),
+                        ServerToClient::Disconnected(DisconnectedPacket { reason: *reason }),
+                        DeliveryType::ReliableOrdered,
+                        Some(GAME_STATE_STREAM),
+                    ));
+                }
+
+                // Despawning them and telling everyone else is left to the
+                // same system that handles players leaving on their own.
+                connected.0.retain(|_, player| player != id);
+                bots.0.retain(|bot| bot != id);
+                player_left_tx.send(PlayerLeft { id: *id });
+            },
+            AdminAction::SetState(state) => {
+                info!(from = ?game_state.0, to = ?state, "Admin changed the game state");
+                next_state.set(state.clone());
+            },
+            AdminAction::UpdateSettings(new_settings) => *settings = new_settings.clone(),
+            AdminAction::Announce(message) => {
+                let packet =
+                    ServerToClient::SystemMessage(SystemMessagePacket { message: message.clone() });
+
+                send_to_everyone(&mut outgoing_packets, &spectators, packet, CHAT_STREAM);
+            },
+            AdminAction::ShutDown(reason) => {
+                info!(%reason, "Disconnecting everyone");
+
+                let packet =
+                    ServerToClient::ServerShutdown(ServerShutdownPacket { reason: reason.clone() });
+                send_to_everyone(&mut outgoing_packets, &spectators, packet, GAME_STATE_STREAM);
+            },
+            AdminAction::RemoveSpectator { addr, reason } => {
+                if !spectators.0.remove(addr) {
+                    continue;
+                }
+
+                info!(%addr, ?reason, "Spectator removed by an admin");
+
+                outgoing_packets.send(OutgoingPacket::new(
+                    PacketDestination::Single(*addr),
+                    ServerToClient::Disconnected(DisconnectedPacket { reason: *reason }),
+                    DeliveryType::ReliableOrdered,
+                    Some(GAME_STATE_STREAM),
+                ));
+            },
+        }
+    }
+}
+
+fn send_to_everyone(
+    outgoing_packets: &mut EventWriter<OutgoingPacket>,
+    spectators: &Spectators,
+    packet: ServerToClient,
+    stream: u8,
+) {
+    if !spectators.0.is_empty() {
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToSet(spectators.0.iter().copied().collect()),
+            packet.clone(),
+            DeliveryType::ReliableOrdered,
+            Some(stream),
+        ));
+    }
+
+    outgoing_packets.send(OutgoingPacket::new(
+        PacketDestination::BroadcastToAll,
+        packet,
+        DeliveryType::ReliableOrdered,
+        Some(stream),
+    ));
+}
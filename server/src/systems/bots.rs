@@ -0,0 +1,219 @@
+use crate::{
+    bots::{BotAction, BotView, VisiblePlayer, WanderingBot},
+    components::{Bot, KillCooldown, Ready, ServerPlayerBundle, Tasks, Venting},
+    events::{
+        CompleteTask, Kill, LobbyAction, LobbyRequest, OutgoingPacket, PlayerLeft, UseVent,
+        VentAction,
+    },
+    resources::{AddrToPlayer, BotRng, Bots, Host, LightsOn},
+    systems::{
+        bots::bevy_ecs::prelude::in_state, network::PlayerIdCounter, players_can_move, sets,
+        PacketDestination,
+    },
+};
+use std::net::SocketAddr;
+use sus_common::{
+    components::player::{PlayerId, UnprocessedInputs},
+    map::Map,
+    network::{
+        DeliveryType, NewPlayerPacket, PlayerInputPacket, ServerToClient, GAME_STATE_STREAM,
+        MAX_PLAYERS,
+    },
+    protocol::Capabilities,
+    resources::{ClosedDoors, PlayerToEntity},
+    settings::GameSettings,
+    simple_game::{
+        bevy::{
+            bevy_ecs, schedule::State, App, Commands, CoreSchedule, Entity, EventReader,
+            EventWriter, IntoSystemAppConfig, IntoSystemConfig, Plugin, Query, Res, ResMut,
+            Transform,
+        },
+        glam::vec2,
+    },
+    tracing::info,
+    vision::can_see,
+    GameState, PlayerState, PlayerType,
+};
+
+pub struct BotsPlugin;
+
+impl Plugin for BotsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Bots>()
+            .add_system(
+                add_and_remove_bots
+                    .after(sets::Lobby)
+                    .run_if(in_state(GameState::Lobby))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                run_bots
+                    .after(sets::Network)
+                    .before(sets::NetworkSystem::PlayerInput)
+                    .run_if(players_can_move)
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+// Bots need an address to fill in PlayerNetworkAddr, but nothing is ever sent
+// from one, and anything sent to one is dropped for lack of a session.
+fn bot_addr(id: u16) -> SocketAddr {
+    SocketAddr::from(([0, 0, 0, 0], id))
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_and_remove_bots(
+    mut commands: Commands,
+    host: Res<Host>,
+    connected: Res<AddrToPlayer>,
+    mut bots: ResMut<Bots>,
+    mut player_to_entity: ResMut<PlayerToEntity>,
+    mut player_id_counter: ResMut<PlayerIdCounter>,
+    mut lobby_request_rx: EventReader<LobbyRequest>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+    mut player_left_tx: EventWriter<PlayerLeft>,
+) {
+    for event in lobby_request_rx.iter() {
+        if host.0 != Some(event.id) {
+            continue;
+        }
+
+        match event.action {
+            // Counted the same way as when someone asks to join the room.
+            LobbyAction::AddBot if connected.0.len() + bots.0.len() < MAX_PLAYERS => {
+                let id = player_id_counter.0;
+                player_id_counter.0 += 1;
+
+                let name = format!("Bot {}", id);
+                info!(player = id, %name, "Adding a bot");
+
+                // Bots are always ready, so they never hold up the start.
+                let mut bundle = ServerPlayerBundle::new(
+                    id,
+                    name.clone(),
+                    bot_addr(id),
+                    Capabilities::default(),
+                );
+                bundle.ready = Ready(true);
+
+                let entity = commands.spawn((bundle, Bot::new(WanderingBot::default()))).id();
+
+                bots.0.push(id);
+                player_to_entity.0.insert(id, entity);
+
+                outgoing_packets.send(OutgoingPacket::new(
+                    PacketDestination::BroadcastToAll,
+                    ServerToClient::NewPlayer(NewPlayerPacket::new(name, id)),
+                    DeliveryType::ReliableOrdered,
+                    Some(GAME_STATE_STREAM),
+                ));
+            },
+            LobbyAction::RemoveBot => {
+                let id = match bots.0.pop() {
+                    Some(id) => id,
+                    None => continue,
+                };
+
+                info!(player = id, "Removing a bot");
+                player_left_tx.send(PlayerLeft { id });
+            },
+            _ => {},
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_bots(
+    game_state: Res<State<GameState>>,
+    map: Res<Map>,
+    settings: Res<GameSettings>,
+    lights_on: Res<LightsOn>,
+    closed_doors: Res<ClosedDoors>,
+    mut rng: ResMut<BotRng>,
+    mut bots: Query<(Entity, &mut Bot, &mut UnprocessedInputs, &Tasks, &KillCooldown)>,
+    players: Query<(&PlayerId, &Transform, &PlayerType, &PlayerState, &Venting)>,
+    mut complete_task_tx: EventWriter<CompleteTask>,
+    mut kill_tx: EventWriter<Kill>,
+    mut use_vent_tx: EventWriter<UseVent>,
+) {
+    for (entity, mut bot, mut unprocessed_inputs, tasks, kill_cooldown) in bots.iter_mut() {
+        let (id, transform, player_type, player_state, venting) = match players.get(entity) {
+            Ok(player) => player,
+            Err(_) => continue,
+        };
+
+        let pos = vec2(transform.translation.x, transform.translation.y);
+        let radius = settings.vision.radius(*player_type, *player_state, lights_on.0);
+
+        // The same rules as the players a client is sent.
+        let visible_players: Vec<_> = players
+            .iter()
+            .filter(|(other_id, ..)| other_id.0 != id.0)
+            .filter(|(_, other_transform, _, other_state, other_venting)| {
+                if other_venting.vent.is_some() {
+                    return false;
+                }
+
+                if game_state.0 == GameState::Lobby {
+                    return true;
+                }
+
+                let target = vec2(other_transform.translation.x, other_transform.translation.y);
+
+                match (player_state, other_state) {
+                    (PlayerState::Alive, PlayerState::Dead) => false,
+                    (PlayerState::Alive, PlayerState::Alive) => {
+                        can_see(&map, &closed_doors, pos, target, radius)
+                    },
+                    (PlayerState::Dead, _) => pos.distance_squared(target) <= radius * radius,
+                }
+            })
+            .map(|(other_id, other_transform, other_type, other_state, _)| VisiblePlayer {
+                id: other_id.0,
+                pos: vec2(other_transform.translation.x, other_transform.translation.y),
+                player_state: *other_state,
+                teammate: *player_type == PlayerType::Impostor
+                    && *other_type == PlayerType::Impostor,
+            })
+            .collect();
+
+        let view = BotView {
+            game_state: &game_state.0,
+            pos,
+            player_type: *player_type,
+            player_state: *player_state,
+            tasks: &tasks.0,
+            can_kill: kill_cooldown.0.is_zero(),
+            vent: venting.vent,
+            can_vent: venting.cooldown.is_zero(),
+            visible_players: &visible_players,
+            map: &map,
+            closed_doors: &closed_doors,
+            settings: &settings,
+        };
+
+        let output = bot.brain.think(&view, &mut rng.0);
+
+        bot.input_counter = bot.input_counter.wrapping_add(1);
+        unprocessed_inputs.0.push_back(PlayerInputPacket::new(
+            bot.input_counter,
+            output.x,
+            output.y,
+        ));
+
+        match output.action {
+            Some(BotAction::CompleteTask(task)) => {
+                complete_task_tx.send(CompleteTask { id: id.0, task });
+            },
+            Some(BotAction::Kill(target)) => kill_tx.send(Kill { id: id.0, target }),
+            Some(BotAction::EnterVent(vent)) => {
+                use_vent_tx.send(UseVent { id: id.0, action: VentAction::Enter(vent) });
+            },
+            Some(BotAction::ExitVent) => {
+                use_vent_tx.send(UseVent { id: id.0, action: VentAction::Exit });
+            },
+            None => {},
+        }
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{
+    components::PlayerCapabilities,
+    events::{Chat, OutgoingPacket},
+    systems::PacketDestination,
+};
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    network::{ChatPacket, DeliveryType, ServerToClient, CHAT_STREAM, MAX_CHAT_MESSAGE_LEN},
+    protocol::Capabilities,
+    resources::PlayerToEntity,
+    simple_game::bevy::{
+        schedule::State, App, CoreSchedule, EventReader, EventWriter, IntoSystemAppConfig, Plugin,
+        Query, Res,
+    },
+    GameState, PlayerState,
+};
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(relay_chat.in_schedule(CoreSchedule::FixedUpdate));
+    }
+}
+
+// Outside of a round everyone can talk. During a round only ghosts can chat,
+// and only other ghosts hear them. Clients without chat support never get it.
+fn relay_chat(
+    game_state: Res<State<GameState>>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut chat_rx: EventReader<Chat>,
+    players: Query<(&PlayerNetworkAddr, &PlayerState, &PlayerCapabilities)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in chat_rx.iter() {
+        let sender_state = match player_to_entity.0.get(&event.id) {
+            Some(entity) => match players.get(*entity) {
+                Ok((_, player_state, _)) => *player_state,
+                Err(_) => continue,
+            },
+            None => continue,
+        };
+
+        let in_round = matches!(game_state.0, GameState::IntroScreen | GameState::Main);
+
+        if in_round && sender_state == PlayerState::Alive {
+            continue;
+        }
+
+        let message: String = event.message.chars().take(MAX_CHAT_MESSAGE_LEN).collect();
+        let message = message.trim();
+
+        if message.is_empty() {
+            continue;
+        }
+
+        let recipients = players
+            .iter()
+            .filter(|(_, _, capabilities)| capabilities.0.contains(Capabilities::CHAT))
+            .filter(|(_, player_state, _)| !in_round || **player_state == PlayerState::Dead)
+            .map(|(network_addr, _, _)| network_addr.0)
+            .collect();
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToSet(recipients),
+            ServerToClient::Chat(ChatPacket { id: event.id, message: message.to_string() }),
+            DeliveryType::ReliableOrdered,
+            Some(CHAT_STREAM),
+        ));
+    }
+}
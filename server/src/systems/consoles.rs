@@ -0,0 +1,172 @@
+use crate::{
+    components::{UsingConsole, Venting},
+    events::{OutgoingPacket, UseConsole},
+    systems::{network_send, sets, PacketDestination},
+};
+use sus_common::{
+    components::player::{PlayerId, PlayerNetworkAddr},
+    map::{within_interact_distance, Map, CAMERA_RADIUS},
+    network::{
+        CameraPlayer, ConsoleInfo, ConsoleInfoPacket, ConsoleKind, DeliveryType, PlayerVitals,
+        ServerToClient, GAME_STATE_STREAM,
+    },
+    resources::{ClosedDoors, PlayerToEntity},
+    simple_game::{
+        bevy::{
+            bevy_ecs::prelude::in_state, App, CoreSchedule, EventReader, EventWriter,
+            IntoSystemAppConfig, IntoSystemConfig, OnExit, Plugin, Query, Res, Transform,
+        },
+        glam::{vec2, Vec2},
+    },
+    vision::can_see,
+    GameState, PlayerState,
+};
+
+pub struct ConsolesPlugin;
+
+impl Plugin for ConsolesPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            use_console.run_if(in_state(GameState::Main)).in_schedule(CoreSchedule::FixedUpdate),
+        )
+        .add_system(
+            send_console_info
+                .in_set(sets::NetworkSystem::SendPackets)
+                .before(network_send)
+                .run_if(in_state(GameState::Main)),
+        )
+        .add_system(leave_consoles.in_schedule(OnExit(GameState::Main)));
+    }
+}
+
+fn player_pos(transform: &Transform) -> Vec2 {
+    vec2(transform.translation.x, transform.translation.y)
+}
+
+fn send_console_info_packet(
+    network_addr: &PlayerNetworkAddr,
+    info: Option<ConsoleInfo>,
+    outgoing_packets: &mut EventWriter<OutgoingPacket>,
+) {
+    // The info is resent every tick so losing one doesn't matter, but the
+    // client has to hear about it when the console is closed.
+    let delivery_type = match info {
+        Some(_) => DeliveryType::UnreliableSequenced,
+        None => DeliveryType::ReliableOrdered,
+    };
+
+    outgoing_packets.send(OutgoingPacket::new(
+        PacketDestination::Single(network_addr.0),
+        ServerToClient::ConsoleInfo(ConsoleInfoPacket { info }),
+        delivery_type,
+        Some(GAME_STATE_STREAM),
+    ));
+}
+
+fn use_console(
+    map: Res<Map>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut use_console_rx: EventReader<UseConsole>,
+    mut players: Query<(&PlayerNetworkAddr, &Transform, &Venting, &mut UsingConsole)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in use_console_rx.iter() {
+        let player_entity = match player_to_entity.0.get(&event.id) {
+            Some(player_entity) => *player_entity,
+            None => continue,
+        };
+
+        let (network_addr, transform, venting, mut using_console) =
+            match players.get_mut(player_entity) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+
+        match event.kind {
+            Some(kind) => {
+                if venting.vent.is_none()
+                    && within_interact_distance(player_pos(transform), map.console_pos(kind))
+                {
+                    using_console.0 = Some(kind);
+                }
+            },
+            None => {
+                if using_console.0.take().is_some() {
+                    send_console_info_packet(network_addr, None, &mut outgoing_packets);
+                }
+            },
+        }
+    }
+}
+
+// Builds what each console shows from the server's own view of the game, and
+// sends it only to the players using that console. Walking away closes it.
+fn send_console_info(
+    map: Res<Map>,
+    closed_doors: Res<ClosedDoors>,
+    mut viewers: Query<(&PlayerNetworkAddr, &Transform, &mut UsingConsole)>,
+    players: Query<(&PlayerId, &Transform, &PlayerState, &Venting)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    // Ghosts and venting impostors don't show up on cameras or the admin table.
+    let visible_positions = || {
+        players
+            .iter()
+            .filter(|(_, _, player_state, venting)| {
+                **player_state == PlayerState::Alive && venting.vent.is_none()
+            })
+            .map(|(id, transform, ..)| (id.0, player_pos(transform)))
+    };
+
+    for (network_addr, transform, mut using_console) in viewers.iter_mut() {
+        let kind = match using_console.0 {
+            Some(kind) => kind,
+            None => continue,
+        };
+
+        if !within_interact_distance(player_pos(transform), map.console_pos(kind)) {
+            using_console.0 = None;
+            send_console_info_packet(network_addr, None, &mut outgoing_packets);
+            continue;
+        }
+
+        let info = match kind {
+            ConsoleKind::Cameras => ConsoleInfo::Cameras {
+                players: visible_positions()
+                    .filter(|(_, pos)| {
+                        map.cameras.iter().any(|camera| {
+                            can_see(&map, &closed_doors, *camera, *pos, CAMERA_RADIUS)
+                        })
+                    })
+                    .map(|(id, pos)| CameraPlayer { id, pos: pos.into() })
+                    .collect(),
+            },
+            ConsoleKind::Admin => {
+                let mut room_counts = vec![0u8; map.rooms.len()];
+
+                for room in visible_positions().filter_map(|(_, pos)| map.room_at(pos)) {
+                    room_counts[room] = room_counts[room].saturating_add(1);
+                }
+
+                ConsoleInfo::Admin { room_counts }
+            },
+            ConsoleKind::Vitals => ConsoleInfo::Vitals {
+                players: players
+                    .iter()
+                    .map(|(id, _, player_state, _)| PlayerVitals {
+                        id: id.0,
+                        player_state: *player_state,
+                    })
+                    .collect(),
+            },
+        };
+
+        send_console_info_packet(network_addr, Some(info), &mut outgoing_packets);
+    }
+}
+
+fn leave_consoles(mut players: Query<&mut UsingConsole>) {
+    for mut using_console in players.iter_mut() {
+        using_console.0 = None;
+    }
+}
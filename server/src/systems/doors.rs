@@ -0,0 +1,129 @@
+use crate::{
+    events::{CloseDoors, OutgoingPacket},
+    systems::PacketDestination,
+};
+use std::time::Duration;
+use sus_common::{
+    map::Map,
+    network::{DeliveryType, DoorStatusPacket, ServerToClient, GAME_STATE_STREAM},
+    resources::{ClosedDoors, PlayerToEntity},
+    simple_game::bevy::{
+        bevy_ecs, bevy_ecs::prelude::in_state, App, CoreSchedule, EventReader, EventWriter,
+        FixedTime, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfigs, OnEnter, Plugin,
+        Query, Res, ResMut, Resource,
+    },
+    GameState, PlayerType,
+};
+
+const DOOR_CLOSE_TIME: Duration = Duration::from_secs(10);
+const DOOR_COOLDOWN: Duration = Duration::from_secs(30);
+
+pub struct DoorsPlugin;
+
+impl Plugin for DoorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<ClosedDoors>()
+            .init_resource::<DoorTimers>()
+            .add_system(open_all_doors.in_schedule(OnEnter(GameState::Lobby)))
+            .add_system(reset_doors.in_schedule(OnEnter(GameState::Main)))
+            .add_systems(
+                (close_doors, update_doors)
+                    .distributive_run_if(in_state(GameState::Main))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+struct DoorGroupTimer {
+    closed_for: Duration,
+    cooldown: Duration,
+}
+
+// Per door group timers, indexed the same way as `ClosedDoors`.
+#[derive(Debug, Default, Resource)]
+struct DoorTimers(Vec<DoorGroupTimer>);
+
+fn broadcast_doors(closed_doors: &ClosedDoors, outgoing_packets: &mut EventWriter<OutgoingPacket>) {
+    outgoing_packets.send(OutgoingPacket::new(
+        PacketDestination::BroadcastToAll,
+        ServerToClient::DoorStatus(DoorStatusPacket { closed_groups: closed_doors.0.clone() }),
+        DeliveryType::ReliableOrdered,
+        Some(GAME_STATE_STREAM),
+    ));
+}
+
+fn open_all_doors(mut closed_doors: ResMut<ClosedDoors>, mut door_timers: ResMut<DoorTimers>) {
+    closed_doors.0.clear();
+    door_timers.0.clear();
+}
+
+fn reset_doors(
+    map: Res<Map>,
+    mut closed_doors: ResMut<ClosedDoors>,
+    mut door_timers: ResMut<DoorTimers>,
+) {
+    let group_count = map.door_group_count();
+
+    closed_doors.0 = vec![false; group_count];
+    door_timers.0 = vec![DoorGroupTimer::default(); group_count];
+}
+
+fn close_doors(
+    player_to_entity: Res<PlayerToEntity>,
+    mut close_doors_rx: EventReader<CloseDoors>,
+    players: Query<&PlayerType>,
+    mut closed_doors: ResMut<ClosedDoors>,
+    mut door_timers: ResMut<DoorTimers>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in close_doors_rx.iter() {
+        let is_impostor = player_to_entity
+            .0
+            .get(&event.id)
+            .and_then(|entity| players.get(*entity).ok())
+            .is_some_and(|player_type| *player_type == PlayerType::Impostor);
+
+        let group = event.group as usize;
+
+        let timer = match door_timers.0.get_mut(group) {
+            Some(timer) if is_impostor && timer.cooldown.is_zero() => timer,
+            _ => continue,
+        };
+
+        timer.closed_for = DOOR_CLOSE_TIME;
+        timer.cooldown = DOOR_COOLDOWN;
+        closed_doors.0[group] = true;
+
+        broadcast_doors(&closed_doors, &mut outgoing_packets);
+    }
+}
+
+fn update_doors(
+    fixed_time: Res<FixedTime>,
+    mut closed_doors: ResMut<ClosedDoors>,
+    mut door_timers: ResMut<DoorTimers>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let dt = fixed_time.period;
+    let mut doors_opened = false;
+
+    for (group, timer) in door_timers.0.iter_mut().enumerate() {
+        timer.cooldown = timer.cooldown.saturating_sub(dt);
+
+        if timer.closed_for.is_zero() {
+            continue;
+        }
+
+        timer.closed_for = timer.closed_for.saturating_sub(dt);
+
+        if timer.closed_for.is_zero() {
+            closed_doors.0[group] = false;
+            doors_opened = true;
+        }
+    }
+
+    if doors_opened {
+        broadcast_doors(&closed_doors, &mut outgoing_packets);
+    }
+}
@@ -0,0 +1,160 @@
+use crate::{
+    components::{KillCooldown, Tasks, UsingConsole, Venting},
+    events::{GameOver, OutgoingPacket},
+    resources::GameRng,
+    systems::PacketDestination,
+};
+use rand::seq::SliceRandom;
+use std::time::Duration;
+use sus_common::{
+    components::player::{PlayerId, PlayerNetworkAddr},
+    network::{
+        DeliveryType, GameOverPacket, RoleAssignmentPacket, ServerToClient, GAME_STATE_STREAM,
+    },
+    settings::GameSettings,
+    simple_game::bevy::{
+        bevy_ecs, bevy_ecs::prelude::in_state, schedule::State, App, CoreSchedule, EventReader,
+        EventWriter, FixedTime, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfig,
+        NextState, OnEnter, Plugin, Query, Res, ResMut, Resource,
+    },
+    tracing::info,
+    GameState, PlayerState, PlayerType,
+};
+
+const INTRO_SCREEN_TIME: Duration = Duration::from_secs(5);
+const END_SCREEN_TIME: Duration = Duration::from_secs(10);
+
+pub struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GameOver>()
+            .insert_resource(PhaseTimer(Duration::ZERO))
+            .add_system(reset_players.in_schedule(OnEnter(GameState::Lobby)))
+            .add_systems((assign_roles, start_intro).in_schedule(OnEnter(GameState::IntroScreen)))
+            .add_system(start_end_screen.in_schedule(OnEnter(GameState::End)))
+            .add_system(
+                update_phase_timer.run_if(in_timed_phase).in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(
+                end_game.run_if(in_state(GameState::Main)).in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+// Counts down how long the intro or end screen has left.
+#[derive(Debug, Resource)]
+struct PhaseTimer(Duration);
+
+fn in_timed_phase(game_state: Res<State<GameState>>) -> bool {
+    matches!(game_state.0, GameState::IntroScreen | GameState::End)
+}
+
+// Nothing about the last game carries over into the next.
+fn reset_players(
+    mut players: Query<(
+        &mut PlayerType,
+        &mut PlayerState,
+        &mut Tasks,
+        &mut Venting,
+        &mut KillCooldown,
+        &mut UsingConsole,
+    )>,
+) {
+    for (
+        mut player_type,
+        mut player_state,
+        mut tasks,
+        mut venting,
+        mut kill_cooldown,
+        mut using_console,
+    ) in players.iter_mut()
+    {
+        *player_type = PlayerType::Crew;
+        *player_state = PlayerState::Alive;
+        tasks.0.clear();
+        *venting = Venting::default();
+        *kill_cooldown = KillCooldown::default();
+        *using_console = UsingConsole::default();
+    }
+}
+
+pub fn assign_roles(
+    settings: Res<GameSettings>,
+    mut rng: ResMut<GameRng>,
+    mut players: Query<(&PlayerId, &PlayerNetworkAddr, &mut PlayerType, &mut PlayerState)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    // Sort so the same RNG state always picks the same impostors.
+    let mut ids: Vec<u16> = players.iter().map(|(id, ..)| id.0).collect();
+    ids.sort_unstable();
+
+    let impostor_count = settings.impostor_count as usize;
+    let impostors: Vec<u16> = ids.choose_multiple(&mut rng.0, impostor_count).copied().collect();
+
+    for (id, network_addr, mut player_type, mut player_state) in players.iter_mut() {
+        *player_state = PlayerState::Alive;
+
+        let packet = if impostors.contains(&id.0) {
+            *player_type = PlayerType::Impostor;
+            RoleAssignmentPacket { player_type: PlayerType::Impostor, impostors: impostors.clone() }
+        } else {
+            *player_type = PlayerType::Crew;
+            RoleAssignmentPacket { player_type: PlayerType::Crew, impostors: vec![] }
+        };
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::Single(network_addr.0),
+            ServerToClient::RoleAssignment(packet),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
+
+fn start_intro(mut phase_timer: ResMut<PhaseTimer>) {
+    phase_timer.0 = INTRO_SCREEN_TIME;
+}
+
+fn start_end_screen(mut phase_timer: ResMut<PhaseTimer>) {
+    phase_timer.0 = END_SCREEN_TIME;
+}
+
+fn update_phase_timer(
+    fixed_time: Res<FixedTime>,
+    game_state: Res<State<GameState>>,
+    mut phase_timer: ResMut<PhaseTimer>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    phase_timer.0 = phase_timer.0.saturating_sub(fixed_time.period);
+
+    if phase_timer.0.is_zero() {
+        match game_state.0 {
+            GameState::IntroScreen => next_state.set(GameState::Main),
+            GameState::End => next_state.set(GameState::Lobby),
+            _ => {},
+        }
+    }
+}
+
+fn end_game(
+    mut game_over_rx: EventReader<GameOver>,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    // Only the first reason the game ended counts.
+    if let Some(game_over) = game_over_rx.iter().next() {
+        info!(winner = ?game_over.winner, "Game over");
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToAll,
+            ServerToClient::GameOver(GameOverPacket { winner: game_over.winner }),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+
+        next_state.set(GameState::End);
+    }
+
+    game_over_rx.clear();
+}
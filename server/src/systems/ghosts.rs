@@ -0,0 +1,143 @@
+use crate::{
+    components::{KillCooldown, Venting},
+    events::{GameOver, Kill, OutgoingPacket},
+    systems::PacketDestination,
+};
+use std::time::Duration;
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    map::Map,
+    network::{DeliveryType, PlayerStatusPacket, ServerToClient, GAME_STATE_STREAM},
+    resources::{ClosedDoors, PlayerToEntity},
+    settings::GameSettings,
+    simple_game::{
+        bevy::{
+            bevy_ecs::prelude::in_state, App, CoreSchedule, DetectChanges, EventReader,
+            EventWriter, FixedTime, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfigs,
+            OnEnter, Plugin, Query, Ref, Res, Transform,
+        },
+        glam::vec2,
+    },
+    tracing::info,
+    GameState, PlayerState, PlayerType,
+};
+
+pub struct GhostsPlugin;
+
+impl Plugin for GhostsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(reset_kill_cooldowns.in_schedule(OnEnter(GameState::Main)))
+            .add_systems(
+                (kill_player, update_kill_cooldowns)
+                    .distributive_run_if(in_state(GameState::Main))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
+            .add_system(send_player_status.in_schedule(CoreSchedule::FixedUpdate));
+    }
+}
+
+fn kill_cooldown_duration(settings: &GameSettings) -> Duration {
+    Duration::from_secs_f32(settings.kill_cooldown_secs)
+}
+
+fn reset_kill_cooldowns(settings: Res<GameSettings>, mut players: Query<&mut KillCooldown>) {
+    for mut kill_cooldown in players.iter_mut() {
+        kill_cooldown.0 = kill_cooldown_duration(&settings);
+    }
+}
+
+fn update_kill_cooldowns(fixed_time: Res<FixedTime>, mut players: Query<&mut KillCooldown>) {
+    for mut kill_cooldown in players.iter_mut() {
+        kill_cooldown.0 = kill_cooldown.0.saturating_sub(fixed_time.period);
+    }
+}
+
+// An alive impostor can kill a living crew member they can see who is standing
+// close enough. The impostors win once they're no longer outnumbered.
+fn kill_player(
+    map: Res<Map>,
+    settings: Res<GameSettings>,
+    closed_doors: Res<ClosedDoors>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut kill_rx: EventReader<Kill>,
+    mut players: Query<(&Transform, &PlayerType, &mut PlayerState, &Venting, &mut KillCooldown)>,
+    mut game_over_tx: EventWriter<GameOver>,
+) {
+    let mut anyone_killed = false;
+
+    for event in kill_rx.iter() {
+        let (killer_entity, target_entity) =
+            match (player_to_entity.0.get(&event.id), player_to_entity.0.get(&event.target)) {
+                (Some(killer), Some(target)) if killer != target => (*killer, *target),
+                _ => continue,
+            };
+
+        let [killer, target] = match players.get_many_mut([killer_entity, target_entity]) {
+            Ok(players) => players,
+            Err(_) => continue,
+        };
+
+        let (killer_transform, killer_type, killer_state, killer_venting, mut kill_cooldown) =
+            killer;
+        let (target_transform, target_type, mut target_state, ..) = target;
+
+        if *killer_type != PlayerType::Impostor
+            || *killer_state != PlayerState::Alive
+            || killer_venting.vent.is_some()
+            || !kill_cooldown.0.is_zero()
+        {
+            continue;
+        }
+
+        if *target_type != PlayerType::Crew || *target_state != PlayerState::Alive {
+            continue;
+        }
+
+        let killer_pos = vec2(killer_transform.translation.x, killer_transform.translation.y);
+        let target_pos = vec2(target_transform.translation.x, target_transform.translation.y);
+
+        if killer_pos.distance(target_pos) > settings.kill_distance
+            || !map.line_of_sight(&closed_doors, killer_pos, target_pos)
+        {
+            continue;
+        }
+
+        info!(player = event.id, target = event.target, "Kill");
+
+        *target_state = PlayerState::Dead;
+        kill_cooldown.0 = kill_cooldown_duration(&settings);
+        anyone_killed = true;
+    }
+
+    if anyone_killed {
+        let alive_count = |player_type| {
+            players
+                .iter()
+                .filter(|(_, t, state, ..)| **t == player_type && **state == PlayerState::Alive)
+                .count()
+        };
+
+        if alive_count(PlayerType::Impostor) >= alive_count(PlayerType::Crew) {
+            game_over_tx.send(GameOver { winner: PlayerType::Impostor });
+        }
+    }
+}
+
+// Lets each player know when they die, and when they're revived for the next game.
+fn send_player_status(
+    players: Query<(&PlayerNetworkAddr, Ref<PlayerState>)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for (network_addr, player_state) in players.iter() {
+        if !player_state.is_changed() {
+            continue;
+        }
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::Single(network_addr.0),
+            ServerToClient::PlayerStatus(PlayerStatusPacket { player_state: *player_state }),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
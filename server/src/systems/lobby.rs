@@ -1,36 +1,37 @@
 use crate::{
-    components::ServerPlayerBundle,
-    events::{NewPlayer, OutgoingPacket, PlayerInput},
-    resources::AddrToPlayer,
+    components::{Bot, Ready, ServerPlayerBundle, Venting},
+    events::{LobbyAction, LobbyRequest, NewPlayer, OutgoingPacket},
+    resources::{AddrToPlayer, Bots, Host, LightsOn, RoomCode, RoomTick, Spectators},
     systems::{
-        lobby::bevy_ecs::prelude::in_state, network::PlayerIdCounter, sets, PacketDestination,
+        lobby::bevy_ecs::prelude::in_state, network::PlayerIdCounter, network_send, sets,
+        PacketDestination,
     },
 };
-use std::{
-    collections::VecDeque,
-    time::{Duration, Instant},
-};
+use std::time::Duration;
 use sus_common::{
     components::player::{
         LastInputCounter, PlayerId, PlayerName, PlayerNetworkAddr, PositionHistory,
-        UnprocessedInputs,
     },
-    math::NormalizedInt,
+    map::Map,
     network::{
-        ConnectAckPacket, DeliveryType, FullGameStatePacket, LobbyPlayer, LobbyTickPacket,
-        NewPlayerPacket, SequenceCmp, ServerToClient, GAME_STATE_STREAM,
+        ConnectAckPacket, DeliveryType, FullGameStatePacket, LobbyPlayer, LobbyStatusPacket,
+        LobbyTickPacket, NewPlayerPacket, ServerToClient, GAME_STATE_STREAM, MAX_POSITION_HISTORY,
     },
-    resources::PlayerToEntity,
+    protocol::{Capabilities, SUPPORTED_PROTOCOL},
+    resources::{ClosedDoors, PlayerToEntity},
+    settings::GameSettings,
     simple_game::{
         bevy::{
-            bevy_ecs, bevy_ecs::event::Events, schedule::State, App, Commands, Component,
-            CoreSchedule, EventReader, EventWriter, IntoSystemAppConfig, IntoSystemAppConfigs,
-            IntoSystemConfig, IntoSystemConfigs, NextState, OnEnter, OnExit, OnUpdate, Plugin,
-            Query, Res, ResMut, Transform,
+            bevy_ecs, bevy_ecs::event::Events, schedule::State, Added, App, Commands, CoreSchedule,
+            EventReader, EventWriter, FixedTime, IntoSystemAppConfig, IntoSystemAppConfigs,
+            IntoSystemConfig, IntoSystemConfigs, Local, NextState, OnEnter, OnExit, Plugin, Query,
+            Res, ResMut, Resource, Transform, Without,
         },
-        glam::{vec3, Vec3},
+        glam::vec2,
     },
-    GameState,
+    tracing::info,
+    vision::can_see,
+    GameState, PlayerState, PlayerType,
 };
 
 #[allow(unused)]
@@ -46,114 +47,264 @@ impl LobbyPlugin {
 
 impl Plugin for LobbyPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup)
+        app.insert_resource(StartCountdown(None))
             .add_system(setup_lobby.in_schedule(OnEnter(GameState::Lobby)))
+            .add_system(
+                new_player_joined
+                    .in_set(sets::Lobby)
+                    .after(sets::Network)
+                    .run_if(in_state(GameState::Lobby))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            )
             .add_systems(
                 (
-                    handle_player_input
-                        .in_set(sets::NetworkSystem::PlayerInput)
-                        .after(sets::NetworkSystem::Receive),
-                    update_lobby.after(sets::NetworkSystem::PlayerInput),
-                    new_player_joined,
+                    handle_lobby_requests,
+                    update_start_countdown.after(handle_lobby_requests),
+                    send_lobby_status.after(update_start_countdown),
                 )
-                    .in_set(sets::Lobby)
-                    .after(sets::Network)
+                    .after(sets::Lobby)
                     .distributive_run_if(in_state(GameState::Lobby))
                     .in_schedule(CoreSchedule::FixedUpdate),
             )
-            .add_system(update_lobby_timer.after(sets::Lobby).in_set(OnUpdate(GameState::Lobby)))
-            .add_system(send_new_state.in_set(sets::NetworkSystem::SendPackets))
+            .add_system(
+                send_new_state.in_set(sets::NetworkSystem::SendPackets).before(network_send),
+            )
             .add_system(close_lobby.in_schedule(OnExit(GameState::Lobby)));
     }
 }
 
-#[derive(Component)]
-struct LobbyTimer(Instant);
-const LOBBY_COUNTDOWN_TIME: Duration = Duration::from_secs(50);
+const START_COUNTDOWN_TIME: Duration = Duration::from_secs(5);
 
-fn setup(mut commands: Commands) {
-    commands.spawn(LobbyTimer(Instant::now()));
-}
+// Counts down to the start of the game once the host starts it. None while
+// waiting in the lobby.
+#[derive(Debug, Resource)]
+struct StartCountdown(Option<Duration>);
 
-fn setup_lobby() {
-    println!("Lobby started");
+// Everyone has to be ready, and there need to be enough crew to outnumber the
+// impostors by more than one. Bots count as players.
+fn can_start(
+    settings: &GameSettings,
+    connected: &AddrToPlayer,
+    bots: &Bots,
+    players: &Query<(&PlayerId, &Ready)>,
+) -> bool {
+    let min_players = settings.impostor_count as usize * 2 + 1;
+    let all_ready = players
+        .iter()
+        .filter(|(id, _)| {
+            connected.0.values().any(|player| *player == id.0) || bots.0.contains(&id.0)
+        })
+        .all(|(_, ready)| ready.0);
+
+    connected.0.len() + bots.0.len() >= min_players && all_ready
 }
 
-fn update_lobby(
-    mut players: Query<(
-        &PlayerId,
-        &mut Transform,
-        &mut UnprocessedInputs,
-        &mut PositionHistory,
-        &mut LastInputCounter,
-    )>,
+// Bots stay ready between games.
+fn setup_lobby(
+    mut countdown: ResMut<StartCountdown>,
+    mut players: Query<&mut Ready, Without<Bot>>,
 ) {
-    // println!("Lobby tick");
+    info!("Lobby started");
 
-    for (
-        _player_id,
-        mut transform,
-        mut unprocessed_inputs,
-        mut position_history,
-        mut last_input_counter,
-    ) in players.iter_mut()
-    {
-        if let Some(input) = unprocessed_inputs.0.pop_front() {
-            if input.counter.sequentially_greater_than(last_input_counter.0) {
-                last_input_counter.0 = input.counter;
-                // println!("Moving player ID {} with input {:?}", player_id.0, input);
+    countdown.0 = None;
+
+    for mut ready in players.iter_mut() {
+        ready.0 = false;
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn handle_lobby_requests(
+    settings: Res<GameSettings>,
+    host: Res<Host>,
+    connected: Res<AddrToPlayer>,
+    bots: Res<Bots>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut lobby_request_rx: EventReader<LobbyRequest>,
+    mut countdown: ResMut<StartCountdown>,
+    mut players: Query<(&PlayerId, &mut Ready)>,
+) {
+    for event in lobby_request_rx.iter() {
+        let is_host = host.0 == Some(event.id);
 
-                let velocity = vec3(input.x.normalized(), input.y.normalized(), 0.0);
+        match event.action {
+            LobbyAction::SetReady(is_ready) => {
+                let player = player_to_entity.0.get(&event.id);
 
-                position_history.0.push((transform.translation.x, transform.translation.y));
-                transform.translation += velocity * 0.1;
-            }
+                if let Some(Ok((_, mut ready))) = player.map(|entity| players.get_mut(*entity)) {
+                    ready.0 = is_ready;
+                }
+            },
+            LobbyAction::Start
+                if is_host
+                    && countdown.0.is_none()
+                    && can_start(&settings, &connected, &bots, &players.to_readonly()) =>
+            {
+                info!(player = event.id, countdown = ?START_COUNTDOWN_TIME, "Starting the game");
+                countdown.0 = Some(START_COUNTDOWN_TIME);
+            },
+            LobbyAction::CancelStart if is_host => countdown.0 = None,
+            _ => {},
         }
     }
 }
 
-fn update_lobby_timer(
-    game_state: Res<State<GameState>>,
+// The countdown is called off if someone stops being ready, or too many
+// players leave.
+fn update_start_countdown(
+    fixed_time: Res<FixedTime>,
+    settings: Res<GameSettings>,
+    connected: Res<AddrToPlayer>,
+    bots: Res<Bots>,
+    mut countdown: ResMut<StartCountdown>,
+    players: Query<(&PlayerId, &Ready)>,
     mut next_state: ResMut<NextState<GameState>>,
-    lobby_timer: Query<&LobbyTimer>,
 ) {
-    let lobby_timer = lobby_timer.single().0;
+    let time_left = match &mut countdown.0 {
+        Some(time_left) => time_left,
+        None => return,
+    };
 
-    if lobby_timer.elapsed() > LOBBY_COUNTDOWN_TIME {
-        println!("Leaving lobby!");
-        if game_state.0 == GameState::Lobby {
-            next_state.set(GameState::IntroScreen);
-        }
+    if !can_start(&settings, &connected, &bots, &players) {
+        info!("Start cancelled");
+        countdown.0 = None;
+        return;
+    }
+
+    *time_left = time_left.saturating_sub(fixed_time.period);
+
+    if time_left.is_zero() {
+        info!("Leaving the lobby");
+        next_state.set(GameState::IntroScreen);
     }
 }
 
+fn send_lobby_status(
+    countdown: Res<StartCountdown>,
+    mut last_status: Local<Option<LobbyStatusPacket>>,
+    players: Query<(&PlayerId, &Ready)>,
+    new_players: Query<&PlayerNetworkAddr, Added<PlayerNetworkAddr>>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let mut ready: Vec<u16> =
+        players.iter().filter(|(_, ready)| ready.0).map(|(id, _)| id.0).collect();
+    ready.sort_unstable();
+
+    let status = LobbyStatusPacket {
+        ready,
+        countdown_secs: countdown.0.map(|time_left| time_left.as_secs_f32().ceil() as u8),
+    };
+
+    let destination = if last_status.as_ref() != Some(&status) {
+        PacketDestination::BroadcastToAll
+    } else {
+        let new_addrs: Vec<_> = new_players.iter().map(|network_addr| network_addr.0).collect();
+
+        if new_addrs.is_empty() {
+            return;
+        }
+
+        PacketDestination::BroadcastToSet(new_addrs)
+    };
+
+    outgoing_packets.send(OutgoingPacket::new(
+        destination,
+        ServerToClient::LobbyStatus(status.clone()),
+        DeliveryType::ReliableOrdered,
+        Some(GAME_STATE_STREAM),
+    ));
+
+    *last_status = Some(status);
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn send_new_state(
+    game_state: Res<State<GameState>>,
+    room_tick: Res<RoomTick>,
+    map: Res<Map>,
+    settings: Res<GameSettings>,
+    lights_on: Res<LightsOn>,
+    closed_doors: Res<ClosedDoors>,
     mut players: Query<(
         &PlayerId,
         &Transform,
         &PlayerNetworkAddr,
         &mut PositionHistory,
         &LastInputCounter,
+        &PlayerType,
+        &PlayerState,
+        &Venting,
     )>,
     mut outgoing_packets: EventWriter<OutgoingPacket>,
 ) {
     let players_vec: Vec<_> = players
         .iter()
-        .map(|(id, transform, _, position_history, _)| LobbyPlayer {
-            id: id.0,
-            pos: (transform.translation.x, transform.translation.y),
-            pos_history: position_history.0.clone(),
+        .map(|(id, transform, _, position_history, _, _, player_state, venting)| {
+            let player = LobbyPlayer {
+                id: id.0,
+                pos: (transform.translation.x, transform.translation.y),
+                // Only the most recent moves, if the server has fallen behind.
+                pos_history: position_history.0
+                    [position_history.0.len().saturating_sub(MAX_POSITION_HISTORY)..]
+                    .to_vec(),
+            };
+
+            (player, *player_state, venting.vent.is_some())
         })
         .collect();
 
-    for (_player_id, _transform, network_addr, mut position_history, last_input_counter) in
-        players.iter_mut()
+    for (
+        player_id,
+        transform,
+        network_addr,
+        mut position_history,
+        last_input_counter,
+        player_type,
+        player_state,
+        _venting,
+    ) in players.iter_mut()
     {
         position_history.0.clear();
 
+        let viewer = vec2(transform.translation.x, transform.translation.y);
+        let radius = settings.vision.radius(*player_type, *player_state, lights_on.0);
+
+        // Everyone can see each other in the lobby. Once the game starts, only send
+        // the players this one can actually see so a modified client can't reveal
+        // the rest of the map. Ghosts are hidden from the living, and see everyone
+        // nearby straight through walls.
+        let visible_players = players_vec
+            .iter()
+            .filter(|(player, target_state, in_vent)| {
+                if player.id == player_id.0 {
+                    return true;
+                }
+
+                if *in_vent {
+                    return false;
+                }
+
+                if game_state.0 == GameState::Lobby {
+                    return true;
+                }
+
+                let target = player.pos.into();
+
+                match (player_state, target_state) {
+                    (PlayerState::Alive, PlayerState::Dead) => false,
+                    (PlayerState::Alive, PlayerState::Alive) => {
+                        can_see(&map, &closed_doors, viewer, target, radius)
+                    },
+                    (PlayerState::Dead, _) => viewer.distance_squared(target) <= radius * radius,
+                }
+            })
+            .map(|(player, ..)| player.clone())
+            .collect();
+
         let packet = ServerToClient::LobbyTick(LobbyTickPacket {
             last_input_counter: last_input_counter.0,
-            players: players_vec.clone(),
+            players: visible_players,
+            tick: room_tick.0,
         });
 
         outgoing_packets.send(OutgoingPacket::new(
@@ -165,26 +316,13 @@ fn send_new_state(
     }
 }
 
-fn handle_player_input(
-    mut input_rx: EventReader<PlayerInput>,
-    player_to_entity: Res<PlayerToEntity>,
-    mut unprocessed_inputs: Query<&mut UnprocessedInputs>,
-) {
-    for event in input_rx.iter() {
-        if let Some(player_entity) = player_to_entity.0.get(&event.id) {
-            // println!("Player (id={}) sent input: {:?}", event.id, event.input);
-
-            if let Ok(mut unprocessed_input) = unprocessed_inputs.get_mut(*player_entity) {
-                unprocessed_input.0.push_back(event.input);
-            }
-        }
-    }
-}
-
+#[allow(clippy::too_many_arguments)]
 fn new_player_joined(
     mut commands: Commands,
+    room_code: Res<RoomCode>,
     mut new_player_rx: ResMut<Events<NewPlayer>>,
     mut players: ResMut<AddrToPlayer>,
+    spectators: Res<Spectators>,
     mut player_to_entity: ResMut<PlayerToEntity>,
     mut player_id_counter: ResMut<PlayerIdCounter>,
     mut outgoing_packets: EventWriter<OutgoingPacket>,
@@ -193,28 +331,49 @@ fn new_player_joined(
     let player_id_counter = &mut player_id_counter.0;
 
     for new_player in new_player_rx.drain() {
+        // They asked to spectate before this got to them, or sent more than
+        // one Connect before the first was handled.
+        if spectators.0.contains(&new_player.addr) || players.0.contains_key(&new_player.addr) {
+            continue;
+        }
+
+        // Incompatible clients are already turned away before reaching a room.
+        let protocol_version =
+            match SUPPORTED_PROTOCOL.negotiate(&new_player.connect_packet.protocol) {
+                Some(version) => version,
+                None => continue,
+            };
+        let capabilities =
+            Capabilities::SUPPORTED.intersection(new_player.connect_packet.capabilities);
+
         let new_player_id = *player_id_counter;
         *player_id_counter += 1;
 
-        println!("Spawning new player with id {}", new_player_id);
+        info!(
+            player = new_player_id,
+            addr = %new_player.addr,
+            name = %new_player.connect_packet.name,
+            "Player joined"
+        );
 
         let entity_id = commands
-            .spawn(ServerPlayerBundle {
-                id: PlayerId(new_player_id),
-                name: PlayerName(new_player.connect_packet.name.clone()),
-                network_addr: PlayerNetworkAddr(new_player.addr),
-                unprocessed_inputs: UnprocessedInputs(VecDeque::new()),
-                position_history: PositionHistory(Vec::new()),
-                last_input_counter: LastInputCounter(0),
-                transform: Transform::from_translation(Vec3::ZERO),
-            })
+            .spawn(ServerPlayerBundle::new(
+                new_player_id,
+                new_player.connect_packet.name.clone(),
+                new_player.addr,
+                capabilities,
+            ))
             .id();
 
-        // TODO - handle this HashMap cleanup on player disconnect.
         players.0.insert(new_player.addr, new_player_id);
         player_to_entity.0.insert(new_player_id, entity_id);
 
-        let reply = ServerToClient::ConnectAck(ConnectAckPacket { id: new_player_id });
+        let reply = ServerToClient::ConnectAck(ConnectAckPacket {
+            id: new_player_id,
+            room_code: room_code.0.clone(),
+            protocol_version,
+            capabilities,
+        });
 
         outgoing_packets.send(OutgoingPacket::new(
             PacketDestination::Single(new_player.addr),
@@ -255,5 +414,5 @@ fn new_player_joined(
 }
 
 fn close_lobby() {
-    println!("lobby is closed");
+    info!("Lobby closed");
 }
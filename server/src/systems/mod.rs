@@ -1,6 +1,34 @@
+pub mod admin;
+pub mod bots;
+pub mod chat;
+pub mod consoles;
+pub mod doors;
+pub mod game;
+pub mod ghosts;
 pub mod lobby;
+pub mod movement;
 pub mod network;
+pub mod ping;
+pub mod sabotage;
 pub mod sets;
+pub mod settings;
+pub mod spectators;
+pub mod tasks;
+pub mod vents;
 
+pub use admin::*;
+pub use bots::*;
+pub use chat::*;
+pub use consoles::*;
+pub use doors::*;
+pub use game::*;
+pub use ghosts::*;
 pub use lobby::*;
+pub use movement::*;
 pub use network::*;
+pub use ping::*;
+pub use sabotage::*;
+pub use settings::*;
+pub use spectators::*;
+pub use tasks::*;
+pub use vents::*;
@@ -0,0 +1,108 @@
+use crate::{components::Venting, events::PlayerInput, systems::sets};
+use sus_common::{
+    components::player::{LastInputCounter, PlayerId, PositionHistory, UnprocessedInputs},
+    map::Map,
+    movement::{move_ghost, move_player},
+    network::SequenceCmp,
+    resources::{ClosedDoors, PlayerToEntity},
+    settings::GameSettings,
+    simple_game::{
+        bevy::{
+            schedule::State, App, CoreSchedule, EventReader, IntoSystemAppConfigs,
+            IntoSystemConfig, IntoSystemConfigs, Plugin, Query, Res, Transform,
+        },
+        glam::{vec2, vec3},
+    },
+    tracing::trace,
+    GameState, PlayerState,
+};
+
+pub struct MovementPlugin;
+
+impl Plugin for MovementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (
+                handle_player_input
+                    .in_set(sets::NetworkSystem::PlayerInput)
+                    .after(sets::NetworkSystem::Receive),
+                move_players.after(sets::NetworkSystem::PlayerInput),
+            )
+                .after(sets::Network)
+                .distributive_run_if(players_can_move)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+// Players can walk around in the lobby and during the main game, but not
+// while the intro or end screens are up.
+pub fn players_can_move(game_state: Res<State<GameState>>) -> bool {
+    matches!(game_state.0, GameState::Lobby | GameState::Main)
+}
+
+fn handle_player_input(
+    mut input_rx: EventReader<PlayerInput>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut unprocessed_inputs: Query<&mut UnprocessedInputs>,
+) {
+    for event in input_rx.iter() {
+        if let Some(player_entity) = player_to_entity.0.get(&event.id) {
+            trace!(player = event.id, input = ?event.input, "Input queued");
+
+            if let Ok(mut unprocessed_input) = unprocessed_inputs.get_mut(*player_entity) {
+                unprocessed_input.0.push_back(event.input);
+            }
+        }
+    }
+}
+
+fn move_players(
+    map: Res<Map>,
+    settings: Res<GameSettings>,
+    closed_doors: Res<ClosedDoors>,
+    mut players: Query<(
+        &PlayerId,
+        &mut Transform,
+        &mut UnprocessedInputs,
+        &mut PositionHistory,
+        &mut LastInputCounter,
+        &Venting,
+        &PlayerState,
+    )>,
+) {
+    for (
+        player_id,
+        mut transform,
+        mut unprocessed_inputs,
+        mut position_history,
+        mut last_input_counter,
+        venting,
+        player_state,
+    ) in players.iter_mut()
+    {
+        if let Some(input) = unprocessed_inputs.0.pop_front() {
+            if input.counter.sequentially_greater_than(last_input_counter.0) {
+                last_input_counter.0 = input.counter;
+
+                // Inputs still get acknowledged while hiding in a vent, they just
+                // don't move the player.
+                if venting.vent.is_some() {
+                    continue;
+                }
+
+                trace!(player = player_id.0, ?input, "Moving player");
+
+                let pos = vec2(transform.translation.x, transform.translation.y);
+                let (speed, x, y) = (settings.player_speed, input.x, input.y);
+                let new_pos = match player_state {
+                    PlayerState::Alive => move_player(&map, &closed_doors, speed, pos, x, y),
+                    PlayerState::Dead => move_ghost(speed, pos, x, y),
+                };
+
+                position_history.0.push((pos.x, pos.y));
+                transform.translation = vec3(new_pos.x, new_pos.y, 0.0);
+            }
+        }
+    }
+}
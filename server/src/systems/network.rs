@@ -1,38 +1,80 @@
 use crate::{
-    events::{NewPlayer, OutgoingPacket, PlayerInput},
-    resources::AddrToPlayer,
+    events::{
+        Chat, CloseDoors, CompleteTask, FixSabotage, Kill, LobbyAction, LobbyRequest, NewPlayer,
+        NewSpectator, OutgoingPacket, PlayerInput, PlayerLeft, Pong, SabotageFix, StartSabotage,
+        UpdateSettings, UseConsole, UseVent, VentAction,
+    },
+    resources::{AddrToPlayer, DecodeViolations, Spectators},
     systems::sets,
-    TICK_RATE_HZ,
 };
-use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use std::{collections::HashMap, net::SocketAddr, time::Instant};
 use sus_common::{
     components::player::PlayerNetworkAddr,
-    laminar::{Config as NetworkConfig, Socket, SocketEvent},
-    network::{make_packet, ClientToServer},
+    crossbeam_channel::{Receiver, Sender},
+    laminar::{Packet, SocketEvent},
+    network::{
+        decode_client_message, encode_server_message, make_packet, ClientToServer, DeliveryType,
+        PlayerLeftPacket, ServerToClient, GAME_STATE_STREAM,
+    },
     resources::{
-        network::{NetRx, NetTx, NetworkThread},
+        network::{NetRx, NetTx},
         PlayerToEntity,
     },
     simple_game::bevy::{
-        bevy_ecs, bevy_ecs::event::Events, App, Commands, EventWriter, IntoSystemConfig, Plugin,
-        Query, Res, ResMut, Resource,
+        bevy_ecs,
+        bevy_ecs::{event::Events, system::SystemParam},
+        App, Commands, EventReader, EventWriter, IntoSystemConfig, Plugin, Query, Res, ResMut,
+        Resource,
     },
+    tracing::{debug, error, info, trace},
 };
 
-const BIND_ADDR: &str = "0.0.0.0:7600";
+// Each room gets its own copy of this plugin. Incoming events are forwarded
+// to `net_rx` by the room router, and everything sent on `net_tx` goes out
+// through the one shared socket.
+pub struct ServerNetworkPlugin {
+    net_tx: Sender<Packet>,
+    net_rx: Receiver<SocketEvent>,
+}
 
-pub struct ServerNetworkPlugin;
+impl ServerNetworkPlugin {
+    pub fn new(net_tx: Sender<Packet>, net_rx: Receiver<SocketEvent>) -> Self {
+        Self { net_tx, net_rx }
+    }
+}
 
 #[derive(Debug, Resource)]
 pub struct PlayerIdCounter(pub u16);
 
 impl Plugin for ServerNetworkPlugin {
     fn build(&self, app: &mut App) {
-        app.add_startup_system(setup)
+        app.insert_resource(NetTx(self.net_tx.clone()))
+            .insert_resource(NetRx(self.net_rx.clone()))
+            .add_startup_system(setup)
             .add_event::<PlayerInput>()
+            .add_event::<CompleteTask>()
+            .add_event::<StartSabotage>()
+            .add_event::<FixSabotage>()
+            .add_event::<UseVent>()
+            .add_event::<CloseDoors>()
+            .add_event::<Kill>()
+            .add_event::<Chat>()
+            .add_event::<UseConsole>()
+            .add_event::<UpdateSettings>()
+            .add_event::<LobbyRequest>()
+            .add_event::<NewSpectator>()
+            .add_event::<Pong>()
+            .add_event::<PlayerLeft>()
+            .init_resource::<Spectators>()
+            .init_resource::<DecodeViolations>()
             .init_resource::<Events<NewPlayer>>()
             .init_resource::<Events<OutgoingPacket>>()
             .add_system(network_receive.in_set(sets::NetworkSystem::Receive).in_set(sets::Network))
+            .add_system(
+                remove_players
+                    .after(sets::NetworkSystem::Receive)
+                    .before(sets::NetworkSystem::SendPackets),
+            )
             .add_system(
                 network_send
                     .in_set(sets::NetworkSystem::SendPackets)
@@ -43,91 +85,228 @@ impl Plugin for ServerNetworkPlugin {
 }
 
 fn setup(mut commands: Commands) {
-    let mut socket = initialize_network();
-    let (net_tx, net_rx) = (socket.get_packet_sender(), socket.get_event_receiver());
-
-    let network_thread = std::thread::spawn(move || {
-        socket.start_polling_with_duration(Some(std::time::Duration::from_millis(
-            (1000 / TICK_RATE_HZ / 2) as u64,
-        )))
-    });
-
-    commands.insert_resource(NetworkThread(network_thread));
-    commands.insert_resource(NetTx(net_tx));
-    commands.insert_resource(NetRx(net_rx));
     commands.insert_resource(AddrToPlayer(HashMap::new()));
     commands.insert_resource(PlayerToEntity(HashMap::new()));
     commands.insert_resource(PlayerIdCounter(0));
 }
 
-fn initialize_network() -> Socket {
-    // TODO(bschwind) - Remove this once we start having a steady flow of packets.
-    let net_config = NetworkConfig {
-        idle_connection_timeout: Duration::from_secs(5),
-        heartbeat_interval: Some(Duration::from_secs(4)),
-        ..NetworkConfig::default()
-    };
-
-    let socket =
-        Socket::bind_with_config(BIND_ADDR, net_config).expect("Couldn't bind to server BIND_ADDR");
-
-    println!("Listening on {:?}", BIND_ADDR);
-
-    socket
+// Who is connected from where, grouped so `network_receive` stays under the
+// system parameter limit.
+#[derive(SystemParam)]
+struct Connections<'w> {
+    players: ResMut<'w, AddrToPlayer>,
+    spectators: ResMut<'w, Spectators>,
+    new_player_tx: EventWriter<'w, NewPlayer>,
+    new_spectator_tx: EventWriter<'w, NewSpectator>,
+    player_left_tx: EventWriter<'w, PlayerLeft>,
 }
 
+#[allow(clippy::too_many_arguments)]
 fn network_receive(
-    mut players: ResMut<AddrToPlayer>,
+    mut connections: Connections,
     net_rx: Res<NetRx>,
-    mut new_player_tx: EventWriter<NewPlayer>,
+    mut violations: ResMut<DecodeViolations>,
     mut input_tx: EventWriter<PlayerInput>,
+    mut complete_task_tx: EventWriter<CompleteTask>,
+    mut start_sabotage_tx: EventWriter<StartSabotage>,
+    mut fix_sabotage_tx: EventWriter<FixSabotage>,
+    mut use_vent_tx: EventWriter<UseVent>,
+    mut close_doors_tx: EventWriter<CloseDoors>,
+    mut kill_tx: EventWriter<Kill>,
+    mut chat_tx: EventWriter<Chat>,
+    mut use_console_tx: EventWriter<UseConsole>,
+    mut update_settings_tx: EventWriter<UpdateSettings>,
+    mut lobby_request_tx: EventWriter<LobbyRequest>,
+    mut pong_tx: EventWriter<Pong>,
 ) {
-    let players = &mut players.0;
+    let players = &mut connections.players.0;
+    let spectators = &mut connections.spectators.0;
     let net_rx = &net_rx.0;
-
-    // println!("Network tick");
+    let now = Instant::now();
 
     for event in net_rx.try_iter() {
         match event {
             SocketEvent::Packet(packet) => {
                 let msg = packet.payload();
 
-                if let Ok(decoded) = bincode::deserialize::<ClientToServer>(msg) {
-                    match decoded {
-                        ClientToServer::Connect(connect_packet) => {
-                            new_player_tx.send(NewPlayer { addr: packet.addr(), connect_packet });
-                        },
-                        ClientToServer::PlayerInput(input) => {
-                            if let Some(player_id) = players.get(&packet.addr()) {
-                                input_tx.send(PlayerInput { id: *player_id, input });
-                            }
-                        },
-                    }
-                } else {
-                    println!("Received an invalid packet");
+                let decoded = match decode_client_message(msg) {
+                    Ok(decoded) => decoded,
+                    Err(e) => {
+                        violations.record(packet.addr(), &e, now);
+                        continue;
+                    },
+                };
+
+                trace!(addr = %packet.addr(), message = ?decoded, "Received");
+
+                // Nobody gets to be both a player and a spectator, or they could
+                // play with everyone in view.
+                match decoded {
+                    ClientToServer::Connect(connect_packet) => {
+                        // Connecting again would spawn a second player with
+                        // nobody left controlling the first.
+                        if !spectators.contains(&packet.addr())
+                            && !players.contains_key(&packet.addr())
+                        {
+                            let new_player = NewPlayer { addr: packet.addr(), connect_packet };
+                            connections.new_player_tx.send(new_player);
+                        }
+
+                        continue;
+                    },
+                    ClientToServer::ConnectAsSpectator(connect_packet) => {
+                        if !players.contains_key(&packet.addr()) && spectators.insert(packet.addr())
+                        {
+                            let new_spectator =
+                                NewSpectator { addr: packet.addr(), connect_packet };
+                            connections.new_spectator_tx.send(new_spectator);
+                        }
+
+                        continue;
+                    },
+                    _ => {},
+                }
+
+                // Everything else is only accepted from players who have already connected.
+                let id = match players.get(&packet.addr()) {
+                    Some(id) => *id,
+                    None => continue,
+                };
+
+                match decoded {
+                    ClientToServer::Connect(_) | ClientToServer::ConnectAsSpectator(_) => {
+                        unreachable!()
+                    },
+                    ClientToServer::PlayerInput(input) => {
+                        input_tx.send(PlayerInput { id, input });
+                    },
+                    ClientToServer::CompleteTask(task) => {
+                        complete_task_tx.send(CompleteTask { id, task });
+                    },
+                    ClientToServer::Sabotage(kind) => {
+                        start_sabotage_tx.send(StartSabotage { id, kind });
+                    },
+                    ClientToServer::ToggleLightSwitch(switch) => {
+                        let fix = SabotageFix::ToggleLightSwitch(switch);
+                        fix_sabotage_tx.send(FixSabotage { id, fix });
+                    },
+                    ClientToServer::FixComms => {
+                        fix_sabotage_tx.send(FixSabotage { id, fix: SabotageFix::Comms });
+                    },
+                    ClientToServer::HoldReactor(hold) => {
+                        let fix = SabotageFix::HoldReactor(hold);
+                        fix_sabotage_tx.send(FixSabotage { id, fix });
+                    },
+                    ClientToServer::EnterOxygenCode(code) => {
+                        let fix = SabotageFix::EnterOxygenCode(code);
+                        fix_sabotage_tx.send(FixSabotage { id, fix });
+                    },
+                    ClientToServer::EnterVent(vent) => {
+                        use_vent_tx.send(UseVent { id, action: VentAction::Enter(vent) });
+                    },
+                    ClientToServer::MoveVent(vent) => {
+                        use_vent_tx.send(UseVent { id, action: VentAction::Move(vent) });
+                    },
+                    ClientToServer::ExitVent => {
+                        use_vent_tx.send(UseVent { id, action: VentAction::Exit });
+                    },
+                    ClientToServer::CloseDoors(group) => {
+                        close_doors_tx.send(CloseDoors { id, group });
+                    },
+                    ClientToServer::Kill(target) => {
+                        kill_tx.send(Kill { id, target });
+                    },
+                    ClientToServer::Chat(message) => {
+                        chat_tx.send(Chat { id, message });
+                    },
+                    ClientToServer::UseConsole(kind) => {
+                        use_console_tx.send(UseConsole { id, kind: Some(kind) });
+                    },
+                    ClientToServer::LeaveConsole => {
+                        use_console_tx.send(UseConsole { id, kind: None });
+                    },
+                    ClientToServer::UpdateSettings(settings) => {
+                        update_settings_tx.send(UpdateSettings { id, settings });
+                    },
+                    ClientToServer::SetReady(ready) => {
+                        let action = LobbyAction::SetReady(ready);
+                        lobby_request_tx.send(LobbyRequest { id, action });
+                    },
+                    ClientToServer::StartGame => {
+                        lobby_request_tx.send(LobbyRequest { id, action: LobbyAction::Start });
+                    },
+                    ClientToServer::CancelStart => {
+                        let action = LobbyAction::CancelStart;
+                        lobby_request_tx.send(LobbyRequest { id, action });
+                    },
+                    ClientToServer::AddBot => {
+                        lobby_request_tx.send(LobbyRequest { id, action: LobbyAction::AddBot });
+                    },
+                    ClientToServer::RemoveBot => {
+                        let action = LobbyAction::RemoveBot;
+                        lobby_request_tx.send(LobbyRequest { id, action });
+                    },
+                    ClientToServer::Pong(ping) => {
+                        pong_tx.send(Pong { id, nonce: ping.nonce });
+                    },
                 }
             },
             SocketEvent::Timeout(addr) => {
-                if let Some(player_id) = players.get(&addr) {
-                    println!("{} ({}) timed out", player_id, addr);
+                violations.by_addr.remove(&addr);
+
+                if spectators.remove(&addr) {
+                    info!(%addr, "Spectator timed out");
+                } else if let Some(id) = players.remove(&addr) {
+                    info!(player = id, %addr, "Player timed out");
+                    connections.player_left_tx.send(PlayerLeft { id });
                 } else {
-                    println!("Unknown player timed out: {}", addr);
+                    debug!(%addr, "Unknown client timed out");
                 }
             },
             SocketEvent::Connect(addr) => {
-                println!("Client connected: {}", addr);
+                debug!(%addr, "Client connected");
             },
             SocketEvent::Disconnect(addr) => {
-                if let Some(player_id) = players.remove(&addr) {
-                    println!("Player {} disconnected ({})", player_id, addr);
+                violations.by_addr.remove(&addr);
+
+                if spectators.remove(&addr) {
+                    info!(%addr, "Spectator disconnected");
+                } else if let Some(id) = players.remove(&addr) {
+                    info!(player = id, %addr, "Player disconnected");
+                    connections.player_left_tx.send(PlayerLeft { id });
                 } else {
-                    println!("Unknown player disconnected: {}", addr);
+                    debug!(%addr, "Unknown client disconnected");
                 }
             },
         }
     }
 }
 
+// Despawns whoever left this update and tells the rest of the room, so nothing
+// sent afterwards still lists them.
+fn remove_players(
+    mut commands: Commands,
+    mut player_left_rx: EventReader<PlayerLeft>,
+    mut player_to_entity: ResMut<PlayerToEntity>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for PlayerLeft { id } in player_left_rx.iter() {
+        let entity = match player_to_entity.0.remove(id) {
+            Some(entity) => entity,
+            None => continue,
+        };
+
+        commands.entity(entity).despawn();
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToAll,
+            ServerToClient::PlayerLeft(PlayerLeftPacket { id: *id }),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
+
 #[allow(unused)]
 pub enum PacketDestination {
     Single(SocketAddr),
@@ -136,7 +315,9 @@ pub enum PacketDestination {
     BroadcastToSet(Vec<SocketAddr>),
 }
 
-fn network_send(
+// Everything else that sends packets in `SendPackets` has to run before this,
+// or its packets go out an update late depending on how the schedule falls.
+pub fn network_send(
     net_tx: Res<NetTx>,
     mut outgoing_packets: ResMut<Events<OutgoingPacket>>,
     player_addrs: Query<&PlayerNetworkAddr>,
@@ -144,14 +325,14 @@ fn network_send(
     let net_tx = &net_tx.0;
 
     for outgoing in outgoing_packets.drain() {
-        let data = bincode::serialize(&outgoing.packet).unwrap();
+        let data = encode_server_message(&outgoing.packet);
 
         match &outgoing.destination {
             PacketDestination::Single(addr) => {
                 let packet = make_packet(outgoing.delivery_type, data, *addr, outgoing.stream_id);
 
                 if let Err(e) = net_tx.send(packet) {
-                    println!("Failed to send packet: {:?}", e);
+                    error!(%addr, error = ?e, "Failed to send packet");
                 }
             },
             PacketDestination::BroadcastToAll => {
@@ -166,7 +347,7 @@ fn network_send(
                     );
 
                     if let Err(e) = net_tx.send(packet) {
-                        println!("Failed to send packet: {:?}", e);
+                        error!(%addr, error = ?e, "Failed to send packet");
                     }
                 });
             },
@@ -183,7 +364,7 @@ fn network_send(
                         );
 
                         if let Err(e) = net_tx.send(packet) {
-                            println!("Failed to send packet: {:?}", e);
+                            error!(%addr, error = ?e, "Failed to send packet");
                         }
                     });
             },
@@ -197,7 +378,7 @@ fn network_send(
                     );
 
                     if let Err(e) = net_tx.send(packet) {
-                        println!("Failed to send packet: {:?}", e);
+                        error!(%addr, error = ?e, "Failed to send packet");
                     }
                 });
             },
@@ -0,0 +1,93 @@
+use crate::{
+    components::{Ping, PlayerCapabilities},
+    events::{OutgoingPacket, Pong},
+    systems::{network_send, sets, PacketDestination},
+};
+use std::time::Duration;
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    network::{DeliveryType, PingPacket, ServerToClient},
+    protocol::Capabilities,
+    resources::PlayerToEntity,
+    simple_game::bevy::{
+        bevy_ecs, App, EventReader, EventWriter, IntoSystemConfig, Plugin, Query, Res, ResMut,
+        Resource, Time,
+    },
+};
+
+const PING_INTERVAL: Duration = Duration::from_secs(1);
+
+// Measures the round trip to each player's client, for the admin console.
+pub struct PingPlugin;
+
+impl Plugin for PingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<PingRound>()
+            .add_system(receive_pongs.after(sets::NetworkSystem::Receive))
+            .add_system(send_pings.in_set(sets::NetworkSystem::SendPackets).before(network_send));
+    }
+}
+
+// Everyone gets the same nonce each round, and answers to older rounds are
+// ignored.
+#[derive(Debug, Default, Resource)]
+struct PingRound {
+    nonce: u16,
+    sent_at: Option<Duration>,
+}
+
+fn send_pings(
+    time: Res<Time>,
+    mut round: ResMut<PingRound>,
+    players: Query<(&PlayerNetworkAddr, &PlayerCapabilities)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let now = time.elapsed();
+
+    if round.sent_at.is_some_and(|sent_at| now.saturating_sub(sent_at) < PING_INTERVAL) {
+        return;
+    }
+
+    round.nonce = round.nonce.wrapping_add(1);
+    round.sent_at = Some(now);
+
+    let recipients: Vec<_> = players
+        .iter()
+        .filter(|(_, capabilities)| capabilities.0.contains(Capabilities::PING))
+        .map(|(network_addr, _)| network_addr.0)
+        .collect();
+
+    if recipients.is_empty() {
+        return;
+    }
+
+    // A lost ping just means that player's number is a second older.
+    outgoing_packets.send(OutgoingPacket::new(
+        PacketDestination::BroadcastToSet(recipients),
+        ServerToClient::Ping(PingPacket { nonce: round.nonce }),
+        DeliveryType::Unreliable,
+        None,
+    ));
+}
+
+fn receive_pongs(
+    time: Res<Time>,
+    round: Res<PingRound>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut pong_rx: EventReader<Pong>,
+    mut pings: Query<&mut Ping>,
+) {
+    for pong in pong_rx.iter() {
+        let sent_at = match round.sent_at {
+            Some(sent_at) if pong.nonce == round.nonce => sent_at,
+            _ => continue,
+        };
+
+        let player =
+            player_to_entity.0.get(&pong.id).and_then(|entity| pings.get_mut(*entity).ok());
+
+        if let Some(mut ping) = player {
+            ping.0 = Some(time.elapsed().saturating_sub(sent_at));
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::{
+    events::{FixSabotage, GameOver, OutgoingPacket, SabotageFix, StartSabotage},
+    resources::{GameRng, LightsOn},
+    systems::PacketDestination,
+};
+use rand::Rng;
+use std::time::Duration;
+use sus_common::{
+    map::{within_interact_distance, Map},
+    network::{
+        DeliveryType, SabotageKind, SabotageStatus, SabotageStatusPacket, ServerToClient,
+        GAME_STATE_STREAM, LIGHT_SWITCH_COUNT,
+    },
+    resources::PlayerToEntity,
+    simple_game::{
+        bevy::{
+            bevy_ecs, bevy_ecs::prelude::in_state, App, CoreSchedule, EventReader, EventWriter,
+            FixedTime, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfig,
+            IntoSystemConfigs, OnEnter, Plugin, Query, Res, ResMut, Resource, Transform,
+        },
+        glam::vec2,
+    },
+    tracing::info,
+    GameState, PlayerState, PlayerType,
+};
+
+const SABOTAGE_COOLDOWN: Duration = Duration::from_secs(30);
+const CRITICAL_SABOTAGE_TIME: Duration = Duration::from_secs(30);
+
+pub struct SabotagePlugin;
+
+impl Plugin for SabotagePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SabotageManager::default())
+            .add_system(reset_sabotage.in_schedule(OnEnter(GameState::Main)))
+            .add_systems(
+                (start_sabotage, fix_sabotage.after(start_sabotage), update_sabotage)
+                    .distributive_run_if(in_state(GameState::Main))
+                    .in_schedule(CoreSchedule::FixedUpdate),
+            );
+    }
+}
+
+#[derive(Debug)]
+enum ActiveSabotage {
+    Lights { switches: [bool; LIGHT_SWITCH_COUNT] },
+    Comms,
+    // Holds the ID of whoever is holding each reactor hand scanner.
+    Reactor { time_left: Duration, holding: [Option<u16>; 2] },
+    Oxygen { time_left: Duration, code: u16, fixed: [bool; 2] },
+}
+
+impl ActiveSabotage {
+    fn is_fixed(&self) -> bool {
+        match self {
+            ActiveSabotage::Lights { switches } => switches.iter().all(|on| *on),
+            ActiveSabotage::Comms => false,
+            ActiveSabotage::Reactor { holding, .. } => holding.iter().all(Option::is_some),
+            ActiveSabotage::Oxygen { fixed, .. } => fixed.iter().all(|fixed| *fixed),
+        }
+    }
+
+    fn status(&self) -> SabotageStatus {
+        match self {
+            ActiveSabotage::Lights { switches } => SabotageStatus::Lights { switches: *switches },
+            ActiveSabotage::Comms => SabotageStatus::Comms,
+            ActiveSabotage::Reactor { time_left, holding } => SabotageStatus::Reactor {
+                time_left_secs: time_left.as_secs_f32(),
+                stations_held: [holding[0].is_some(), holding[1].is_some()],
+            },
+            ActiveSabotage::Oxygen { time_left, code, fixed } => SabotageStatus::Oxygen {
+                time_left_secs: time_left.as_secs_f32(),
+                code: *code,
+                stations_fixed: *fixed,
+            },
+        }
+    }
+}
+
+// Only one sabotage can be active at a time, and impostors have to wait out
+// the cooldown after one is fixed before starting another.
+#[derive(Debug, Default, Resource)]
+pub struct SabotageManager {
+    cooldown: Duration,
+    active: Option<ActiveSabotage>,
+}
+
+impl SabotageManager {
+    pub fn comms_down(&self) -> bool {
+        matches!(self.active, Some(ActiveSabotage::Comms))
+    }
+
+    fn status_packet(&self) -> ServerToClient {
+        ServerToClient::SabotageStatus(SabotageStatusPacket {
+            sabotage: self.active.as_ref().map(ActiveSabotage::status),
+        })
+    }
+}
+
+fn broadcast_status(
+    sabotage: &SabotageManager,
+    outgoing_packets: &mut EventWriter<OutgoingPacket>,
+) {
+    outgoing_packets.send(OutgoingPacket::new(
+        PacketDestination::BroadcastToAll,
+        sabotage.status_packet(),
+        DeliveryType::ReliableOrdered,
+        Some(GAME_STATE_STREAM),
+    ));
+}
+
+fn reset_sabotage(mut sabotage: ResMut<SabotageManager>, mut lights_on: ResMut<LightsOn>) {
+    sabotage.cooldown = SABOTAGE_COOLDOWN;
+    sabotage.active = None;
+    lights_on.0 = true;
+}
+
+fn start_sabotage(
+    mut rng: ResMut<GameRng>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut start_sabotage_rx: EventReader<StartSabotage>,
+    players: Query<&PlayerType>,
+    mut sabotage: ResMut<SabotageManager>,
+    mut lights_on: ResMut<LightsOn>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in start_sabotage_rx.iter() {
+        let is_impostor = player_to_entity
+            .0
+            .get(&event.id)
+            .and_then(|entity| players.get(*entity).ok())
+            .is_some_and(|player_type| *player_type == PlayerType::Impostor);
+
+        if !is_impostor || sabotage.active.is_some() || !sabotage.cooldown.is_zero() {
+            continue;
+        }
+
+        let active = match event.kind {
+            SabotageKind::Lights => {
+                let mut switches = [true; LIGHT_SWITCH_COUNT];
+                rng.0.fill(&mut switches[..]);
+
+                // Make sure there's at least one switch to flip back.
+                switches[rng.0.gen_range(0..LIGHT_SWITCH_COUNT)] = false;
+
+                ActiveSabotage::Lights { switches }
+            },
+            SabotageKind::Comms => ActiveSabotage::Comms,
+            SabotageKind::Reactor => {
+                ActiveSabotage::Reactor { time_left: CRITICAL_SABOTAGE_TIME, holding: [None; 2] }
+            },
+            SabotageKind::Oxygen => ActiveSabotage::Oxygen {
+                time_left: CRITICAL_SABOTAGE_TIME,
+                code: rng.0.gen_range(0..10000),
+                fixed: [false; 2],
+            },
+        };
+
+        info!(player = event.id, kind = ?event.kind, "Sabotage started");
+
+        sabotage.active = Some(active);
+        lights_on.0 = event.kind != SabotageKind::Lights;
+        broadcast_status(&sabotage, &mut outgoing_packets);
+    }
+}
+
+fn fix_sabotage(
+    map: Res<Map>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut fix_sabotage_rx: EventReader<FixSabotage>,
+    players: Query<(&Transform, &PlayerState)>,
+    mut sabotage: ResMut<SabotageManager>,
+    mut lights_on: ResMut<LightsOn>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in fix_sabotage_rx.iter() {
+        let pos = match player_to_entity.0.get(&event.id).and_then(|e| players.get(*e).ok()) {
+            Some((transform, PlayerState::Alive)) => {
+                vec2(transform.translation.x, transform.translation.y)
+            },
+            _ => continue,
+        };
+
+        let active = match sabotage.active.as_mut() {
+            Some(active) => active,
+            None => continue,
+        };
+
+        let mut fixed = false;
+
+        match (active, &event.fix) {
+            (ActiveSabotage::Lights { switches }, SabotageFix::ToggleLightSwitch(switch))
+                if within_interact_distance(pos, map.lights_panel) =>
+            {
+                if let Some(on) = switches.get_mut(*switch as usize) {
+                    *on = !*on;
+                }
+            },
+            (ActiveSabotage::Comms, SabotageFix::Comms)
+                if within_interact_distance(pos, map.comms_panel) =>
+            {
+                fixed = true;
+            },
+            (ActiveSabotage::Reactor { holding, .. }, SabotageFix::HoldReactor(hold)) => {
+                let station = hold.station as usize;
+
+                if let Some(station_pos) = map.reactor_stations.get(station) {
+                    if hold.holding && within_interact_distance(pos, *station_pos) {
+                        holding[station] = Some(event.id);
+                    } else if !hold.holding && holding[station] == Some(event.id) {
+                        holding[station] = None;
+                    }
+                }
+            },
+            (
+                ActiveSabotage::Oxygen { code, fixed: stations_fixed, .. },
+                SabotageFix::EnterOxygenCode(entry),
+            ) => {
+                let station = entry.station as usize;
+
+                if let Some(station_pos) = map.oxygen_stations.get(station) {
+                    if entry.code == *code && within_interact_distance(pos, *station_pos) {
+                        stations_fixed[station] = true;
+                    }
+                }
+            },
+            _ => continue,
+        }
+
+        if fixed || sabotage.active.as_ref().is_some_and(ActiveSabotage::is_fixed) {
+            info!(player = event.id, "Sabotage fixed");
+
+            sabotage.active = None;
+            sabotage.cooldown = SABOTAGE_COOLDOWN;
+            lights_on.0 = true;
+        }
+
+        broadcast_status(&sabotage, &mut outgoing_packets);
+    }
+}
+
+fn update_sabotage(
+    fixed_time: Res<FixedTime>,
+    map: Res<Map>,
+    player_to_entity: Res<PlayerToEntity>,
+    players: Query<(&Transform, &PlayerState)>,
+    mut sabotage: ResMut<SabotageManager>,
+    mut game_over_tx: EventWriter<GameOver>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let dt = fixed_time.period;
+    let sabotage = &mut *sabotage;
+
+    sabotage.cooldown = sabotage.cooldown.saturating_sub(dt);
+
+    let mut status_changed = false;
+
+    let time_left = match &mut sabotage.active {
+        Some(ActiveSabotage::Reactor { time_left, holding }) => {
+            // Players let go of the scanner if they walk away from it or die.
+            for (station, holder) in holding.iter_mut().enumerate() {
+                let still_holding = holder
+                    .and_then(|id| player_to_entity.0.get(&id))
+                    .and_then(|entity| players.get(*entity).ok())
+                    .is_some_and(|(transform, player_state)| {
+                        let pos = vec2(transform.translation.x, transform.translation.y);
+                        *player_state == PlayerState::Alive
+                            && within_interact_distance(pos, map.reactor_stations[station])
+                    });
+
+                if holder.is_some() && !still_holding {
+                    *holder = None;
+                    status_changed = true;
+                }
+            }
+
+            time_left
+        },
+        Some(ActiveSabotage::Oxygen { time_left, .. }) => time_left,
+        _ => return,
+    };
+
+    // Let clients resync their countdown once a second.
+    let secs_before = time_left.as_secs();
+    *time_left = time_left.saturating_sub(dt);
+    status_changed |= time_left.as_secs() != secs_before;
+
+    if time_left.is_zero() {
+        game_over_tx.send(GameOver { winner: PlayerType::Impostor });
+        sabotage.active = None;
+    }
+
+    if status_changed {
+        broadcast_status(sabotage, &mut outgoing_packets);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::{
+    events::{OutgoingPacket, UpdateSettings},
+    resources::{AddrToPlayer, Host},
+    systems::{sets, PacketDestination},
+};
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    network::{DeliveryType, ServerToClient, SettingsPacket, GAME_STATE_STREAM},
+    settings::GameSettings,
+    simple_game::bevy::{
+        bevy_ecs::prelude::in_state, Added, App, CoreSchedule, DetectChanges, EventReader,
+        EventWriter, IntoSystemAppConfigs, IntoSystemConfig, IntoSystemConfigs, Plugin, Query, Res,
+        ResMut,
+    },
+    tracing::{info, warn},
+    GameState,
+};
+
+pub struct SettingsPlugin;
+
+impl Plugin for SettingsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GameSettings>().init_resource::<Host>().add_systems(
+            (
+                update_host,
+                update_settings.run_if(in_state(GameState::Lobby)),
+                send_settings.after(update_host).after(update_settings),
+            )
+                .after(sets::Lobby)
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+// The first player to join is the host. If they leave, the longest connected
+// player left takes over.
+fn update_host(players: Res<AddrToPlayer>, mut host: ResMut<Host>) {
+    let host_connected = host.0.is_some_and(|id| players.0.values().any(|player| *player == id));
+
+    if !host_connected {
+        let new_host = players.0.values().min().copied();
+
+        if new_host != host.0 {
+            info!(host = ?new_host, "Host changed");
+            host.0 = new_host;
+        }
+    }
+}
+
+fn update_settings(
+    host: Res<Host>,
+    mut update_settings_rx: EventReader<UpdateSettings>,
+    mut settings: ResMut<GameSettings>,
+) {
+    for event in update_settings_rx.iter() {
+        if host.0 != Some(event.id) {
+            continue;
+        }
+
+        match event.settings.validate() {
+            Ok(()) => *settings = event.settings.clone(),
+            Err(name) => warn!(player = event.id, setting = name, "Invalid setting"),
+        }
+    }
+}
+
+fn send_settings(
+    settings: Res<GameSettings>,
+    host: Res<Host>,
+    new_players: Query<&PlayerNetworkAddr, Added<PlayerNetworkAddr>>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let packet =
+        || ServerToClient::Settings(SettingsPacket { settings: settings.clone(), host: host.0 });
+
+    if settings.is_changed() || host.is_changed() {
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToAll,
+            packet(),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    } else {
+        for network_addr in new_players.iter() {
+            outgoing_packets.send(OutgoingPacket::new(
+                PacketDestination::Single(network_addr.0),
+                packet(),
+                DeliveryType::ReliableOrdered,
+                Some(GAME_STATE_STREAM),
+            ));
+        }
+    }
+}
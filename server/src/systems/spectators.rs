@@ -0,0 +1,209 @@
+use crate::{
+    components::{PlayerCapabilities, Venting},
+    events::{NewSpectator, OutgoingPacket},
+    resources::{RoomCode, SpectatorDelay, Spectators},
+    systems::{network_send, sets, PacketDestination},
+};
+use std::{
+    collections::{BTreeSet, VecDeque},
+    net::SocketAddr,
+    time::Duration,
+};
+use sus_common::{
+    components::player::{PlayerId, PlayerName, PlayerNetworkAddr},
+    network::{
+        DeliveryType, FullGameStatePacket, NewPlayerPacket, ServerToClient, SpectateAckPacket,
+        SpectatedPlayer, SpectatorCountPacket, SpectatorTickPacket, GAME_STATE_STREAM,
+    },
+    protocol::{Capabilities, SUPPORTED_PROTOCOL},
+    simple_game::bevy::{
+        bevy_ecs, bevy_ecs::prelude::in_state, schedule::State, Added, App, EventReader,
+        EventWriter, IntoSystemConfig, Local, Plugin, Query, Res, ResMut, Resource, Time,
+        Transform,
+    },
+    tracing::info,
+    GameState, PlayerState, PlayerType,
+};
+
+// Spectators see the whole room every tick, with nothing hidden, but running
+// `SpectatorDelay` behind the game.
+pub struct SpectatorsPlugin;
+
+impl Plugin for SpectatorsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<SpectatorDelay>()
+            .init_resource::<SpectatorFeed>()
+            .add_system(spectator_joined.after(sets::NetworkSystem::Receive))
+            .add_system(
+                send_spectator_ticks
+                    .in_set(sets::NetworkSystem::SendPackets)
+                    .after(spectator_joined)
+                    .before(network_send),
+            )
+            .add_system(
+                send_spectator_count
+                    .in_set(sets::NetworkSystem::SendPackets)
+                    .before(network_send)
+                    .run_if(in_state(GameState::Lobby)),
+            );
+    }
+}
+
+// Ticks waiting to go out to spectators, with the time they were taken.
+#[derive(Debug, Default, Resource)]
+struct SpectatorFeed(VecDeque<(Duration, SpectatorTickPacket)>);
+
+fn spectator_joined(
+    room_code: Res<RoomCode>,
+    delay: Res<SpectatorDelay>,
+    mut new_spectator_rx: EventReader<NewSpectator>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for new_spectator in new_spectator_rx.iter() {
+        // Incompatible clients are already turned away before reaching a room.
+        let protocol_version =
+            match SUPPORTED_PROTOCOL.negotiate(&new_spectator.connect_packet.protocol) {
+                Some(version) => version,
+                None => continue,
+            };
+        let capabilities =
+            Capabilities::SUPPORTED.intersection(new_spectator.connect_packet.capabilities);
+
+        info!(
+            name = %new_spectator.connect_packet.name,
+            addr = %new_spectator.addr,
+            "Spectator joined"
+        );
+
+        let reply = ServerToClient::SpectateAck(SpectateAckPacket {
+            room_code: room_code.0.clone(),
+            protocol_version,
+            capabilities,
+            delay_secs: delay.0.as_secs() as u16,
+        });
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::Single(new_spectator.addr),
+            reply,
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn send_spectator_ticks(
+    time: Res<Time>,
+    game_state: Res<State<GameState>>,
+    delay: Res<SpectatorDelay>,
+    spectators: Res<Spectators>,
+    mut feed: ResMut<SpectatorFeed>,
+    mut last_roster: Local<Vec<(u16, String)>>,
+    mut last_spectators: Local<BTreeSet<SocketAddr>>,
+    players: Query<(&PlayerId, &PlayerName, &Transform, &PlayerType, &PlayerState, &Venting)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    if spectators.0.is_empty() {
+        feed.0.clear();
+        last_spectators.clear();
+        return;
+    }
+
+    // Who's who isn't secret, so names go out straight away, to everyone when
+    // someone joins or leaves and otherwise just to new spectators.
+    let mut roster: Vec<_> = players.iter().map(|(id, name, ..)| (id.0, name.0.clone())).collect();
+    roster.sort_unstable();
+
+    let roster_addrs: Vec<_> = if *last_roster != roster {
+        spectators.0.iter().copied().collect()
+    } else {
+        spectators.0.difference(&last_spectators).copied().collect()
+    };
+
+    if !roster_addrs.is_empty() {
+        let players_vec =
+            roster.iter().map(|(id, name)| NewPlayerPacket::new(name.clone(), *id)).collect();
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToSet(roster_addrs),
+            ServerToClient::FullGameState(FullGameStatePacket::new(players_vec)),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+
+    *last_roster = roster;
+    *last_spectators = spectators.0.clone();
+
+    let mut spectated: Vec<_> = players
+        .iter()
+        .map(|(id, _, transform, player_type, player_state, venting)| SpectatedPlayer {
+            id: id.0,
+            pos: (transform.translation.x, transform.translation.y),
+            player_type: *player_type,
+            player_state: *player_state,
+            in_vent: venting.vent.is_some(),
+        })
+        .collect();
+    spectated.sort_by_key(|player| player.id);
+
+    let now = time.elapsed();
+    let tick = SpectatorTickPacket { game_state: game_state.0.clone(), players: spectated };
+    feed.0.push_back((now, tick));
+
+    // Only the newest tick that's old enough is worth sending.
+    let mut due = None;
+
+    while let Some((taken_at, _)) = feed.0.front() {
+        if now.saturating_sub(*taken_at) < delay.0 {
+            break;
+        }
+
+        due = feed.0.pop_front();
+    }
+
+    if let Some((_, tick)) = due {
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::BroadcastToSet(spectators.0.iter().copied().collect()),
+            ServerToClient::SpectatorTick(tick),
+            DeliveryType::UnreliableSequenced,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
+
+// Players only ever see how many people are watching, never who.
+fn send_spectator_count(
+    spectators: Res<Spectators>,
+    mut last_count: Local<Option<usize>>,
+    players: Query<(&PlayerNetworkAddr, &PlayerCapabilities)>,
+    new_players: Query<(&PlayerNetworkAddr, &PlayerCapabilities), Added<PlayerNetworkAddr>>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let count = spectators.0.len();
+
+    let candidates: Vec<_> = if *last_count != Some(count) {
+        players.iter().collect()
+    } else {
+        new_players.iter().collect()
+    };
+
+    let recipients: Vec<_> = candidates
+        .into_iter()
+        .filter(|(_, capabilities)| capabilities.0.contains(Capabilities::SPECTATORS))
+        .map(|(network_addr, _)| network_addr.0)
+        .collect();
+
+    *last_count = Some(count);
+
+    if recipients.is_empty() {
+        return;
+    }
+
+    outgoing_packets.send(OutgoingPacket::new(
+        PacketDestination::BroadcastToSet(recipients),
+        ServerToClient::SpectatorCount(SpectatorCountPacket { count: count as u8 }),
+        DeliveryType::ReliableOrdered,
+        Some(GAME_STATE_STREAM),
+    ));
+}
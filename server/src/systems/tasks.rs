@@ -0,0 +1,130 @@
+use crate::{
+    components::Tasks,
+    events::{CompleteTask, GameOver, OutgoingPacket},
+    resources::GameRng,
+    systems::{assign_roles, sabotage::SabotageManager, PacketDestination},
+};
+use rand::seq::index::sample;
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    map::{within_interact_distance, Map},
+    network::{DeliveryType, ServerToClient, TaskListPacket, TaskProgress, GAME_STATE_STREAM},
+    resources::PlayerToEntity,
+    settings::GameSettings,
+    simple_game::{
+        bevy::{
+            bevy_ecs::prelude::in_state, App, CoreSchedule, DetectChanges, EventReader,
+            EventWriter, IntoSystemAppConfig, IntoSystemAppConfigs, IntoSystemConfig,
+            IntoSystemConfigs, Local, OnEnter, Plugin, Query, Ref, Res, ResMut, Transform,
+        },
+        glam::vec2,
+    },
+    GameState, PlayerType,
+};
+
+pub struct TasksPlugin;
+
+impl Plugin for TasksPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(
+            assign_tasks.after(assign_roles).in_schedule(OnEnter(GameState::IntroScreen)),
+        )
+        .add_systems(
+            (complete_task, send_task_lists.after(complete_task))
+                .distributive_run_if(in_state(GameState::Main))
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+fn assign_tasks(
+    map: Res<Map>,
+    settings: Res<GameSettings>,
+    mut rng: ResMut<GameRng>,
+    mut players: Query<(&PlayerType, &mut Tasks)>,
+) {
+    let task_count = (settings.tasks_per_player as usize).min(map.tasks.len());
+
+    for (player_type, mut tasks) in players.iter_mut() {
+        tasks.0 = match player_type {
+            PlayerType::Crew => sample(&mut rng.0, map.tasks.len(), task_count)
+                .into_iter()
+                .map(|task| TaskProgress { task: task as u8, done: false })
+                .collect(),
+            PlayerType::Impostor => vec![],
+        };
+    }
+}
+
+fn complete_task(
+    map: Res<Map>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut complete_task_rx: EventReader<CompleteTask>,
+    mut players: Query<(&Transform, &mut Tasks)>,
+    mut game_over_tx: EventWriter<GameOver>,
+) {
+    let mut any_completed = false;
+
+    for event in complete_task_rx.iter() {
+        let player_entity = match player_to_entity.0.get(&event.id) {
+            Some(player_entity) => *player_entity,
+            None => continue,
+        };
+
+        let station = match map.tasks.get(event.task as usize) {
+            Some(station) => station,
+            None => continue,
+        };
+
+        if let Ok((transform, mut tasks)) = players.get_mut(player_entity) {
+            let pos = vec2(transform.translation.x, transform.translation.y);
+
+            if !within_interact_distance(pos, station.pos) {
+                continue;
+            }
+
+            if let Some(progress) =
+                tasks.0.iter_mut().find(|progress| progress.task == event.task && !progress.done)
+            {
+                progress.done = true;
+                any_completed = true;
+            }
+        }
+    }
+
+    if any_completed {
+        let mut all_tasks = players.iter().flat_map(|(_, tasks)| tasks.0.iter()).peekable();
+
+        if all_tasks.peek().is_some() && all_tasks.all(|progress| progress.done) {
+            game_over_tx.send(GameOver { winner: PlayerType::Crew });
+        }
+    }
+}
+
+// Sends a player their task list whenever it changes. Everyone's list is
+// hidden while comms are sabotaged, and sent again once they're fixed.
+fn send_task_lists(
+    sabotage: Res<SabotageManager>,
+    mut comms_were_down: Local<bool>,
+    players: Query<(&PlayerNetworkAddr, Ref<Tasks>)>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    let comms_down = sabotage.comms_down();
+    let comms_changed = comms_down != *comms_were_down;
+    *comms_were_down = comms_down;
+
+    for (network_addr, tasks) in players.iter() {
+        if !comms_changed && !tasks.is_changed() {
+            continue;
+        }
+
+        let tasks = if comms_down { None } else { Some(tasks.0.clone()) };
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::Single(network_addr.0),
+            ServerToClient::TaskList(TaskListPacket { tasks }),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::{
+    components::Venting,
+    events::{OutgoingPacket, UseVent, VentAction},
+    systems::PacketDestination,
+};
+use std::time::Duration;
+use sus_common::{
+    components::player::PlayerNetworkAddr,
+    map::{within_interact_distance, Map},
+    network::{DeliveryType, ServerToClient, VentStatusPacket, GAME_STATE_STREAM},
+    resources::PlayerToEntity,
+    simple_game::{
+        bevy::{
+            bevy_ecs::prelude::in_state, App, CoreSchedule, EventReader, EventWriter, FixedTime,
+            IntoSystemAppConfigs, IntoSystemConfigs, Plugin, Query, Res, Transform,
+        },
+        glam::{vec2, vec3},
+    },
+    GameState, PlayerState, PlayerType,
+};
+
+const VENT_COOLDOWN: Duration = Duration::from_secs(5);
+
+pub struct VentsPlugin;
+
+impl Plugin for VentsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(
+            (use_vent, update_vent_cooldowns)
+                .distributive_run_if(in_state(GameState::Main))
+                .in_schedule(CoreSchedule::FixedUpdate),
+        );
+    }
+}
+
+fn use_vent(
+    map: Res<Map>,
+    player_to_entity: Res<PlayerToEntity>,
+    mut use_vent_rx: EventReader<UseVent>,
+    mut players: Query<(
+        &PlayerNetworkAddr,
+        &PlayerType,
+        &PlayerState,
+        &mut Transform,
+        &mut Venting,
+    )>,
+    mut outgoing_packets: EventWriter<OutgoingPacket>,
+) {
+    for event in use_vent_rx.iter() {
+        let player_entity = match player_to_entity.0.get(&event.id) {
+            Some(player_entity) => *player_entity,
+            None => continue,
+        };
+
+        let (network_addr, player_type, player_state, mut transform, mut venting) =
+            match players.get_mut(player_entity) {
+                Ok(player) => player,
+                Err(_) => continue,
+            };
+
+        if *player_type != PlayerType::Impostor || *player_state != PlayerState::Alive {
+            continue;
+        }
+
+        let pos = vec2(transform.translation.x, transform.translation.y);
+
+        match (venting.vent, event.action) {
+            (None, VentAction::Enter(vent)) => {
+                let in_range = map
+                    .vents
+                    .get(vent as usize)
+                    .is_some_and(|vent| within_interact_distance(pos, vent.pos));
+
+                if !in_range || !venting.cooldown.is_zero() {
+                    continue;
+                }
+
+                venting.vent = Some(vent);
+            },
+            (Some(current), VentAction::Move(target)) => {
+                if !map.vents[current as usize].links.contains(&target) {
+                    continue;
+                }
+
+                venting.vent = Some(target);
+            },
+            (Some(_), VentAction::Exit) => {
+                venting.vent = None;
+                venting.cooldown = VENT_COOLDOWN;
+            },
+            _ => continue,
+        }
+
+        // Snap the player onto the vent so they climb out where they went in.
+        if let Some(vent) = venting.vent {
+            let vent_pos = map.vents[vent as usize].pos;
+            transform.translation = vec3(vent_pos.x, vent_pos.y, 0.0);
+        }
+
+        outgoing_packets.send(OutgoingPacket::new(
+            PacketDestination::Single(network_addr.0),
+            ServerToClient::VentStatus(VentStatusPacket { vent: venting.vent }),
+            DeliveryType::ReliableOrdered,
+            Some(GAME_STATE_STREAM),
+        ));
+    }
+}
+
+fn update_vent_cooldowns(fixed_time: Res<FixedTime>, mut players: Query<&mut Venting>) {
+    for mut venting in players.iter_mut() {
+        if !venting.cooldown.is_zero() {
+            venting.cooldown = venting.cooldown.saturating_sub(fixed_time.period);
+        }
+    }
+}